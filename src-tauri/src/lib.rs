@@ -1,89 +1,109 @@
-use tauri::{AppHandle, Manager, State};
+use std::path::Path;
+
+use tauri::{AppHandle, Manager, Runtime, State};
 use tokio::sync::Mutex;
-use tracing::{error, info, instrument};
-use tracing_subscriber::EnvFilter;
+use tracing::{error, info};
 
 use crate::messangers::{
     discord::{
+        account::{add_account, get_account_summary, list_accounts},
         api::{
-            check_discord_token, fetch_channel_messages, fetch_guild_channels, fetch_user_channels,
+            fetch_channel_messages, fetch_guild_channels, fetch_user_channels,
             fetch_user_guilds, send_message,
         },
+        auth::{AuthSettings, AuthWatch, get_auth_settings, run_auth_watch, set_auth_settings},
+        cache::DiscordCache,
+        channel_tree::fetch_guild_channel_tree,
+        composer::fetch_autocomplete,
+        endpoints::{Endpoints, get_endpoints},
         gateway::GatewayClient,
+        history::{MessageHistory, jump_to_date, jump_to_message},
+        links::{LinkPreviews, open_message_link},
+        login::{MfaTicket, login, submit_mfa_code},
+        markdown::parse_message_markdown,
+        members::fetch_guild_members,
+        mentions::Lookups,
+        notifications::{
+            NotificationEngine, get_notification_rules, set_channel_mute, set_guild_mute,
+            set_notification_rules,
+        },
+        outbox::{Outbox, discard_message, retry_message, run_outbox},
+        permissions::fetch_channel_capabilities,
+        recorder::{
+            GatewayRecorder, replay_gateway_recording, start_gateway_recording,
+            stop_gateway_recording,
+        },
+        remote_auth::{RemoteAuth, cancel_remote_auth, start_remote_auth},
+        slowmode::fetch_slowmode,
+        unread::{ack_message, fetch_unread_summary, mark_guild_read},
+    },
+    vault::{
+        Vault, change_vault_password, create_vault, lock_vault, run_idle_lock, unlock_vault,
+        vault_activity, vault_status,
     },
-    token_storage,
 };
 
+pub mod logging;
 mod messangers;
 
 pub struct AppState {
+    endpoints: Endpoints,
     token: Mutex<Option<String>>,
     gateway: Mutex<GatewayClient>,
+    cache: Mutex<DiscordCache>,
+    notifications: Mutex<NotificationEngine>,
+    outbox: Mutex<Outbox>,
+    history: Mutex<MessageHistory>,
+    link_previews: Mutex<LinkPreviews>,
+    lookups: Mutex<Lookups>,
+    vault: Mutex<Vault>,
+    auth_settings: Mutex<AuthSettings>,
+    auth: AuthWatch,
+    pending_mfa: Mutex<Option<MfaTicket>>,
+    remote_auth: Mutex<RemoteAuth>,
+    recorder: Mutex<GatewayRecorder>,
 }
 
-#[instrument]
-async fn load_validate_and_persist_token() -> Option<String> {
-    let loaded_token = match token_storage::load_discord_token_from_file().await {
-        Ok(token) => token,
-        Err(e) => {
-            error!("Failed to load token from file: {}", e);
-            None
-        }
-    };
-
-    let Some(token) = loaded_token else {
-        info!("No token found in file");
-        return None;
-    };
-
-    info!("Token found in file, validating...");
-
-    let is_valid = check_discord_token(&token).await.unwrap_or(false);
-    if !is_valid {
-        info!("Stored token is invalid, clearing");
-        if let Err(e) = token_storage::save_discord_token_to_file(None).await {
-            error!("Failed to clear token file: {}", e);
+impl AppState {
+    /// Loads the persisted state from `dir`: the app data directory, or a
+    /// scratch directory in tests.
+    pub async fn load(dir: &Path) -> Self {
+        Self {
+            endpoints: Endpoints::load(dir.join("endpoints.json")).await,
+            token: Mutex::new(None),
+            gateway: Mutex::new(GatewayClient::new()),
+            cache: Mutex::new(DiscordCache::default()),
+            notifications: Mutex::new(
+                NotificationEngine::load(dir.join("notification_rules.json")).await,
+            ),
+            outbox: Mutex::new(Outbox::load(dir.join("outbox.json")).await),
+            history: Mutex::new(MessageHistory::default()),
+            link_previews: Mutex::new(LinkPreviews::default()),
+            lookups: Mutex::new(Lookups::default()),
+            vault: Mutex::new(Vault::load(dir.join("vault.json")).await),
+            auth_settings: Mutex::new(AuthSettings::load(dir.join("auth_settings.json")).await),
+            auth: AuthWatch::default(),
+            pending_mfa: Mutex::new(None),
+            remote_auth: Mutex::new(RemoteAuth::default()),
+            recorder: Mutex::new(GatewayRecorder::new(dir.join("recordings"))),
         }
-        return None;
     }
-
-    // Persist normalized new-format file even if it was in the old array format.
-    if let Err(e) = token_storage::save_discord_token_to_file(Some(&token)).await {
-        error!("Failed to save token to file: {}", e);
-    }
-
-    Some(token)
 }
 
 #[tauri::command]
-async fn set_token(
+async fn set_token<R: Runtime>(
     state: State<'_, AppState>,
-    app_handle: AppHandle,
+    app_handle: AppHandle<R>,
     token: String,
-) -> Result<bool, ()> {
-    if !check_discord_token(&token).await.unwrap_or(false) {
-        return Ok(false);
-    }
-
-    if let Err(e) = token_storage::save_discord_token_to_file(Some(&token)).await {
-        error!("Failed to save token to file: {}", e);
-        return Ok(false);
-    }
-
-    // Store token in state
-    *state.token.lock().await = Some(token.clone());
-
-    // Start Gateway connection
-    if let Err(e) = state.gateway.lock().await.connect(token, app_handle).await {
-        error!("Failed to start Gateway: {}", e);
-    }
-
-    info!("Token saved successfully");
-    Ok(true)
+) -> Result<bool, String> {
+    add_account(&state, app_handle, token).await
 }
 
 #[tauri::command]
-async fn start_gateway(state: State<'_, AppState>, app_handle: AppHandle) -> Result<bool, String> {
+async fn start_gateway<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+) -> Result<bool, String> {
     let token = state
         .token
         .lock()
@@ -112,69 +132,97 @@ async fn is_gateway_connected(state: State<'_, AppState>) -> Result<bool, ()> {
     Ok(state.gateway.lock().await.is_connected().await)
 }
 
-#[tauri::command]
-#[instrument(skip(state))]
-async fn get_token(state: State<'_, AppState>) -> Result<String, ()> {
-    if let Some(token) = state.token.lock().await.clone() {
-        Ok(token)
-    } else {
-        Err(())
-    }
-}
-
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| EnvFilter::new("messagify=debug,info")),
-        )
-        .with_file(true)
-        .with_line_number(true)
-        .without_time()
-        .init();
-
+    logging::init();
     info!("Starting Messagify application");
 
-    tauri::Builder::default()
+    let builder = tauri::Builder::default()
         .setup(|app| {
-            let token = tauri::async_runtime::block_on(load_validate_and_persist_token());
-
-            // Initialize Gateway client
-            let gateway = GatewayClient::new();
-
-            // Auto-start Gateway if token exists
-            if let Some(ref tok) = token {
-                let tok = tok.clone();
-                let app_handle = app.handle().clone();
-                tauri::async_runtime::spawn(async move {
-                    let mut gw = GatewayClient::new();
-                    if let Err(e) = gw.connect(tok, app_handle).await {
-                        error!("Failed to auto-start Gateway: {}", e);
-                    }
-                });
+            // The log directory is only known once the app exists
+            match app.path().app_log_dir() {
+                Ok(dir) => logging::init_file(&dir),
+                Err(e) => error!("Failed to resolve log dir: {}", e),
             }
 
+            // Nothing is signed in until the vault is unlocked
+            let state = match app.path().app_data_dir() {
+                Ok(dir) => tauri::async_runtime::block_on(AppState::load(&dir)),
+                Err(e) => {
+                    error!("Failed to resolve app data dir: {}", e);
+                    return Err(e.into());
+                }
+            };
+
             // Store state
-            app.manage(AppState {
-                token: Mutex::new(token),
-                gateway: Mutex::new(gateway),
-            });
+            app.manage(state);
+            spawn_background_tasks(app.handle());
+
             Ok(())
         })
-        .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![
-            get_token,
-            set_token,
-            start_gateway,
-            stop_gateway,
-            is_gateway_connected,
-            fetch_user_guilds,
-            fetch_guild_channels,
-            fetch_user_channels,
-            fetch_channel_messages,
-            send_message
-        ])
+        .plugin(tauri_plugin_opener::init());
+
+    with_commands(builder)
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+/// Starts the tasks that run for the lifetime of the app. Call after
+/// `AppState` is managed.
+pub fn spawn_background_tasks<R: Runtime>(app_handle: &AppHandle<R>) {
+    tauri::async_runtime::spawn(run_outbox(app_handle.clone()));
+    tauri::async_runtime::spawn(run_idle_lock(app_handle.clone()));
+    tauri::async_runtime::spawn(run_auth_watch(app_handle.clone()));
+}
+
+/// Registers every command. Shared with the integration tests, which build
+/// the app on tauri's mock runtime.
+pub fn with_commands<R: Runtime>(builder: tauri::Builder<R>) -> tauri::Builder<R> {
+    builder.invoke_handler(tauri::generate_handler![
+        get_account_summary,
+        list_accounts,
+        set_token,
+        start_gateway,
+        stop_gateway,
+        is_gateway_connected,
+        fetch_user_guilds,
+        fetch_guild_channels,
+        fetch_user_channels,
+        fetch_channel_messages,
+        send_message,
+        fetch_guild_members,
+        fetch_channel_capabilities,
+        fetch_guild_channel_tree,
+        fetch_unread_summary,
+        ack_message,
+        mark_guild_read,
+        get_notification_rules,
+        set_notification_rules,
+        set_guild_mute,
+        set_channel_mute,
+        parse_message_markdown,
+        fetch_autocomplete,
+        retry_message,
+        discard_message,
+        fetch_slowmode,
+        jump_to_message,
+        jump_to_date,
+        open_message_link,
+        vault_status,
+        create_vault,
+        unlock_vault,
+        lock_vault,
+        change_vault_password,
+        vault_activity,
+        get_auth_settings,
+        set_auth_settings,
+        login,
+        submit_mfa_code,
+        start_remote_auth,
+        cancel_remote_auth,
+        get_endpoints,
+        start_gateway_recording,
+        stop_gateway_recording,
+        replay_gateway_recording
+    ])
+}
@@ -0,0 +1,149 @@
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    path::Path,
+    sync::{LazyLock, OnceLock},
+};
+
+use regex::Regex;
+use tracing::error;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{
+    EnvFilter, Registry,
+    fmt::{
+        self, MakeWriter,
+        format::{DefaultFields, Format},
+    },
+    layer::SubscriberExt,
+    reload,
+    util::SubscriberInitExt,
+};
+
+// A week of daily files
+const LOG_FILES_KEPT: usize = 7;
+
+type FileLayer = fmt::Layer<Registry, DefaultFields, Format, Redacting<RollingFileAppender>>;
+
+// Set by `init`; `init_file` swaps the file layer in through it
+static FILE_LAYER: OnceLock<reload::Handle<Option<FileLayer>, Registry>> = OnceLock::new();
+
+/// What gets scrubbed from log output, in order.
+static SECRETS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    [
+        // Authorization headers: a bare value runs to the end of the line,
+        // as in "Authorization: Bearer ..."
+        (
+            r#"(?i)("?\bauthorization"?\s*[:=]\s*)(?:"[^"]*"|[^\r\n]+)"#,
+            r#"$1"[redacted]""#,
+        ),
+        // Named fields, quoted as in JSON and Debug output or bare as in
+        // tracing fields: "token": "...", password="..."
+        (
+            r#"(?i)("?\b(?:token|password)"?\s*[:=]\s*)(?:"[^"]*"|[^\s,}]+)"#,
+            r#"$1"[redacted]""#,
+        ),
+        // Discord user tokens: base64 user id, timestamp and HMAC
+        (
+            r"\b(?:mfa\.[\w-]{20,}|[\w-]{24,}\.[\w-]{6}\.[\w-]{27,})\b",
+            "[token]",
+        ),
+        (r"\b[\w.+-]+@[\w-]+(?:\.[\w-]+)*\.[A-Za-z]{2,}\b", "[email]"),
+        // E.164 as Discord formats phone numbers; ids and timestamps have no +
+        (r"\+\d[\d ().-]{6,}\d", "[phone]"),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+    .collect()
+});
+
+/// Replaces tokens, email addresses and phone numbers in `text`.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let mut text = Cow::Borrowed(text);
+    for (pattern, replacement) in SECRETS.iter() {
+        let replaced = match pattern.replace_all(&text, *replacement) {
+            Cow::Borrowed(_) => continue,
+            Cow::Owned(replaced) => replaced,
+        };
+        text = Cow::Owned(replaced);
+    }
+    text
+}
+
+/// Scrubs everything written through it with `redact`. The fmt layer
+/// writes each formatted event in one call, so a secret is never split
+/// across writes.
+pub struct RedactingWriter<W>(W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Makes `RedactingWriter`s around another `MakeWriter`, so that fields and
+/// messages of every event are scrubbed before they reach a sink.
+pub struct Redacting<M>(pub M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+/// Logs to stdout, redacted. Call once, before anything else logs; log
+/// files are added by `init_file` once their directory is known.
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("messagify=debug,info"));
+
+    let stdout = fmt::layer()
+        .with_file(true)
+        .with_line_number(true)
+        .without_time()
+        .with_writer(Redacting(io::stdout));
+    let (file, handle) = reload::Layer::new(None);
+
+    tracing_subscriber::registry()
+        .with(file)
+        .with(filter)
+        .with(stdout)
+        .init();
+    let _ = FILE_LAYER.set(handle);
+}
+
+/// Also logs to daily rotated files in `dir`, redacted like stdout.
+pub fn init_file(dir: &Path) {
+    let Some(handle) = FILE_LAYER.get() else {
+        return;
+    };
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("messagify")
+        .filename_suffix("log")
+        .max_log_files(LOG_FILES_KEPT)
+        .build(dir);
+    let appender = match appender {
+        Ok(appender) => appender,
+        Err(e) => {
+            error!("Failed to open log file, logging to stdout only: {}", e);
+            return;
+        }
+    };
+
+    let layer = fmt::layer()
+        .with_ansi(false)
+        .with_file(true)
+        .with_line_number(true)
+        .with_writer(Redacting(appender));
+    if let Err(e) = handle.reload(Some(layer)) {
+        error!("Failed to add log file: {}", e);
+    }
+}
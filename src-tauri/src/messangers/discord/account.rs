@@ -0,0 +1,234 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Runtime, State};
+use tracing::{debug, error, info, warn};
+use zeroize::Zeroize;
+
+use super::{api::check_discord_token, snowflake::Snowflake};
+use crate::AppState;
+
+/// The user behind a saved token, as returned by `/users/@me` and in the
+/// gateway's READY and USER_UPDATE.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountProfile {
+    pub id: Snowflake,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub global_name: Option<String>,
+    #[serde(default)]
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub flags: u64,
+    // 0 none, 1 Nitro Classic, 2 Nitro, 3 Nitro Basic
+    #[serde(default)]
+    pub premium_type: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenValidity {
+    Valid,
+    // Rejected by Discord
+    Invalid,
+    // Discord could not be reached
+    Unknown,
+}
+
+/// A token kept in the vault, with what Discord last said about it.
+#[derive(Serialize, Deserialize)]
+pub struct SavedAccount {
+    pub token: String,
+    #[serde(default)]
+    pub profile: Option<AccountProfile>,
+    pub status: TokenValidity,
+    // RFC 3339 time of the last check
+    #[serde(default)]
+    pub checked_at: Option<String>,
+}
+
+impl Drop for SavedAccount {
+    fn drop(&mut self) {
+        self.token.zeroize();
+    }
+}
+
+impl SavedAccount {
+    /// A token that has not been checked yet.
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            profile: None,
+            status: TokenValidity::Unknown,
+            checked_at: None,
+        }
+    }
+
+    /// Records the outcome of `check_discord_token`. A failed request keeps
+    /// the profile from the last successful one.
+    pub fn record_check(&mut self, result: &Result<Option<AccountProfile>, ()>) {
+        self.status = match result {
+            Ok(Some(profile)) => {
+                self.profile = Some(profile.clone());
+                TokenValidity::Valid
+            }
+            Ok(None) => TokenValidity::Invalid,
+            Err(()) => TokenValidity::Unknown,
+        };
+        self.checked_at = Some(Utc::now().to_rfc3339());
+    }
+
+    fn summary(&self, active: bool) -> AccountSummary {
+        let profile = self.profile.as_ref();
+        AccountSummary {
+            user_id: profile.map(|p| p.id),
+            username: profile.map(|p| p.username.clone()),
+            global_name: profile.and_then(|p| p.global_name.clone()),
+            avatar: profile.and_then(|p| p.avatar.clone()),
+            flags: profile.map(|p| p.flags).unwrap_or_default(),
+            premium_type: profile.map(|p| p.premium_type).unwrap_or_default(),
+            masked_token: mask_token(&self.token),
+            validity: self.status,
+            checked_at: self.checked_at.clone(),
+            active,
+        }
+    }
+}
+
+/// What the UI may know about a saved account. The token itself never
+/// leaves Rust, only a masked form of it.
+#[derive(Debug, Serialize)]
+pub struct AccountSummary {
+    pub user_id: Option<Snowflake>,
+    pub username: Option<String>,
+    pub global_name: Option<String>,
+    pub avatar: Option<String>,
+    pub flags: u64,
+    pub premium_type: u8,
+    pub masked_token: String,
+    pub validity: TokenValidity,
+    pub checked_at: Option<String>,
+    // Signed in right now
+    pub active: bool,
+}
+
+/// Keeps the first and last four characters, enough to tell tokens apart.
+pub fn mask_token(token: &str) -> String {
+    let chars: Vec<char> = token.chars().collect();
+    if chars.len() < 16 {
+        return "•".repeat(8);
+    }
+    let start: String = chars[..4].iter().collect();
+    let end: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", start, end)
+}
+
+/// The id of the signed-in account, as Discord last confirmed it.
+pub async fn current_user_id(state: &AppState) -> Option<Snowflake> {
+    let token = state.token.lock().await.clone()?;
+    let vault = state.vault.lock().await;
+    let account = vault.contents()?.account(&token)?;
+    account.profile.as_ref().map(|p| p.id)
+}
+
+/// Stores the outcome of checking a saved token.
+pub async fn record_check(
+    state: &AppState,
+    token: &str,
+    result: &Result<Option<AccountProfile>, ()>,
+) {
+    let mut vault = state.vault.lock().await;
+    let updated = vault.update(|contents| {
+        if let Some(account) = contents.account_mut(token) {
+            account.record_check(result);
+        }
+    });
+    if let Err(e) = updated.await {
+        warn!("Could not record account check: {}", e);
+    }
+}
+
+/// Refreshes the saved profile from the user object of a READY or
+/// USER_UPDATE. Other users' updates are ignored.
+pub async fn refresh_profile(state: &AppState, user: &Value) {
+    let Ok(profile) = serde_json::from_value::<AccountProfile>(user.clone()) else {
+        return;
+    };
+    let mut vault = state.vault.lock().await;
+    let updated = vault.update(|contents| {
+        if let Some(account) = contents.account_by_id_mut(profile.id) {
+            account.record_check(&Ok(Some(profile)));
+        }
+    });
+    // Expected when the vault locked while the gateway was still running
+    if let Err(e) = updated.await {
+        debug!("Could not refresh account profile: {}", e);
+    }
+}
+
+/// Validates a new token, saves it as the most recently used account and
+/// signs in with it. False when Discord rejects the token.
+pub async fn add_account<R: Runtime>(
+    state: &AppState,
+    app_handle: AppHandle<R>,
+    token: String,
+) -> Result<bool, String> {
+    let result = check_discord_token(&state.endpoints, &token).await;
+    if !matches!(result, Ok(Some(_))) {
+        return Ok(false);
+    }
+
+    let mut account = SavedAccount::new(token.clone());
+    account.record_check(&result);
+    state
+        .vault
+        .lock()
+        .await
+        .update(|contents| contents.upsert(account))
+        .await?;
+
+    // Store token in state
+    *state.token.lock().await = Some(token.clone());
+
+    // Start Gateway connection
+    if let Err(e) = state.gateway.lock().await.connect(token, app_handle).await {
+        error!("Failed to start Gateway: {}", e);
+    }
+
+    info!("Token saved successfully");
+    Ok(true)
+}
+
+/// The signed-in account, checked against Discord again.
+#[tauri::command]
+pub async fn get_account_summary(
+    state: State<'_, AppState>,
+) -> Result<Option<AccountSummary>, String> {
+    let Some(token) = state.token.lock().await.clone() else {
+        return Ok(None);
+    };
+
+    let result = check_discord_token(&state.endpoints, &token).await;
+    record_check(&state, &token, &result).await;
+
+    let vault = state.vault.lock().await;
+    Ok(vault
+        .contents()
+        .and_then(|c| c.accounts.iter().find(|a| a.token == token))
+        .map(|a| a.summary(true)))
+}
+
+/// Every account in the vault with its last known status, most recently
+/// used first.
+#[tauri::command]
+pub async fn list_accounts(state: State<'_, AppState>) -> Result<Vec<AccountSummary>, String> {
+    let token = state.token.lock().await.clone();
+    let vault = state.vault.lock().await;
+    let contents = vault.contents().ok_or_else(|| "vault_locked".to_string())?;
+    Ok(contents
+        .accounts
+        .iter()
+        .map(|a| a.summary(token.as_deref() == Some(a.token.as_str())))
+        .collect())
+}
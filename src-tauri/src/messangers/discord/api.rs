@@ -1,11 +1,30 @@
-use tauri::State;
+use tauri::{AppHandle, Manager, Runtime, State};
 use tracing::error;
 
+use super::{
+    account::AccountProfile,
+    auth::http_error,
+    composer::{MAX_MESSAGE_LENGTH, Overflow, encode_mentions, split_content},
+    endpoints::Endpoints,
+    mentions::attach_resolved,
+    models::{Channel, User},
+    outbox::enqueue,
+    permissions::capabilities_for,
+    snowflake::Snowflake,
+};
 use crate::AppState;
 
-pub async fn check_discord_token(token: &str) -> Result<bool, ()> {
-    let response = reqwest::Client::new()
-        .get("https://discord.com/api/v10/users/@me")
+/// The account behind a token, or None if Discord rejects it with a 401.
+/// Err when Discord could not be reached or answered with anything else,
+/// e.g. a rate limit or an outage, so callers do not treat those as a
+/// revoked token.
+pub async fn check_discord_token(
+    endpoints: &Endpoints,
+    token: &str,
+) -> Result<Option<AccountProfile>, ()> {
+    let response = endpoints
+        .http()
+        .get(endpoints.api("/users/@me"))
         .header("Authorization", token)
         .send()
         .await
@@ -13,19 +32,61 @@ pub async fn check_discord_token(token: &str) -> Result<bool, ()> {
             error!("Request failed: {}", e);
         })?;
 
-    if response.status().is_success() {
-        return Ok(true);
+    let status = response.status();
+    if status.is_success() {
+        response.json().await.map(Some).map_err(|e| {
+            error!("Failed to parse user: {}", e);
+        })
+    } else if status == reqwest::StatusCode::UNAUTHORIZED {
+        error!("Token rejected: {}", status);
+        Ok(None)
     } else {
-        error!("Token validation failed: {}", response.status());
-        return Ok(false);
+        error!("Token validation failed: {}", status);
+        Err(())
     }
 }
 
+pub async fn fetch_user(state: &AppState, token: &str, user_id: Snowflake) -> Result<User, String> {
+    let url = state.endpoints.api(&format!("/users/{}", user_id));
+
+    let response = state
+        .endpoints
+        .http()
+        .get(&url)
+        .header("Authorization", token)
+        .send()
+        .await
+        .map_err(|e| {
+            error!("Request failed: {}", e);
+            "request_failed".to_string()
+        })?;
+
+    if !response.status().is_success() {
+        error!("User fetch failed: {}", response.status());
+        state.auth.report_status(response.status());
+        return Err(http_error(response.status()));
+    }
+
+    response.json().await.map_err(|e| {
+        error!("Failed to parse user response: {}", e);
+        "parse_failed".to_string()
+    })
+}
+
 #[tauri::command]
 pub async fn fetch_user_guilds(state: State<'_, AppState>) -> Result<String, String> {
-    let response = reqwest::Client::new()
-        .get("https://discord.com/api/v10/users/@me/guilds")
-        .header("Authorization", state.token.lock().await.as_ref().unwrap())
+    let token = state
+        .token
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| "not_authenticated".to_string())?;
+
+    let response = state
+        .endpoints
+        .http()
+        .get(state.endpoints.api("/users/@me/guilds"))
+        .header("Authorization", &token)
         .send()
         .await
         .map_err(|e| {
@@ -35,7 +96,8 @@ pub async fn fetch_user_guilds(state: State<'_, AppState>) -> Result<String, Str
 
     if !response.status().is_success() {
         error!("Guild fetch failed: {}", response.status());
-        return Err(format!("http_{}", response.status().as_u16()));
+        state.auth.report_status(response.status());
+        return Err(http_error(response.status()));
     }
 
     response.text().await.map_err(|e| {
@@ -47,9 +109,11 @@ pub async fn fetch_user_guilds(state: State<'_, AppState>) -> Result<String, Str
 #[tauri::command]
 pub async fn fetch_guild_channels(
     state: State<'_, AppState>,
-    guild_id: String,
+    guild_id: Snowflake,
 ) -> Result<String, String> {
-    let url = format!("https://discord.com/api/v10/guilds/{}/channels", guild_id);
+    let url = state
+        .endpoints
+        .api(&format!("/guilds/{}/channels", guild_id));
 
     let token = state
         .token
@@ -58,7 +122,9 @@ pub async fn fetch_guild_channels(
         .clone()
         .ok_or_else(|| "not_authenticated".to_string())?;
 
-    let response = reqwest::Client::new()
+    let response = state
+        .endpoints
+        .http()
         .get(&url)
         .header("Authorization", &token)
         .send()
@@ -70,12 +136,36 @@ pub async fn fetch_guild_channels(
 
     if !response.status().is_success() {
         error!("Guild channels fetch failed: {}", response.status());
-        return Err(format!("http_{}", response.status().as_u16()));
+        state.auth.report_status(response.status());
+        return Err(http_error(response.status()));
     }
 
-    response.text().await.map_err(|e| {
+    let channels: Vec<serde_json::Value> = response.json().await.map_err(|e| {
         error!("Failed to parse guild channels response: {}", e);
         "parse_failed".to_string()
+    })?;
+
+    // Refresh the cache, then hide channels the current user cannot view.
+    let mut cache = state.cache.lock().await;
+    cache.replace_channels(
+        guild_id,
+        channels
+            .iter()
+            .filter_map(|c| serde_json::from_value::<Channel>(c.clone()).ok())
+            .collect(),
+    );
+    let visible: Vec<_> = channels
+        .into_iter()
+        .filter(|c| {
+            Snowflake::from_value(&c["id"])
+                .and_then(|id| capabilities_for(&cache, id))
+                .is_none_or(|caps| caps.view)
+        })
+        .collect();
+
+    serde_json::to_string(&visible).map_err(|e| {
+        error!("Failed to serialize guild channels: {}", e);
+        "parse_failed".to_string()
     })
 }
 
@@ -88,8 +178,10 @@ pub async fn fetch_user_channels(state: State<'_, AppState>) -> Result<String, S
         .clone()
         .ok_or_else(|| "not_authenticated".to_string())?;
 
-    let response = reqwest::Client::new()
-        .get("https://discord.com/api/v10/users/@me/channels")
+    let response = state
+        .endpoints
+        .http()
+        .get(state.endpoints.api("/users/@me/channels"))
         .header("Authorization", &token)
         .send()
         .await
@@ -100,7 +192,8 @@ pub async fn fetch_user_channels(state: State<'_, AppState>) -> Result<String, S
 
     if !response.status().is_success() {
         error!("User channels fetch failed: {}", response.status());
-        return Err(format!("http_{}", response.status().as_u16()));
+        state.auth.report_status(response.status());
+        return Err(http_error(response.status()));
     }
 
     response.text().await.map_err(|e| {
@@ -109,41 +202,30 @@ pub async fn fetch_user_channels(state: State<'_, AppState>) -> Result<String, S
     })
 }
 
-#[tauri::command]
-pub async fn fetch_channel_messages(
-    state: State<'_, AppState>,
-    channel_id: String,
-    limit: Option<u32>,
-    before: Option<String>,
-) -> Result<String, String> {
-    let token = state
-        .token
-        .lock()
-        .await
-        .clone()
-        .ok_or_else(|| "not_authenticated".to_string())?;
+/// GET /channels/{id}/messages with the given query parameters, newest first.
+pub async fn get_channel_messages(
+    state: &AppState,
+    token: &str,
+    channel_id: Snowflake,
+    query: &[(&str, String)],
+) -> Result<Vec<serde_json::Value>, String> {
+    let mut url = state
+        .endpoints
+        .api(&format!("/channels/{}/messages", channel_id));
 
-    let mut url = format!(
-        "https://discord.com/api/v10/channels/{}/messages",
-        channel_id
-    );
-    
-    // Build query parameters
-    let mut query_params = Vec::new();
-    if let Some(limit) = limit {
-        query_params.push(format!("limit={}", limit));
-    }
-    if let Some(before) = before {
-        query_params.push(format!("before={}", before));
-    }
-    
-    if !query_params.is_empty() {
+    if !query.is_empty() {
+        let query_params: Vec<String> = query
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
         url = format!("{}?{}", url, query_params.join("&"));
     }
 
-    let response = reqwest::Client::new()
+    let response = state
+        .endpoints
+        .http()
         .get(&url)
-        .header("Authorization", &token)
+        .header("Authorization", token)
         .send()
         .await
         .map_err(|e| {
@@ -153,20 +235,40 @@ pub async fn fetch_channel_messages(
 
     if !response.status().is_success() {
         error!("Channel messages fetch failed: {}", response.status());
-        return Err(format!("http_{}", response.status().as_u16()));
+        state.auth.report_status(response.status());
+        return Err(http_error(response.status()));
     }
 
-    response.text().await.map_err(|e| {
+    response.json().await.map_err(|e| {
         error!("Failed to parse channel messages response: {}", e);
         "parse_failed".to_string()
     })
 }
 
+/// Fetches a page of messages and resolves their mentions.
+pub async fn fetch_resolved_messages<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    token: &str,
+    channel_id: Snowflake,
+    query: &[(&str, String)],
+) -> Result<Vec<serde_json::Value>, String> {
+    let state = app_handle.state::<AppState>();
+    let mut messages = get_channel_messages(&state, token, channel_id, query).await?;
+    attach_resolved(app_handle, &mut messages).await;
+    Ok(messages)
+}
+
+/// Loads a page of a channel. At most one of `before`, `after` and `around`
+/// may be given; without any the newest messages are returned.
 #[tauri::command]
-pub async fn send_message(
+pub async fn fetch_channel_messages<R: Runtime>(
     state: State<'_, AppState>,
-    channel_id: String,
-    content: String,
+    app_handle: AppHandle<R>,
+    channel_id: Snowflake,
+    limit: Option<u32>,
+    before: Option<Snowflake>,
+    after: Option<Snowflake>,
+    around: Option<Snowflake>,
 ) -> Result<String, String> {
     let token = state
         .token
@@ -175,35 +277,242 @@ pub async fn send_message(
         .clone()
         .ok_or_else(|| "not_authenticated".to_string())?;
 
-    let url = format!(
-        "https://discord.com/api/v10/channels/{}/messages",
-        channel_id
-    );
+    // Build query parameters
+    let mut query = Vec::new();
+    if let Some(limit) = limit {
+        query.push(("limit", limit.to_string()));
+    }
+    let cursors: Vec<(&str, String)> = [("before", before), ("after", after), ("around", around)]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?.to_string())))
+        .collect();
+    if cursors.len() > 1 {
+        return Err("conflicting_cursors".to_string());
+    }
+    let latest = cursors.is_empty();
+    query.extend(cursors);
+
+    let mut messages = fetch_resolved_messages(&app_handle, &token, channel_id, &query).await?;
+
+    if latest {
+        // Remember how far the UI got, to fill gaps after a reconnect
+        state
+            .history
+            .lock()
+            .await
+            .note_loaded(channel_id, &messages);
+
+        // The newest page also shows what is still waiting in the outbox
+        let echoes = state.outbox.lock().await.echoes(channel_id);
+        messages.splice(0..0, echoes);
+    }
+
+    serde_json::to_string(&messages).map_err(|e| {
+        error!("Failed to serialize channel messages: {}", e);
+        "parse_failed".to_string()
+    })
+}
+
+/// Why a message could not be posted.
+#[derive(Debug)]
+pub enum PostError {
+    Request,
+    RateLimited { retry_after_ms: i64 },
+    Http(u16),
+    Parse,
+}
+
+impl PostError {
+    /// The error code used by commands for this error.
+    pub fn code(&self) -> String {
+        match self {
+            PostError::Request => "request_failed".to_string(),
+            PostError::RateLimited { .. } => "http_429".to_string(),
+            PostError::Http(status) => format!("http_{}", status),
+            PostError::Parse => "parse_failed".to_string(),
+        }
+    }
+}
+
+/// Turns a non-2xx response into a `PostError`, or parses the body.
+async fn post_response(
+    state: &AppState,
+    response: reqwest::Response,
+    what: &str,
+) -> Result<serde_json::Value, PostError> {
+    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        // Slowmode and regular rate limits both say how long to wait
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        let retry_after = body["retry_after"].as_f64().unwrap_or(1.0);
+        return Err(PostError::RateLimited {
+            retry_after_ms: (retry_after * 1000.0).ceil() as i64,
+        });
+    }
+
+    if !response.status().is_success() {
+        error!("{} failed: {}", what, response.status());
+        // Reported for the sign out check, the outbox keeps the message
+        state.auth.report_status(response.status());
+        return Err(PostError::Http(response.status().as_u16()));
+    }
 
+    response.json().await.map_err(|e| {
+        error!("Failed to parse {} response: {}", what, e);
+        PostError::Parse
+    })
+}
+
+pub async fn post_message(
+    state: &AppState,
+    token: &str,
+    channel_id: Snowflake,
+    content: &str,
+    nonce: &str,
+    attachments: &[serde_json::Value],
+) -> Result<serde_json::Value, PostError> {
+    let url = state
+        .endpoints
+        .api(&format!("/channels/{}/messages", channel_id));
+
+    // With enforce_nonce Discord returns the existing message for a nonce it
+    // has already seen instead of creating a second one.
     let payload = serde_json::json!({
-        "content": content
+        "content": content,
+        "nonce": nonce,
+        "enforce_nonce": true,
+        "attachments": attachments
     });
 
-    let response = reqwest::Client::new()
+    let response = state
+        .endpoints
+        .http()
         .post(&url)
-        .header("Authorization", &token)
+        .header("Authorization", token)
         .header("Content-Type", "application/json")
         .json(&payload)
         .send()
         .await
         .map_err(|e| {
             error!("Request failed: {}", e);
-            "request_failed".to_string()
+            PostError::Request
+        })?;
+
+    post_response(state, response, "Send message").await
+}
+
+/// Uploads a text file the way the official client does: Discord hands out
+/// an upload URL, the file is PUT there, and the message then references it.
+/// Returns the entry for the message's `attachments`.
+pub async fn upload_attachment(
+    state: &AppState,
+    token: &str,
+    channel_id: Snowflake,
+    filename: &str,
+    content: &str,
+) -> Result<serde_json::Value, PostError> {
+    let url = state
+        .endpoints
+        .api(&format!("/channels/{}/attachments", channel_id));
+
+    let payload = serde_json::json!({
+        "files": [{ "id": "0", "filename": filename, "file_size": content.len() }]
+    });
+
+    let client = state.endpoints.http();
+    let response = client
+        .post(&url)
+        .header("Authorization", token)
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| {
+            error!("Request failed: {}", e);
+            PostError::Request
+        })?;
+
+    let slots = post_response(state, response, "Attachment upload").await?;
+    let slot = &slots["attachments"][0];
+    let (Some(upload_url), Some(upload_filename)) =
+        (slot["upload_url"].as_str(), slot["upload_filename"].as_str())
+    else {
+        error!("Attachment upload response without upload URL");
+        return Err(PostError::Parse);
+    };
+
+    let response = client
+        .put(upload_url)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(content.to_string())
+        .send()
+        .await
+        .map_err(|e| {
+            error!("Request failed: {}", e);
+            PostError::Request
         })?;
 
     if !response.status().is_success() {
-        error!("Send message failed: {}", response.status());
-        return Err(format!("http_{}", response.status().as_u16()));
+        error!("Attachment upload failed: {}", response.status());
+        state.auth.report_status(response.status());
+        return Err(PostError::Http(response.status().as_u16()));
     }
 
-    response.text().await.map_err(|e| {
-        error!("Failed to parse send message response: {}", e);
-        "parse_failed".to_string()
-    })
+    Ok(serde_json::json!({
+        "id": "0",
+        "filename": filename,
+        "uploaded_filename": upload_filename
+    }))
+}
+
+/// Queues a message in the outbox and returns the local echoes. The UI gets
+/// `MessagePending` right away and later either `MessageCreate` or
+/// `MessageSendFailed`. Content over 2000 characters is rejected with
+/// `message_too_long` unless `overflow` says to split it or to send it as a
+/// message.txt attachment.
+#[tauri::command]
+pub async fn send_message<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    channel_id: Snowflake,
+    content: String,
+    overflow: Option<Overflow>,
+) -> Result<String, String> {
+    if state.token.lock().await.is_none() {
+        return Err("not_authenticated".to_string());
+    }
+
+    let (capabilities, content) = {
+        let cache = state.cache.lock().await;
+        (
+            capabilities_for(&cache, channel_id),
+            encode_mentions(&cache, channel_id, &content),
+        )
+    };
+    if capabilities.is_some_and(|caps| !caps.send) {
+        return Err("missing_permissions".to_string());
+    }
+
+    let messages = if content.chars().count() <= MAX_MESSAGE_LENGTH {
+        vec![(content, false)]
+    } else {
+        match overflow {
+            None => return Err("message_too_long".to_string()),
+            Some(Overflow::Split) => split_content(&content, MAX_MESSAGE_LENGTH)
+                .into_iter()
+                .map(|chunk| (chunk, false))
+                .collect(),
+            Some(Overflow::Attachment) => {
+                if capabilities.is_some_and(|caps| !caps.attach) {
+                    return Err("missing_permissions".to_string());
+                }
+                vec![(content, true)]
+            }
+        }
+    };
+
+    let mut echoes = Vec::new();
+    for (content, as_attachment) in messages {
+        echoes.push(enqueue(&state, &app_handle, channel_id, content, as_attachment).await?);
+    }
+    Ok(serde_json::Value::Array(echoes).to_string())
 }
 
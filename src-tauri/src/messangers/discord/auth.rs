@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+use super::{
+    account::record_check,
+    api::check_discord_token,
+    cache::DiscordCache,
+    gateway::{GatewayEvent, emit},
+    history::MessageHistory,
+    links::LinkPreviews,
+};
+use crate::AppState;
+
+/// What happens when Discord signs the account out, persisted as JSON in the
+/// app data directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthSettings {
+    // Also forget cached guilds, messages and unsent messages
+    pub clear_private_data: bool,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl AuthSettings {
+    pub async fn load(path: PathBuf) -> Self {
+        let settings: Self = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                error!("Failed to parse auth settings: {}", e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        Self {
+            path: Some(path),
+            ..settings
+        }
+    }
+
+    async fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| format!("Failed to create data dir: {}", e))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize auth settings: {}", e))?;
+        tokio::fs::write(path, json)
+            .await
+            .map_err(|e| format!("Failed to write auth settings: {}", e))
+    }
+}
+
+/// The error code for a failed REST response.
+pub fn http_error(status: StatusCode) -> String {
+    format!("http_{}", status.as_u16())
+}
+
+/// Lets `run_auth_watch` know about REST responses that say the token may no
+/// longer be valid.
+#[derive(Debug, Default)]
+pub struct AuthWatch {
+    unauthorized: Notify,
+}
+
+impl AuthWatch {
+    /// Reports the status of a failed REST response. A 401 wakes
+    /// `run_auth_watch`, which signs out if the token was revoked.
+    pub fn report_status(&self, status: StatusCode) {
+        if status == StatusCode::UNAUTHORIZED {
+            self.unauthorized.notify_one();
+        }
+    }
+}
+
+/// The one way out of a session Discord no longer accepts: the account is
+/// saved as invalid, the gateway stops, the outbox waits for the account to
+/// sign in again, and the UI gets `SignedOut` to ask for a new token.
+pub async fn sign_out<R: Runtime>(app_handle: &AppHandle<R>, reason: &str) {
+    let state = app_handle.state::<AppState>();
+    // Without a token the outbox stops sending
+    let Some(token) = state.token.lock().await.take() else {
+        return;
+    };
+    warn!("Signed out by Discord: {}", reason);
+
+    record_check(&state, &token, &Ok(None)).await;
+    state.gateway.lock().await.disconnect().await;
+
+    if state.auth_settings.lock().await.clear_private_data {
+        info!("Clearing cached data after sign out");
+        *state.cache.lock().await = DiscordCache::default();
+        *state.history.lock().await = MessageHistory::default();
+        *state.link_previews.lock().await = LinkPreviews::default();
+        state.outbox.lock().await.clear().await;
+    }
+
+    if let Err(e) = emit(app_handle, GatewayEvent::SignedOut(reason.to_string())) {
+        error!("Failed to emit sign out: {}", e);
+    }
+}
+
+/// Signs out after a 401 once Discord confirms the token itself is no longer
+/// valid; a 401 from a single endpoint does not end the session.
+pub async fn run_auth_watch<R: Runtime>(app_handle: AppHandle<R>) {
+    let state = app_handle.state::<AppState>();
+    loop {
+        state.auth.unauthorized.notified().await;
+        let Some(token) = state.token.lock().await.clone() else {
+            continue;
+        };
+        if let Ok(None) = check_discord_token(&state.endpoints, &token).await {
+            sign_out(&app_handle, "token_revoked").await;
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_auth_settings(state: State<'_, AppState>) -> Result<AuthSettings, String> {
+    Ok(state.auth_settings.lock().await.clone())
+}
+
+#[tauri::command]
+pub async fn set_auth_settings(
+    state: State<'_, AppState>,
+    settings: AuthSettings,
+) -> Result<(), String> {
+    let mut current = state.auth_settings.lock().await;
+    current.clear_private_data = settings.clear_private_data;
+    current.save().await
+}
@@ -0,0 +1,671 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use super::{
+    models::{Channel, Emoji, Member, ReadState, Role, User},
+    snowflake::Snowflake,
+};
+
+/// Guild state built from READY / GUILD_CREATE and kept live by gateway events.
+#[derive(Debug, Default)]
+pub struct GuildCache {
+    pub id: Snowflake,
+    pub owner_id: Option<Snowflake>,
+    pub roles: HashMap<Snowflake, Role>,
+    // Channels and active threads, keyed by id
+    pub channels: HashMap<Snowflake, Channel>,
+    pub members: HashMap<Snowflake, Member>,
+    // Custom emoji, keyed by id
+    pub emojis: HashMap<Snowflake, Emoji>,
+    // user id -> "online" | "idle" | "dnd" | "offline"
+    pub presences: HashMap<Snowflake, String>,
+    // Channel whose member list we subscribed to with op 14
+    pub member_list_channel: Option<Snowflake>,
+    // The member sidebar in Discord's order, kept by GUILD_MEMBER_LIST_UPDATE
+    pub member_list: Vec<MemberListRow>,
+}
+
+/// A row of the lazy member list: a group header or a member.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemberListRow {
+    // Role id for hoisted roles, otherwise "online" / "offline"
+    Group { id: String, count: u32 },
+    Member(Snowflake),
+}
+
+impl GuildCache {
+    pub fn status_of(&self, user_id: Snowflake) -> &str {
+        match self.presences.get(&user_id).map(String::as_str) {
+            Some("invisible") | None => "offline",
+            Some(status) => status,
+        }
+    }
+
+    fn upsert_member(&mut self, member: Member) {
+        if let Some(id) = member.user_id() {
+            self.members.insert(id, member);
+        }
+    }
+
+    fn set_emojis(&mut self, emojis: &Value) {
+        self.emojis.clear();
+        for emoji in emojis
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(parse::<Emoji>)
+        {
+            if let Some(id) = emoji.id {
+                self.emojis.insert(id, emoji);
+            }
+        }
+    }
+
+    fn set_presence(&mut self, presence: &Value) {
+        let (Some(user_id), Some(status)) = (
+            Snowflake::from_value(&presence["user"]["id"]),
+            presence["status"].as_str(),
+        ) else {
+            return;
+        };
+        self.presences.insert(user_id, status.to_string());
+    }
+
+    /// Parses a list item, caching the member and its presence on the way.
+    fn member_list_row(&mut self, item: &Value) -> Option<MemberListRow> {
+        if let Some(group) = item.get("group") {
+            return Some(MemberListRow::Group {
+                id: group["id"].as_str()?.to_string(),
+                count: group["count"].as_u64().unwrap_or(0) as u32,
+            });
+        }
+        let member = parse::<Member>(&item["member"])?;
+        let user_id = member.user_id()?;
+        self.upsert_member(member);
+        if let Some(status) = item["member"]["presence"]["status"].as_str() {
+            self.presences.insert(user_id, status.to_string());
+        }
+        Some(MemberListRow::Member(user_id))
+    }
+
+    /// Applies one op of GUILD_MEMBER_LIST_UPDATE to `member_list`.
+    fn apply_member_list_op(&mut self, op: &Value) {
+        let index = op["index"].as_u64().map(|i| i as usize);
+        let range = |i: usize| op["range"][i].as_u64().map(|i| i as usize);
+        match op["op"].as_str().unwrap_or_default() {
+            "SYNC" => {
+                let (Some(start), Some(end)) = (range(0), range(1)) else {
+                    return;
+                };
+                let rows: Vec<_> = op["items"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|item| self.member_list_row(item))
+                    .collect();
+                let len = self.member_list.len();
+                self.member_list
+                    .splice(start.min(len)..(end + 1).min(len), rows);
+            }
+            "INSERT" => {
+                let (Some(index), Some(row)) = (index, self.member_list_row(&op["item"])) else {
+                    return;
+                };
+                let index = index.min(self.member_list.len());
+                self.member_list.insert(index, row);
+            }
+            "UPDATE" => {
+                let (Some(index), Some(row)) = (index, self.member_list_row(&op["item"])) else {
+                    return;
+                };
+                if let Some(slot) = self.member_list.get_mut(index) {
+                    *slot = row;
+                }
+            }
+            "DELETE" => {
+                if let Some(index) = index.filter(|&i| i < self.member_list.len()) {
+                    self.member_list.remove(index);
+                }
+            }
+            // We only subscribe to the first range, so everything from its
+            // start is gone
+            "INVALIDATE" => {
+                if let Some(start) = range(0) {
+                    self.member_list.truncate(start);
+                }
+            }
+            op => debug!("Unhandled member list op: {}", op),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DiscordCache {
+    pub current_user: Option<User>,
+    pub guilds: HashMap<Snowflake, GuildCache>,
+    // Users seen in READY, messages or fetched on demand
+    pub users: HashMap<Snowflake, User>,
+    // DMs and group DMs, keyed by channel id
+    pub private_channels: HashMap<Snowflake, Channel>,
+    // channel id -> read marker
+    pub read_states: HashMap<Snowflake, ReadState>,
+}
+
+impl DiscordCache {
+    pub fn guild(&self, guild_id: Snowflake) -> Option<&GuildCache> {
+        self.guilds.get(&guild_id)
+    }
+
+    /// Finds a guild channel (or thread) by id along with its guild.
+    pub fn channel(&self, channel_id: Snowflake) -> Option<(&GuildCache, &Channel)> {
+        self.guilds
+            .values()
+            .find_map(|g| g.channels.get(&channel_id).map(|c| (g, c)))
+    }
+
+    /// Any known channel, guild or private.
+    pub fn any_channel(&self, channel_id: Snowflake) -> Option<&Channel> {
+        self.private_channels
+            .get(&channel_id)
+            .or_else(|| self.channel(channel_id).map(|(_, c)| c))
+    }
+
+    fn any_channel_mut(&mut self, channel_id: Snowflake) -> Option<&mut Channel> {
+        if let Some(channel) = self.private_channels.get_mut(&channel_id) {
+            return Some(channel);
+        }
+        self.guilds
+            .values_mut()
+            .find_map(|g| g.channels.get_mut(&channel_id))
+    }
+
+    /// Whether the channel has messages newer than its read marker. Channels
+    /// without a read marker are treated as read.
+    pub fn is_unread(&self, channel_id: Snowflake) -> bool {
+        let Some(last_message_id) = self.any_channel(channel_id).and_then(|c| c.last_message_id)
+        else {
+            return false;
+        };
+        let Some(read) = self.read_states.get(&channel_id) else {
+            return false;
+        };
+        read.last_message_id
+            .is_none_or(|read_id| last_message_id > read_id)
+    }
+
+    /// Moves the read marker of a channel forward and clears its mentions.
+    /// Acks older than the marker, e.g. after jumping into history, leave it
+    /// where it is.
+    pub fn mark_read(&mut self, channel_id: Snowflake, message_id: Snowflake) {
+        let entry = self.read_states.entry(channel_id).or_default();
+        if entry
+            .last_message_id
+            .is_some_and(|read_id| read_id > message_id)
+        {
+            return;
+        }
+        entry.last_message_id = Some(message_id);
+        entry.mention_count = 0;
+    }
+
+    /// Replaces a guild's channels with a fresh REST listing.
+    pub fn replace_channels(&mut self, guild_id: Snowflake, channels: Vec<Channel>) {
+        let Some(guild) = self.guilds.get_mut(&guild_id) else {
+            return;
+        };
+        // The channel listing does not include threads, keep those around.
+        guild.channels.retain(|_, c| c.is_thread());
+        for channel in channels {
+            guild.channels.insert(channel.id, channel);
+        }
+    }
+
+    /// USER_UPDATE is only sent for the current user.
+    pub fn apply_user_update(&mut self, data: &Value) {
+        if let Some(user) = parse::<User>(data) {
+            self.remember_user(data);
+            self.current_user = Some(user);
+        }
+    }
+
+    pub fn apply_ready(&mut self, data: &Value) {
+        self.guilds.clear();
+        self.private_channels.clear();
+        self.read_states.clear();
+        self.users.clear();
+        self.current_user = parse(&data["user"]);
+
+        for user in data["users"].as_array().into_iter().flatten() {
+            self.remember_user(user);
+        }
+
+        for channel in data["private_channels"].as_array().into_iter().flatten() {
+            if let Some(channel) = parse::<Channel>(channel) {
+                self.private_channels.insert(channel.id, channel);
+            }
+        }
+
+        // Either a bare array or, with newer capabilities, { "entries": [...] }
+        let read_state = data["read_state"]
+            .as_array()
+            .or_else(|| data["read_state"]["entries"].as_array());
+        for entry in read_state.into_iter().flatten() {
+            // Non-channel read states (notification center etc.) have a type
+            if entry["read_state_type"].as_u64().unwrap_or(0) != 0 {
+                continue;
+            }
+            let Some(channel_id) = Snowflake::from_value(&entry["id"]) else {
+                continue;
+            };
+            self.read_states.insert(
+                channel_id,
+                ReadState {
+                    last_message_id: Snowflake::from_value(&entry["last_message_id"]),
+                    mention_count: entry["mention_count"].as_u64().unwrap_or(0) as u32,
+                },
+            );
+        }
+
+        let guilds = data["guilds"].as_array().cloned().unwrap_or_default();
+        for guild in &guilds {
+            self.apply_guild_create(guild);
+        }
+
+        // User accounts receive their own member objects separately, indexed
+        // like the guilds array.
+        if let Some(merged) = data["merged_members"].as_array() {
+            for (guild, members) in guilds.iter().zip(merged) {
+                let Some(cached) =
+                    Snowflake::from_value(&guild["id"]).and_then(|id| self.guilds.get_mut(&id))
+                else {
+                    continue;
+                };
+                for member in members.as_array().into_iter().flatten() {
+                    let mut member = member.clone();
+                    // merged members carry a bare user_id instead of a user object
+                    if member.get("user").is_none() {
+                        member["user"] = serde_json::json!({ "id": member["user_id"] });
+                    }
+                    if let Some(member) = parse::<Member>(&member) {
+                        cached.upsert_member(member);
+                    }
+                }
+            }
+        }
+
+        debug!("Cache populated with {} guilds", self.guilds.len());
+    }
+
+    pub fn apply_guild_create(&mut self, data: &Value) {
+        let Some(id) = Snowflake::from_value(&data["id"]) else {
+            warn!("Guild payload without id");
+            return;
+        };
+
+        // Newer user READY payloads nest guild metadata under "properties".
+        let properties = if data["properties"].is_object() {
+            &data["properties"]
+        } else {
+            data
+        };
+
+        let mut guild = GuildCache {
+            id,
+            owner_id: Snowflake::from_value(&properties["owner_id"]),
+            ..Default::default()
+        };
+
+        for role in data["roles"].as_array().into_iter().flatten() {
+            if let Some(role) = parse::<Role>(role) {
+                guild.roles.insert(role.id, role);
+            }
+        }
+        let channels = data["channels"].as_array().into_iter().flatten();
+        let threads = data["threads"].as_array().into_iter().flatten();
+        for channel in channels.chain(threads) {
+            if let Some(channel) = parse::<Channel>(channel) {
+                guild.channels.insert(channel.id, channel);
+            }
+        }
+        for member in data["members"].as_array().into_iter().flatten() {
+            if let Some(member) = parse::<Member>(member) {
+                guild.upsert_member(member);
+            }
+        }
+        for presence in data["presences"].as_array().into_iter().flatten() {
+            guild.set_presence(presence);
+        }
+        guild.set_emojis(&data["emojis"]);
+
+        self.guilds.insert(id, guild);
+    }
+
+    pub fn apply_guild_delete(&mut self, data: &Value) {
+        if let Some(id) = Snowflake::from_value(&data["id"]) {
+            self.guilds.remove(&id);
+        }
+    }
+
+    /// GUILD_EMOJIS_UPDATE sends the full emoji list of the guild.
+    pub fn apply_emojis_update(&mut self, data: &Value) {
+        if let Some(guild) = self.guild_mut(data) {
+            guild.set_emojis(&data["emojis"]);
+        }
+    }
+
+    pub fn apply_role_upsert(&mut self, data: &Value) -> Option<Snowflake> {
+        let guild = self.guild_mut(data)?;
+        let role = parse::<Role>(&data["role"])?;
+        guild.roles.insert(role.id, role);
+        Some(guild.id)
+    }
+
+    pub fn apply_role_delete(&mut self, data: &Value) -> Option<Snowflake> {
+        let guild = self.guild_mut(data)?;
+        guild
+            .roles
+            .remove(&Snowflake::from_value(&data["role_id"])?);
+        Some(guild.id)
+    }
+
+    /// CHANNEL_CREATE / CHANNEL_UPDATE and their THREAD_* counterparts all
+    /// send the full channel object.
+    pub fn apply_channel_upsert(&mut self, data: &Value) -> Option<Snowflake> {
+        if data["guild_id"].is_null() {
+            // A new DM or group DM; there is no guild tree to refresh
+            let channel = parse::<Channel>(data)?;
+            self.private_channels.insert(channel.id, channel);
+            return None;
+        }
+        let guild = self.guild_mut(data)?;
+        let channel = parse::<Channel>(data)?;
+        guild.channels.insert(channel.id, channel);
+        Some(guild.id)
+    }
+
+    pub fn apply_channel_delete(&mut self, data: &Value) -> Option<Snowflake> {
+        let guild = self.guild_mut(data)?;
+        let channel_id = Snowflake::from_value(&data["id"])?;
+        guild.channels.remove(&channel_id);
+        // Threads of a deleted channel go with it
+        guild
+            .channels
+            .retain(|_, c| !(c.is_thread() && c.parent_id == Some(channel_id)));
+        Some(guild.id)
+    }
+
+    /// THREAD_LIST_SYNC replaces the active threads of the listed parents, or
+    /// of the whole guild when no parents are given.
+    pub fn apply_thread_list_sync(&mut self, data: &Value) -> Option<Snowflake> {
+        let guild = self.guild_mut(data)?;
+        let parents: Option<Vec<Snowflake>> = data["channel_ids"]
+            .as_array()
+            .map(|ids| ids.iter().filter_map(Snowflake::from_value).collect());
+        guild.channels.retain(|_, c| {
+            !c.is_thread()
+                || parents.as_ref().is_some_and(|parents| {
+                    c.parent_id
+                        .is_none_or(|parent_id| !parents.contains(&parent_id))
+                })
+        });
+        for thread in data["threads"].as_array().into_iter().flatten() {
+            if let Some(thread) = parse::<Channel>(thread) {
+                guild.channels.insert(thread.id, thread);
+            }
+        }
+        Some(guild.id)
+    }
+
+    /// Bumps the channel's last message and updates its read state: our own
+    /// messages mark the channel read, mentions of us are counted.
+    /// Returns the channel id.
+    pub fn apply_message_create(&mut self, data: &Value) -> Option<Snowflake> {
+        let channel_id = Snowflake::from_value(&data["channel_id"])?;
+        let message_id = Snowflake::from_value(&data["id"])?;
+        let own_message = self
+            .current_user
+            .as_ref()
+            .is_some_and(|me| Snowflake::from_value(&data["author"]["id"]) == Some(me.id));
+        let mentioned = !own_message && self.mentions_current_user(data);
+
+        self.remember_user(&data["author"]);
+        for user in data["mentions"].as_array().into_iter().flatten() {
+            self.remember_user(user);
+        }
+
+        let channel = self.any_channel_mut(channel_id)?;
+        let previous = channel.last_message_id.replace(message_id);
+
+        if own_message {
+            self.mark_read(channel_id, message_id);
+        } else {
+            // Without a marker the channel counts as read up to the previous message
+            let entry = self
+                .read_states
+                .entry(channel_id)
+                .or_insert_with(|| ReadState {
+                    last_message_id: previous,
+                    mention_count: 0,
+                });
+            if mentioned {
+                entry.mention_count += 1;
+            }
+        }
+        Some(channel_id)
+    }
+
+    fn remember_user(&mut self, user: &Value) {
+        if let Some(user) = parse::<User>(user) {
+            self.users.insert(user.id, user);
+        }
+    }
+
+    /// MESSAGE_ACK is sent when any of our sessions reads a channel.
+    pub fn apply_message_ack(&mut self, data: &Value) -> Option<Snowflake> {
+        let channel_id = Snowflake::from_value(&data["channel_id"])?;
+        let message_id = Snowflake::from_value(&data["message_id"])?;
+        let entry = self.read_states.entry(channel_id).or_default();
+        // A delayed ack must not bring back unreads
+        if entry
+            .last_message_id
+            .is_some_and(|read_id| read_id > message_id)
+        {
+            return None;
+        }
+        entry.last_message_id = Some(message_id);
+        entry.mention_count = data["mention_count"].as_u64().unwrap_or(0) as u32;
+        Some(channel_id)
+    }
+
+    /// DMs always notify; in guilds a direct, role or @everyone mention does.
+    pub fn mentions_current_user(&self, message: &Value) -> bool {
+        let Some(me) = self.current_user.as_ref() else {
+            return false;
+        };
+        let Some(channel_id) = Snowflake::from_value(&message["channel_id"]) else {
+            return false;
+        };
+        if self.private_channels.contains_key(&channel_id) {
+            return true;
+        }
+
+        let direct = message["mentions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|u| Snowflake::from_value(&u["id"]) == Some(me.id));
+        if direct || message["mention_everyone"].as_bool().unwrap_or(false) {
+            return true;
+        }
+
+        let my_roles = self
+            .channel(channel_id)
+            .and_then(|(guild, _)| guild.members.get(&me.id))
+            .map(|m| m.roles.as_slice())
+            .unwrap_or_default();
+        message["mention_roles"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Snowflake::from_value)
+            .any(|role| my_roles.contains(&role))
+    }
+
+    /// GUILD_MEMBER_ADD and GUILD_MEMBER_UPDATE both carry a full member object
+    /// flattened next to `guild_id`.
+    pub fn apply_member_upsert(&mut self, data: &Value) -> Option<Snowflake> {
+        let guild = self.guild_mut(data)?;
+        guild.upsert_member(parse::<Member>(data)?);
+        Some(guild.id)
+    }
+
+    pub fn apply_member_remove(&mut self, data: &Value) -> Option<Snowflake> {
+        let guild = self.guild_mut(data)?;
+        let user_id = Snowflake::from_value(&data["user"]["id"])?;
+        guild.members.remove(&user_id);
+        guild.presences.remove(&user_id);
+        Some(guild.id)
+    }
+
+    pub fn apply_members_chunk(&mut self, data: &Value) -> Option<Snowflake> {
+        let guild = self.guild_mut(data)?;
+        for member in data["members"].as_array().into_iter().flatten() {
+            if let Some(member) = parse::<Member>(member) {
+                guild.upsert_member(member);
+            }
+        }
+        for presence in data["presences"].as_array().into_iter().flatten() {
+            guild.set_presence(presence);
+        }
+        Some(guild.id)
+    }
+
+    pub fn apply_member_list_update(&mut self, data: &Value) -> Option<Snowflake> {
+        let guild = self.guild_mut(data)?;
+        for op in data["ops"].as_array().into_iter().flatten() {
+            guild.apply_member_list_op(op);
+        }
+        Some(guild.id)
+    }
+
+    pub fn apply_presence_update(&mut self, data: &Value) -> Option<Snowflake> {
+        let guild = self.guild_mut(data)?;
+        guild.set_presence(data);
+        Some(guild.id)
+    }
+
+    fn guild_mut(&mut self, data: &Value) -> Option<&mut GuildCache> {
+        self.guilds
+            .get_mut(&Snowflake::from_value(&data["guild_id"])?)
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(value: &Value) -> Option<T> {
+    serde_json::from_value(value.clone())
+        .map_err(|e| debug!("Skipping malformed cache entry: {}", e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn guild_cache() -> DiscordCache {
+        let mut cache = DiscordCache::default();
+        cache.apply_guild_create(&json!({ "id": "1" }));
+        cache
+    }
+
+    fn member(id: &str) -> Value {
+        json!({ "member": { "user": { "id": id }, "roles": [] } })
+    }
+
+    fn member_list(cache: &DiscordCache) -> &[MemberListRow] {
+        &cache.guild(Snowflake(1)).unwrap().member_list
+    }
+
+    fn unread_channel() -> DiscordCache {
+        let mut cache = DiscordCache::default();
+        cache.apply_ready(&json!({
+            "user": { "id": "2" },
+            "private_channels": [{ "id": "5", "type": 1, "last_message_id": "30" }],
+            "read_state": [{ "id": "5", "last_message_id": "10", "mention_count": 1 }],
+        }));
+        cache
+    }
+
+    #[test]
+    fn read_marker_only_moves_forward() {
+        let mut cache = unread_channel();
+        cache.mark_read(Snowflake(5), Snowflake(30));
+        assert!(!cache.is_unread(Snowflake(5)));
+
+        cache.mark_read(Snowflake(5), Snowflake(20));
+        assert!(!cache.is_unread(Snowflake(5)));
+        assert_eq!(
+            cache.read_states[&Snowflake(5)].last_message_id,
+            Some(Snowflake(30))
+        );
+    }
+
+    #[test]
+    fn late_message_ack_is_ignored() {
+        let mut cache = unread_channel();
+        let ack = |id: &str| json!({ "channel_id": "5", "message_id": id, "mention_count": 0 });
+        assert_eq!(cache.apply_message_ack(&ack("30")), Some(Snowflake(5)));
+        assert_eq!(cache.apply_message_ack(&ack("20")), None);
+        assert!(!cache.is_unread(Snowflake(5)));
+    }
+
+    #[test]
+    fn member_list_ops_keep_discords_order() {
+        let mut cache = guild_cache();
+        let update = |ops: Value| json!({ "guild_id": "1", "ops": ops });
+        cache.apply_member_list_update(&update(json!([{
+            "op": "SYNC",
+            "range": [0, 99],
+            "items": [{ "group": { "id": "online", "count": 2 } }, member("10"), member("11")],
+        }])));
+        cache.apply_member_list_update(&update(json!([
+            { "op": "DELETE", "index": 1 },
+            { "op": "INSERT", "index": 2, "item": member("12") },
+            { "op": "UPDATE", "index": 0, "item": { "group": { "id": "online", "count": 3 } } },
+        ])));
+
+        assert_eq!(
+            member_list(&cache),
+            [
+                MemberListRow::Group {
+                    id: "online".to_string(),
+                    count: 3
+                },
+                MemberListRow::Member(Snowflake(11)),
+                MemberListRow::Member(Snowflake(12)),
+            ]
+        );
+        assert!(
+            cache
+                .guild(Snowflake(1))
+                .unwrap()
+                .members
+                .contains_key(&Snowflake(12))
+        );
+
+        cache.apply_member_list_update(&update(json!([{ "op": "INVALIDATE", "range": [0, 99] }])));
+        assert!(member_list(&cache).is_empty());
+    }
+
+    #[test]
+    fn member_list_sync_replaces_its_whole_range() {
+        let mut cache = guild_cache();
+        let sync = |items: Value| json!({ "guild_id": "1", "ops": [{ "op": "SYNC", "range": [0, 99], "items": items }] });
+        cache.apply_member_list_update(&sync(json!([member("10"), member("11")])));
+        cache.apply_member_list_update(&sync(json!([member("12")])));
+
+        assert_eq!(member_list(&cache), [MemberListRow::Member(Snowflake(12))]);
+    }
+}
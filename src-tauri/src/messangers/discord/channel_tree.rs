@@ -0,0 +1,222 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use serde::Serialize;
+use tauri::State;
+
+use super::{
+    api::fetch_guild_channels,
+    cache::{DiscordCache, GuildCache},
+    models::Channel,
+    permissions::capabilities_for,
+    snowflake::Snowflake,
+};
+use crate::AppState;
+
+// https://discord.com/developers/docs/resources/channel#channel-object-channel-types
+const GUILD_VOICE: u8 = 2;
+pub(super) const GUILD_CATEGORY: u8 = 4;
+const GUILD_STAGE_VOICE: u8 = 13;
+
+/// A channel with its nested children: channels for a category, threads for
+/// a text channel.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelTreeNode {
+    #[serde(flatten)]
+    pub channel: Channel,
+    pub children: Vec<ChannelTreeNode>,
+}
+
+impl ChannelTreeNode {
+    fn leaf(channel: &Channel) -> Self {
+        Self {
+            channel: channel.clone(),
+            children: Vec::new(),
+        }
+    }
+}
+
+fn is_voice(channel: &Channel) -> bool {
+    matches!(channel.kind, GUILD_VOICE | GUILD_STAGE_VOICE)
+}
+
+/// Sidebar order inside a category: text-like channels before voice, then
+/// `position`, then id.
+fn sidebar_order(a: &Channel, b: &Channel) -> Ordering {
+    is_voice(a)
+        .cmp(&is_voice(b))
+        .then(a.position.cmp(&b.position))
+        .then(a.id.cmp(&b.id))
+}
+
+/// Builds the sidebar tree: uncategorized channels first, then categories by
+/// `position` and id, each holding its channels and their threads.
+pub fn build_channel_tree<'a>(
+    channels: impl IntoIterator<Item = &'a Channel>,
+) -> Vec<ChannelTreeNode> {
+    let mut categories: Vec<&Channel> = Vec::new();
+    let mut regular: Vec<&Channel> = Vec::new();
+    let mut threads: HashMap<Snowflake, Vec<&Channel>> = HashMap::new();
+
+    for channel in channels {
+        if channel.kind == GUILD_CATEGORY {
+            categories.push(channel);
+        } else if channel.is_thread() {
+            if let Some(parent_id) = channel.parent_id {
+                threads.entry(parent_id).or_default().push(channel);
+            }
+        } else {
+            regular.push(channel);
+        }
+    }
+
+    let with_threads = |channel: &Channel| {
+        let mut node = ChannelTreeNode::leaf(channel);
+        if let Some(children) = threads.get(&channel.id) {
+            let mut children = children.clone();
+            children.sort_by_key(|c| c.id);
+            node.children = children.into_iter().map(ChannelTreeNode::leaf).collect();
+        }
+        node
+    };
+
+    categories.sort_by(|a, b| a.position.cmp(&b.position).then(a.id.cmp(&b.id)));
+    regular.sort_by(|a, b| sidebar_order(a, b));
+
+    let mut by_category: HashMap<Snowflake, Vec<ChannelTreeNode>> = HashMap::new();
+    let mut tree = Vec::new();
+    for channel in regular {
+        match channel.parent_id {
+            Some(parent_id) if categories.iter().any(|c| c.id == parent_id) => {
+                by_category
+                    .entry(parent_id)
+                    .or_default()
+                    .push(with_threads(channel));
+            }
+            _ => tree.push(with_threads(channel)),
+        }
+    }
+
+    tree.extend(categories.into_iter().map(|category| ChannelTreeNode {
+        channel: category.clone(),
+        children: by_category.remove(&category.id).unwrap_or_default(),
+    }));
+    tree
+}
+
+/// The guild's channels the current user can view. A hidden category stays
+/// when one of its channels is visible, so the channel keeps its place in the
+/// sidebar.
+pub fn visible_channels<'a>(cache: &'a DiscordCache, guild: &'a GuildCache) -> Vec<&'a Channel> {
+    let can_view = |c: &Channel| capabilities_for(cache, c.id).is_none_or(|caps| caps.view);
+    let mut channels: Vec<&Channel> = guild.channels.values().filter(|c| can_view(c)).collect();
+    let hidden_parents: Vec<&Channel> = guild
+        .channels
+        .values()
+        .filter(|c| {
+            c.kind == GUILD_CATEGORY
+                && !can_view(c)
+                && channels.iter().any(|child| child.parent_id == Some(c.id))
+        })
+        .collect();
+    channels.extend(hidden_parents);
+    channels
+}
+
+/// Returns the guild's channels as a ready-made sidebar tree, without the
+/// channels the current user cannot view. Changes are announced through
+/// `ChannelTreeUpdate` events.
+#[tauri::command]
+pub async fn fetch_guild_channel_tree(
+    state: State<'_, AppState>,
+    guild_id: Snowflake,
+) -> Result<Vec<ChannelTreeNode>, String> {
+    let needs_fetch = state
+        .cache
+        .lock()
+        .await
+        .guild(guild_id)
+        .is_none_or(|g| g.channels.is_empty());
+    if needs_fetch {
+        fetch_guild_channels(state.clone(), guild_id).await?;
+    }
+
+    let cache = state.cache.lock().await;
+    let guild = cache
+        .guild(guild_id)
+        .ok_or_else(|| "unknown_guild".to_string())?;
+    Ok(build_channel_tree(visible_channels(&cache, guild)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::messangers::discord::permissions::VIEW_CHANNEL;
+
+    const GUILD: Snowflake = Snowflake(1);
+    const ME: Snowflake = Snowflake(2);
+    const CATEGORY: Snowflake = Snowflake(10);
+    const TEXT: Snowflake = Snowflake(11);
+    const VOICE: Snowflake = Snowflake(12);
+    const THREAD: Snowflake = Snowflake(13);
+    const LOOSE: Snowflake = Snowflake(14);
+
+    fn hide(id: Snowflake) -> serde_json::Value {
+        json!([{ "id": id, "type": 0, "allow": "0", "deny": VIEW_CHANNEL.to_string() }])
+    }
+
+    fn cache(
+        category_overwrites: serde_json::Value,
+        text_overwrites: serde_json::Value,
+    ) -> DiscordCache {
+        let mut cache = DiscordCache::default();
+        cache.apply_user_update(&json!({ "id": ME }));
+        cache.apply_guild_create(&json!({
+            "id": GUILD,
+            "roles": [{ "id": GUILD, "permissions": VIEW_CHANNEL.to_string() }],
+            "channels": [
+                { "id": VOICE, "type": 2, "parent_id": CATEGORY, "position": 0 },
+                { "id": TEXT, "type": 0, "parent_id": CATEGORY, "position": 1,
+                  "permission_overwrites": text_overwrites },
+                { "id": CATEGORY, "type": 4, "position": 0,
+                  "permission_overwrites": category_overwrites },
+                { "id": LOOSE, "type": 0, "position": 5 },
+            ],
+            "threads": [{ "id": THREAD, "type": 11, "parent_id": TEXT }],
+            "members": [{ "user": { "id": ME }, "roles": [] }],
+        }));
+        cache
+    }
+
+    fn tree(cache: &DiscordCache) -> Vec<ChannelTreeNode> {
+        build_channel_tree(visible_channels(cache, cache.guild(GUILD).unwrap()))
+    }
+
+    fn ids(nodes: &[ChannelTreeNode]) -> Vec<Snowflake> {
+        nodes.iter().map(|n| n.channel.id).collect()
+    }
+
+    #[test]
+    fn nests_channels_and_threads() {
+        let tree = tree(&cache(json!([]), json!([])));
+        assert_eq!(ids(&tree), [LOOSE, CATEGORY]);
+        // Text before voice, whatever the position
+        assert_eq!(ids(&tree[1].children), [TEXT, VOICE]);
+        assert_eq!(ids(&tree[1].children[0].children), [THREAD]);
+    }
+
+    #[test]
+    fn hidden_category_keeps_its_visible_channels_grouped() {
+        // The channels do not inherit the category's overwrites here
+        let tree = tree(&cache(hide(GUILD), json!([])));
+        assert_eq!(ids(&tree), [LOOSE, CATEGORY]);
+        assert_eq!(ids(&tree[1].children), [TEXT, VOICE]);
+    }
+
+    #[test]
+    fn hidden_channels_are_left_out() {
+        let tree = tree(&cache(json!([]), hide(GUILD)));
+        assert_eq!(ids(&tree[1].children), [VOICE]);
+    }
+}
@@ -0,0 +1,601 @@
+use std::{borrow::Cow, sync::LazyLock};
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::{
+    cache::{DiscordCache, GuildCache},
+    channel_tree::GUILD_CATEGORY,
+    models::{Channel, Emoji, User},
+    permissions::capabilities_for,
+    snowflake::Snowflake,
+};
+use crate::AppState;
+
+const AUTOCOMPLETE_LIMIT: usize = 10;
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+
+// Already encoded entities and URLs are matched first so they pass through
+// untouched.
+static TOKEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?x)
+        <(?:a?:\w+:\d+|@[!&]?\d+|\#\d+)>
+        | https?://\S+
+        | @(?P<user>\w(?:[\w.]*\w)?)
+        | \#(?P<channel>[\w-]+)
+        | :(?P<emoji>\w{2,32}):
+        ",
+    )
+    .expect("valid mention pattern")
+});
+
+/// What to do with content over `MAX_MESSAGE_LENGTH`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    Split,
+    Attachment,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateKind {
+    User,
+    Channel,
+    Emoji,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AutocompleteCandidate {
+    pub kind: CandidateKind,
+    pub id: Snowflake,
+    // Name shown in the popup
+    pub label: String,
+    // Username for users whose label is a nickname or global name
+    pub detail: Option<String>,
+    // Encoded text to put in the composer, e.g. `<@id>`
+    pub insert: String,
+}
+
+/// Names that can be referenced from one channel: its guild's members,
+/// channels and emoji, or the recipients of a DM.
+struct Context<'a> {
+    cache: &'a DiscordCache,
+    guild: Option<&'a GuildCache>,
+    channel_id: Snowflake,
+}
+
+impl<'a> Context<'a> {
+    fn new(cache: &'a DiscordCache, channel_id: Snowflake) -> Self {
+        Self {
+            cache,
+            guild: cache.channel(channel_id).map(|(guild, _)| guild),
+            channel_id,
+        }
+    }
+
+    /// Users with their nickname in this guild, if any.
+    fn users(&self) -> Vec<(&'a User, Option<&'a str>)> {
+        let cache = self.cache;
+        match self.guild {
+            Some(guild) => guild
+                .members
+                .values()
+                .filter_map(|member| {
+                    let id = member.user_id()?;
+                    // Merged members from READY only carry the user id
+                    let user = member
+                        .user
+                        .as_ref()
+                        .filter(|u| !u.username.is_empty())
+                        .or_else(|| cache.users.get(&id))?;
+                    Some((user, member.nick.as_deref()))
+                })
+                .collect(),
+            None => cache
+                .private_channels
+                .get(&self.channel_id)
+                .into_iter()
+                .flat_map(|c| &c.recipients)
+                .chain(&cache.current_user)
+                .map(|user| (user, None))
+                .collect(),
+        }
+    }
+
+    fn channels(&self) -> Vec<&'a Channel> {
+        let Some(guild) = self.guild else {
+            return Vec::new();
+        };
+        let mut channels: Vec<&Channel> = guild
+            .channels
+            .values()
+            .filter(|c| c.kind != GUILD_CATEGORY && c.name.is_some())
+            .filter(|c| capabilities_for(self.cache, c.id).is_none_or(|caps| caps.view))
+            .collect();
+        // Channels win over threads of the same name
+        channels.sort_by_key(|c| (c.is_thread(), c.position));
+        channels
+    }
+
+    fn emojis(&self) -> Vec<(Snowflake, &'a Emoji)> {
+        self.guild
+            .into_iter()
+            .flat_map(|g| g.emojis.iter())
+            .filter(|(_, e)| e.available)
+            .map(|(id, e)| (*id, e))
+            .collect()
+    }
+
+    /// Usernames are unique; nicknames and global names only count when
+    /// exactly one user has them.
+    fn user_by_name(&self, name: &str) -> Option<&'a User> {
+        let users = self.users();
+        if let Some((user, _)) = users.iter().find(|(u, _)| same_name(&u.username, name)) {
+            return Some(user);
+        }
+        let mut matches = users.iter().filter(|(user, nick)| {
+            nick.is_some_and(|n| same_name(n, name))
+                || user
+                    .global_name
+                    .as_deref()
+                    .is_some_and(|g| same_name(g, name))
+        });
+        match (matches.next(), matches.next()) {
+            (Some((user, _)), None) => Some(user),
+            _ => None,
+        }
+    }
+
+    fn channel_by_name(&self, name: &str) -> Option<&'a Channel> {
+        self.channels()
+            .into_iter()
+            .find(|c| c.name.as_deref().is_some_and(|n| same_name(n, name)))
+    }
+
+    fn emoji_by_name(&self, name: &str) -> Option<(Snowflake, &'a Emoji)> {
+        let emojis = self.emojis();
+        emojis
+            .iter()
+            .find(|(_, e)| e.name.as_deref() == Some(name))
+            .or_else(|| {
+                emojis
+                    .iter()
+                    .find(|(_, e)| e.name.as_deref().is_some_and(|n| same_name(n, name)))
+            })
+            .copied()
+    }
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+fn encode_emoji(id: Snowflake, emoji: &Emoji) -> String {
+    format!(
+        "<{}:{}:{}>",
+        if emoji.animated { "a" } else { "" },
+        emoji.name.as_deref().unwrap_or_default(),
+        id
+    )
+}
+
+/// Splits content into `(is_code, text)` pieces so code spans and code
+/// blocks are sent as typed.
+fn split_code(content: &str) -> Vec<(bool, &str)> {
+    let mut pieces = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+    while let Some(offset) = content[i..].find('`') {
+        let start = i + offset;
+        let run = content[start..].bytes().take_while(|&b| b == b'`').count();
+        let fence = &content[start..start + run];
+        match content[start + run..].find(fence) {
+            Some(len) => {
+                let end = start + run + len + run;
+                if text_start < start {
+                    pieces.push((false, &content[text_start..start]));
+                }
+                pieces.push((true, &content[start..end]));
+                text_start = end;
+                i = end;
+            }
+            // An unclosed fence is plain text
+            None => i = start + run,
+        }
+    }
+    if text_start < content.len() {
+        pieces.push((false, &content[text_start..]));
+    }
+    pieces
+}
+
+fn encode_text<'t>(context: &Context, text: &'t str) -> Cow<'t, str> {
+    TOKEN.replace_all(text, |caps: &Captures| {
+        let whole = &caps[0];
+        let start = caps.get(0).map_or(0, |m| m.start());
+        // `bob@example.com` is not a mention
+        let after_word = text[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+        let encoded = if after_word {
+            None
+        } else if let Some(name) = caps.name("user") {
+            context
+                .user_by_name(name.as_str())
+                .map(|user| format!("<@{}>", user.id))
+        } else if let Some(name) = caps.name("channel") {
+            context
+                .channel_by_name(name.as_str())
+                .map(|channel| format!("<#{}>", channel.id))
+        } else if let Some(name) = caps.name("emoji") {
+            context
+                .emoji_by_name(name.as_str())
+                .map(|(id, emoji)| encode_emoji(id, emoji))
+        } else {
+            None
+        };
+        encoded.unwrap_or_else(|| whole.to_string())
+    })
+}
+
+/// Turns `@name`, `#channel` and `:emoji:` into Discord's `<@id>`, `<#id>`
+/// and `<:name:id>` forms. Names that do not resolve in the channel's
+/// context, and anything inside code, are left as typed.
+pub fn encode_mentions(cache: &DiscordCache, channel_id: Snowflake, content: &str) -> String {
+    let context = Context::new(cache, channel_id);
+    split_code(content)
+        .into_iter()
+        .map(|(code, text)| {
+            if code {
+                Cow::Borrowed(text)
+            } else {
+                encode_text(&context, text)
+            }
+        })
+        .collect()
+}
+
+fn char_len(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Cuts a line into pieces of at most `limit` characters, at whitespace
+/// where possible.
+fn wrap_line(line: &str, limit: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut rest = line;
+    while char_len(rest) > limit {
+        let hard_end = rest
+            .char_indices()
+            .nth(limit)
+            .map_or(rest.len(), |(i, _)| i);
+        let end = rest[..hard_end]
+            .rfind(char::is_whitespace)
+            .filter(|&i| i > 0)
+            .unwrap_or(hard_end);
+        pieces.push(rest[..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    if !rest.is_empty() {
+        pieces.push(rest.to_string());
+    }
+    pieces
+}
+
+/// Greedily joins pieces with `separator` into chunks of at most `limit`
+/// characters. Pieces must already fit on their own.
+fn pack(pieces: impl IntoIterator<Item = String>, separator: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for piece in pieces {
+        if !current.is_empty()
+            && char_len(&current) + char_len(separator) + char_len(&piece) > limit
+        {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str(separator);
+        }
+        current.push_str(&piece);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Paragraphs (separated by blank lines) and fenced code blocks, the latter
+/// flagged as code.
+fn blocks(content: &str) -> Vec<(bool, String)> {
+    let mut blocks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut in_code = false;
+    let flush = |current: &mut Vec<&str>, blocks: &mut Vec<(bool, String)>, code: bool| {
+        if !current.is_empty() {
+            blocks.push((code, current.join("\n")));
+            current.clear();
+        }
+    };
+
+    for line in content.split('\n') {
+        let trimmed = line.trim();
+        if in_code {
+            current.push(line);
+            if trimmed.ends_with("```") {
+                flush(&mut current, &mut blocks, true);
+                in_code = false;
+            }
+        } else if trimmed.starts_with("```") && !(trimmed.len() > 3 && trimmed.ends_with("```")) {
+            flush(&mut current, &mut blocks, false);
+            current.push(line);
+            in_code = true;
+        } else if trimmed.is_empty() {
+            flush(&mut current, &mut blocks, false);
+        } else {
+            current.push(line);
+        }
+    }
+    // An unclosed fence is sent as plain text
+    flush(&mut current, &mut blocks, false);
+    blocks
+}
+
+/// Splits a code block that does not fit into a message into several,
+/// each fenced with the original opening line.
+fn split_code_block(block: &str, limit: usize) -> Vec<String> {
+    let mut lines = block.lines();
+    let opening = lines.next().unwrap_or("```").trim();
+    let body: Vec<&str> = lines.collect();
+    let body = match body.split_last() {
+        Some((last, rest)) if last.trim() == "```" => rest,
+        _ => body.as_slice(),
+    };
+    // Room left for the body once the fences and their newlines are added
+    let room = limit.saturating_sub(char_len(opening) + 5).max(1);
+    let pieces = body.iter().flat_map(|line| {
+        if char_len(line) > room {
+            wrap_line(line, room)
+        } else {
+            vec![line.to_string()]
+        }
+    });
+    pack(pieces, "\n", room)
+        .into_iter()
+        .map(|chunk| format!("{}\n{}\n```", opening, chunk))
+        .collect()
+}
+
+/// Splits content into messages of at most `limit` characters. Breaks go
+/// between paragraphs and code blocks where possible, then between lines;
+/// code blocks that are too long are split by line and fenced again.
+pub fn split_content(content: &str, limit: usize) -> Vec<String> {
+    if char_len(content) <= limit {
+        return vec![content.to_string()];
+    }
+    let pieces = blocks(content).into_iter().flat_map(|(code, block)| {
+        if char_len(&block) <= limit {
+            vec![block]
+        } else if code {
+            split_code_block(&block, limit)
+        } else {
+            let lines = block.lines().flat_map(|line| wrap_line(line, limit));
+            pack(lines, "\n", limit)
+        }
+    });
+    pack(pieces, "\n\n", limit)
+}
+
+fn starts_with_ignore_case(name: &str, prefix: &str) -> bool {
+    name.to_lowercase().starts_with(prefix)
+}
+
+fn complete_users(context: &Context, prefix: &str) -> Vec<AutocompleteCandidate> {
+    context
+        .users()
+        .into_iter()
+        .filter(|(user, nick)| {
+            [
+                Some(user.username.as_str()),
+                user.global_name.as_deref(),
+                *nick,
+            ]
+            .into_iter()
+            .flatten()
+            .any(|name| starts_with_ignore_case(name, prefix))
+        })
+        .map(|(user, nick)| {
+            let label = nick
+                .or(user.global_name.as_deref())
+                .unwrap_or(&user.username)
+                .to_string();
+            AutocompleteCandidate {
+                kind: CandidateKind::User,
+                id: user.id,
+                detail: (label != user.username).then(|| user.username.clone()),
+                label,
+                insert: format!("<@{}>", user.id),
+            }
+        })
+        .collect()
+}
+
+fn complete_channels(context: &Context, prefix: &str) -> Vec<AutocompleteCandidate> {
+    context
+        .channels()
+        .into_iter()
+        .filter_map(|channel| {
+            let name = channel.name.as_deref()?;
+            starts_with_ignore_case(name, prefix).then(|| AutocompleteCandidate {
+                kind: CandidateKind::Channel,
+                id: channel.id,
+                label: name.to_string(),
+                detail: None,
+                insert: format!("<#{}>", channel.id),
+            })
+        })
+        .collect()
+}
+
+fn complete_emojis(context: &Context, prefix: &str) -> Vec<AutocompleteCandidate> {
+    context
+        .emojis()
+        .into_iter()
+        .filter_map(|(id, emoji)| {
+            let name = emoji.name.as_deref()?;
+            starts_with_ignore_case(name, prefix).then(|| AutocompleteCandidate {
+                kind: CandidateKind::Emoji,
+                id,
+                label: name.to_string(),
+                detail: None,
+                insert: encode_emoji(id, emoji),
+            })
+        })
+        .collect()
+}
+
+/// Completion candidates for the word being typed in a channel's composer:
+/// `@ali` for members, `#gen` for channels, `:pa` for custom emoji. Emoji
+/// need at least two characters, like in the official client.
+pub fn autocomplete(
+    cache: &DiscordCache,
+    channel_id: Snowflake,
+    query: &str,
+) -> Vec<AutocompleteCandidate> {
+    let context = Context::new(cache, channel_id);
+    let mut chars = query.chars();
+    let trigger = chars.next();
+    let prefix = chars.as_str().to_lowercase();
+
+    let mut candidates = match trigger {
+        Some('@') => complete_users(&context, &prefix),
+        Some('#') => complete_channels(&context, &prefix),
+        Some(':') if prefix.chars().count() >= 2 => complete_emojis(&context, &prefix),
+        _ => Vec::new(),
+    };
+
+    // Labels that themselves start with the prefix come first
+    candidates.sort_by_cached_key(|c| {
+        let label = c.label.to_lowercase();
+        (!label.starts_with(&prefix), label)
+    });
+    candidates.truncate(AUTOCOMPLETE_LIMIT);
+    candidates
+}
+
+#[tauri::command]
+pub async fn fetch_autocomplete(
+    state: State<'_, AppState>,
+    channel_id: Snowflake,
+    query: String,
+) -> Result<Vec<AutocompleteCandidate>, String> {
+    Ok(autocomplete(&*state.cache.lock().await, channel_id, &query))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const CHANNEL: Snowflake = Snowflake(10);
+
+    fn cache() -> DiscordCache {
+        let mut cache = DiscordCache::default();
+        cache.apply_user_update(&json!({ "id": "2", "username": "me" }));
+        cache.apply_guild_create(&json!({
+            "id": "1",
+            "owner_id": "2",
+            "roles": [{ "id": "1", "permissions": "1024" }],
+            "channels": [
+                { "id": "10", "type": 0, "name": "general" },
+                { "id": "11", "type": 0, "name": "random" },
+            ],
+            "emojis": [{ "id": "30", "name": "party" }],
+            "members": [
+                { "user": { "id": "2", "username": "me" }, "roles": [] },
+                { "user": { "id": "20", "username": "ann" }, "nick": "Annie", "roles": [] },
+                { "user": { "id": "21", "username": "sam1", "global_name": "Sam" }, "roles": [] },
+                { "user": { "id": "22", "username": "sam2", "global_name": "Sam" }, "roles": [] },
+            ],
+        }));
+        cache
+    }
+
+    fn encode(content: &str) -> String {
+        encode_mentions(&cache(), CHANNEL, content)
+    }
+
+    #[test]
+    fn encodes_names() {
+        assert_eq!(
+            encode("hi @ann and @Annie in #random :party:"),
+            "hi <@20> and <@20> in <#11> <:party:30>"
+        );
+        assert_eq!(
+            encode("@nobody in #nowhere :nope:"),
+            "@nobody in #nowhere :nope:"
+        );
+    }
+
+    #[test]
+    fn leaves_code_as_typed() {
+        assert_eq!(encode("@ann `@ann`"), "<@20> `@ann`");
+        let block = "```\n@ann #general\n```";
+        assert_eq!(encode(block), block);
+        // An unclosed fence does not hide what follows
+        assert_eq!(encode("`` @ann"), "`` <@20>");
+    }
+
+    #[test]
+    fn leaves_emails_urls_and_encoded_mentions() {
+        assert_eq!(encode("mail ann@example.com"), "mail ann@example.com");
+        assert_eq!(
+            encode("see https://example.com/@ann"),
+            "see https://example.com/@ann"
+        );
+        assert_eq!(encode("<@20> <#11>"), "<@20> <#11>");
+    }
+
+    #[test]
+    fn ambiguous_names_need_the_username() {
+        assert_eq!(encode("@Sam"), "@Sam");
+        assert_eq!(encode("@sam1"), "<@21>");
+    }
+
+    #[test]
+    fn short_content_is_one_message() {
+        assert_eq!(split_content("hello", 5), vec!["hello"]);
+    }
+
+    #[test]
+    fn splits_between_paragraphs_then_words() {
+        assert_eq!(split_content("aaaa\n\nbbbb", 6), vec!["aaaa", "bbbb"]);
+        assert_eq!(split_content("one two three", 8), vec!["one two", "three"]);
+        // Limits count characters, not bytes
+        assert_eq!(split_content("ééé ééé", 3), vec!["ééé", "ééé"]);
+    }
+
+    #[test]
+    fn splits_code_blocks_by_line_and_fences_them_again() {
+        let block = "```rs\nlet a = 1;\nlet b = 2;\n```";
+        assert_eq!(
+            split_content(block, 25),
+            vec!["```rs\nlet a = 1;\n```", "```rs\nlet b = 2;\n```"]
+        );
+    }
+
+    #[test]
+    fn wraps_long_code_lines() {
+        let chunks = split_code_block("```\nabcdefghij\n```", 12);
+        assert_eq!(
+            chunks,
+            vec!["```\nabcd\n```", "```\nefgh\n```", "```\nij\n```"]
+        );
+        assert!(chunks.iter().all(|c| char_len(c) <= 12));
+    }
+}
@@ -0,0 +1,116 @@
+use std::{path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use tracing::{error, info};
+
+use crate::AppState;
+
+const DISCORD_GATEWAY_URL: &str = "wss://gateway.discord.gg";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the client talks to: Discord by default, or a relay, a
+/// Discord-compatible server or a local mock. Read once at startup from
+/// `endpoints.json` in the app data directory; missing fields keep their
+/// defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    // REST base without the version, e.g. https://discord.com/api
+    pub api_base: String,
+    pub api_version: u8,
+    // Used until READY hands out a resume URL
+    pub gateway_url: String,
+    pub remote_auth_url: String,
+    pub cdn_url: String,
+    pub user_agent: String,
+    // Shared by every request; rebuilt once the config is read
+    #[serde(skip)]
+    client: reqwest::Client,
+}
+
+fn build_client(user_agent: &str) -> reqwest::Client {
+    reqwest::Client::builder()
+        .user_agent(user_agent)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_else(|e| {
+            error!("Failed to build HTTP client: {}", e);
+            reqwest::Client::new()
+        })
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        let user_agent = concat!("Messagify/", env!("CARGO_PKG_VERSION"));
+        Self {
+            api_base: "https://discord.com/api".to_string(),
+            api_version: 10,
+            gateway_url: DISCORD_GATEWAY_URL.to_string(),
+            remote_auth_url: "wss://remote-auth-gateway.discord.gg/?v=2".to_string(),
+            cdn_url: "https://cdn.discordapp.com".to_string(),
+            user_agent: user_agent.to_string(),
+            client: build_client(user_agent),
+        }
+    }
+}
+
+impl Endpoints {
+    pub async fn load(path: PathBuf) -> Self {
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => match serde_json::from_str::<Self>(&content) {
+                Ok(mut endpoints) => {
+                    info!("Using custom endpoints: {}", endpoints.api_base);
+                    endpoints.client = build_client(&endpoints.user_agent);
+                    endpoints
+                }
+                Err(e) => {
+                    error!("Failed to parse endpoints: {}", e);
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Full REST URL for a path like `/users/@me`.
+    pub fn api(&self, path: &str) -> String {
+        format!(
+            "{}/v{}{}",
+            self.api_base.trim_end_matches('/'),
+            self.api_version,
+            path
+        )
+    }
+
+    /// Whether `gateway_url` points somewhere other than Discord. Resume URLs
+    /// handed out by READY are then ignored, as they would lead back to
+    /// Discord.
+    pub fn is_custom_gateway(&self) -> bool {
+        self.gateway_url != DISCORD_GATEWAY_URL
+    }
+
+    /// Gateway URL with the version and encoding query, for `gateway_url` or
+    /// a resume URL.
+    pub fn gateway(&self, base: Option<&str>) -> String {
+        format!(
+            "{}/?v={}&encoding=json",
+            base.unwrap_or(&self.gateway_url).trim_end_matches('/'),
+            self.api_version
+        )
+    }
+
+    /// The shared HTTP client, sending the configured user agent. Cheap to
+    /// clone; clones share one connection pool.
+    pub fn http(&self) -> reqwest::Client {
+        self.client.clone()
+    }
+}
+
+/// The UI builds avatar and icon URLs from `cdn_url`.
+#[tauri::command]
+pub async fn get_endpoints(state: State<'_, AppState>) -> Result<Endpoints, String> {
+    Ok(state.endpoints.clone())
+}
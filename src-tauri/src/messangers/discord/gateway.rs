@@ -4,26 +4,48 @@ use flate2::read::ZlibDecoder;
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
 use tokio::{
     sync::{Mutex, mpsc},
     time::{Duration, interval},
 };
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Message, protocol::CloseFrame},
+};
 use tracing::{debug, error, info, warn};
 
-const GATEWAY_URL: &str = "wss://gateway.discord.gg/?v=10&encoding=json";
+use super::{
+    account::refresh_profile,
+    auth::sign_out,
+    endpoints::Endpoints,
+    history::start_backfill,
+    mentions::{attach_cached, attach_resolved},
+    notifications::Notification,
+    recorder::{Direction, RecordedFrame},
+    slowmode::{SlowmodeStatus, start_cooldown},
+    snowflake::Snowflake,
+};
+use crate::AppState;
 
 // Gateway opcodes
 const OP_DISPATCH: u8 = 0;
 const OP_HEARTBEAT: u8 = 1;
 const OP_IDENTIFY: u8 = 2;
-#[allow(dead_code)]
 const OP_RESUME: u8 = 6;
 const OP_RECONNECT: u8 = 7;
 const OP_INVALID_SESSION: u8 = 9;
 const OP_HELLO: u8 = 10;
 const OP_HEARTBEAT_ACK: u8 = 11;
+// Lazy guild subscriptions; user accounts get the member list through this
+pub const OP_GUILD_SUBSCRIPTIONS: u8 = 14;
+
+// Close codes after which reconnecting cannot help
+// https://discord.com/developers/docs/topics/opcodes-and-status-codes#gateway-gateway-close-event-codes
+const FATAL_CLOSE_CODES: [u16; 5] = [4010, 4011, 4012, 4013, 4014];
+// The token was rejected, see `auth::sign_out`
+const CLOSE_AUTHENTICATION_FAILED: u16 = 4004;
+const MAX_RECONNECT_DELAY_SECS: u64 = 30;
 
 #[derive(Debug, Deserialize)]
 struct GatewayPayload {
@@ -57,14 +79,35 @@ pub enum GatewayEvent {
     MessageCreate(Value),
     MessageUpdate(Value),
     MessageDelete(Value),
+    // New `resolved` of a message after its unknown mentions and links were
+    // looked up; carries `id`, `channel_id` and `resolved`
+    MessageResolved(Value),
+    // Local echo of an unsent message, with `id` set to its nonce. Sent again
+    // whenever its `send_state` or `retry_at` changes
+    MessagePending(Value),
+    // Local echo of a send Discord rejected; retry with `retry_message`
+    MessageSendFailed(Value),
     Ready(Value),
+    // Guild id whose member list changed; refetch with `fetch_guild_members`
+    GuildMembersUpdate(Snowflake),
+    // Guild id whose channels changed; refetch with `fetch_guild_channel_tree`
+    ChannelTreeUpdate(Snowflake),
+    // Channel id whose read state changed; refetch with `fetch_unread_summary`
+    UnreadUpdate(Snowflake),
+    // Our slowmode cooldown in a channel started
+    SlowmodeUpdate(SlowmodeStatus),
+    // A message that passed the notification rules
+    Notify(Notification),
     GatewayError(String),
+    // Discord no longer accepts the token; carries the reason
+    SignedOut(String),
     Connected,
     Disconnected,
 }
 
 pub struct GatewayClient {
     shutdown_tx: Option<mpsc::Sender<()>>,
+    outbound_tx: Option<mpsc::Sender<Value>>,
     is_connected: Arc<Mutex<bool>>,
 }
 
@@ -72,6 +115,7 @@ impl GatewayClient {
     pub fn new() -> Self {
         Self {
             shutdown_tx: None,
+            outbound_tx: None,
             is_connected: Arc::new(Mutex::new(false)),
         }
     }
@@ -80,7 +124,11 @@ impl GatewayClient {
         *self.is_connected.lock().await
     }
 
-    pub async fn connect(&mut self, token: String, app_handle: AppHandle) -> Result<(), String> {
+    pub async fn connect<R: Runtime>(
+        &mut self,
+        token: String,
+        app_handle: AppHandle<R>,
+    ) -> Result<(), String> {
         if self.is_connected().await {
             info!("Gateway already connected, disconnecting first");
             self.disconnect().await;
@@ -89,11 +137,21 @@ impl GatewayClient {
         let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>(1);
         self.shutdown_tx = Some(shutdown_tx);
 
+        let (outbound_tx, outbound_rx) = mpsc::channel::<Value>(16);
+        self.outbound_tx = Some(outbound_tx);
+
         let is_connected = self.is_connected.clone();
 
         // Spawn the Gateway connection task
         tokio::spawn(async move {
-            if let Err(e) = run_gateway(token, app_handle.clone(), shutdown_rx, is_connected).await
+            if let Err(e) = run_gateway(
+                token,
+                app_handle.clone(),
+                shutdown_rx,
+                outbound_rx,
+                is_connected,
+            )
+            .await
             {
                 error!("Gateway error: {}", e);
                 let _ = app_handle.emit("discord-gateway", GatewayEvent::GatewayError(e));
@@ -103,10 +161,22 @@ impl GatewayClient {
         Ok(())
     }
 
+    /// Queues a raw payload (e.g. op 8) to be written to the socket.
+    pub async fn send(&self, payload: Value) -> Result<(), String> {
+        let tx = self
+            .outbound_tx
+            .as_ref()
+            .ok_or_else(|| "Gateway not connected".to_string())?;
+        tx.send(payload)
+            .await
+            .map_err(|_| "Gateway connection closed".to_string())
+    }
+
     pub async fn disconnect(&mut self) {
         if let Some(tx) = self.shutdown_tx.take() {
             let _ = tx.send(()).await;
         }
+        self.outbound_tx = None;
         *self.is_connected.lock().await = false;
     }
 }
@@ -117,32 +187,165 @@ impl Default for GatewayClient {
     }
 }
 
-async fn run_gateway(
+/// Resume state carried from one connection to the next.
+#[derive(Debug, Default)]
+struct Session {
+    id: Option<String>,
+    resume_url: Option<String>,
+    sequence: Option<u64>,
+    // Failed connections in a row, for the reconnect backoff
+    failures: u32,
+}
+
+impl Session {
+    fn can_resume(&self) -> bool {
+        self.id.is_some() && self.sequence.is_some()
+    }
+
+    /// Where to connect next. A custom gateway is also used for resumes.
+    fn url(&self, endpoints: &Endpoints) -> String {
+        match &self.resume_url {
+            Some(url) if self.can_resume() && !endpoints.is_custom_gateway() => {
+                endpoints.gateway(Some(url))
+            }
+            _ => endpoints.gateway(None),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.id = None;
+        self.resume_url = None;
+        self.sequence = None;
+    }
+}
+
+/// Why a connection ended.
+enum ConnectionEnd {
+    Shutdown,
+    Reconnect,
+    Fatal(String),
+    Unauthorized,
+}
+
+/// Where a payload comes from. Replayed payloads only update the cache and
+/// reach the UI: nothing is saved, sent or fetched for them, and no
+/// notifications fire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Live,
+    Replay,
+}
+
+/// What the event loop does after handling a payload.
+enum Flow {
+    Continue,
+    HeartbeatNow,
+    Reconnect,
+}
+
+enum HeartbeatSignal {
+    Beat,
+    // The last heartbeat was never acknowledged
+    Zombie,
+}
+
+async fn run_gateway<R: Runtime>(
     token: String,
-    app_handle: AppHandle,
+    app_handle: AppHandle<R>,
     mut shutdown_rx: mpsc::Receiver<()>,
+    mut outbound_rx: mpsc::Receiver<Value>,
     is_connected: Arc<Mutex<bool>>,
 ) -> Result<(), String> {
+    let mut session = Session::default();
+
+    loop {
+        let end = run_connection(
+            &token,
+            &app_handle,
+            &mut shutdown_rx,
+            &mut outbound_rx,
+            &is_connected,
+            &mut session,
+        )
+        .await;
+
+        *is_connected.lock().await = false;
+        let _ = app_handle.emit("discord-gateway", GatewayEvent::Disconnected);
+
+        match end {
+            ConnectionEnd::Shutdown => {
+                info!("Gateway disconnected");
+                return Ok(());
+            }
+            ConnectionEnd::Fatal(e) => return Err(e),
+            ConnectionEnd::Unauthorized => {
+                sign_out(&app_handle, "gateway_closed_4004").await;
+                return Ok(());
+            }
+            ConnectionEnd::Reconnect => {}
+        }
+
+        session.failures += 1;
+        let delay =
+            Duration::from_secs((1u64 << session.failures.min(5)).min(MAX_RECONNECT_DELAY_SECS));
+        info!("Reconnecting to Discord Gateway in {:?}", delay);
+        tokio::select! {
+            Some(_) = shutdown_rx.recv() => {
+                info!("Gateway shutdown requested");
+                return Ok(());
+            }
+            _ = tokio::time::sleep(delay) => {}
+        }
+    }
+}
+
+fn close_end(frame: Option<CloseFrame>) -> ConnectionEnd {
+    warn!("Gateway closed: {:?}", frame);
+    match frame.map(|f| u16::from(f.code)) {
+        Some(CLOSE_AUTHENTICATION_FAILED) => ConnectionEnd::Unauthorized,
+        Some(code) if FATAL_CLOSE_CODES.contains(&code) => {
+            ConnectionEnd::Fatal(format!("gateway_closed_{}", code))
+        }
+        _ => ConnectionEnd::Reconnect,
+    }
+}
+
+/// Runs one WebSocket connection: IDENTIFY or RESUME, then the event loop.
+async fn run_connection<R: Runtime>(
+    token: &str,
+    app_handle: &AppHandle<R>,
+    shutdown_rx: &mut mpsc::Receiver<()>,
+    outbound_rx: &mut mpsc::Receiver<Value>,
+    is_connected: &Arc<Mutex<bool>>,
+    session: &mut Session,
+) -> ConnectionEnd {
     info!("Connecting to Discord Gateway...");
 
-    let (ws_stream, _) = connect_async(GATEWAY_URL)
-        .await
-        .map_err(|e| format!("WebSocket connection failed: {}", e))?;
+    let url = session.url(&app_handle.state::<AppState>().endpoints);
+    let ws_stream = match connect_async(url).await {
+        Ok((ws_stream, _)) => ws_stream,
+        Err(e) => {
+            error!("WebSocket connection failed: {}", e);
+            return ConnectionEnd::Reconnect;
+        }
+    };
 
     let (mut write, mut read) = ws_stream.split();
 
     *is_connected.lock().await = true;
     let _ = app_handle.emit("discord-gateway", GatewayEvent::Connected);
+    // Messages queued while offline can go out now
+    app_handle.state::<AppState>().outbox.lock().await.resume();
     info!("Connected to Discord Gateway");
 
     let mut heartbeat_interval: Option<u64> = None;
-    let mut last_sequence: Option<u64> = None;
     let heartbeat_ack_received = Arc::new(Mutex::new(true));
 
     // Read the first message (should be HELLO)
     if let Some(msg) = read.next().await {
         match msg {
             Ok(Message::Text(text)) => {
+                record(app_handle, Direction::Inbound, &text).await;
                 if let Ok(payload) = serde_json::from_str::<GatewayPayload>(&text) {
                     if payload.op == OP_HELLO {
                         if let Some(d) = payload.d {
@@ -155,41 +358,55 @@ async fn run_gateway(
                     }
                 }
             }
-            Ok(Message::Close(_)) => {
-                return Err("Connection closed immediately".to_string());
-            }
+            Ok(Message::Close(frame)) => return close_end(frame),
             Err(e) => {
-                return Err(format!("Error reading HELLO: {}", e));
+                error!("Error reading HELLO: {}", e);
+                return ConnectionEnd::Reconnect;
             }
             _ => {}
         }
     }
 
-    // Send IDENTIFY
-    let identify = json!({
-        "op": OP_IDENTIFY,
-        "d": {
-            "token": token,
-            "properties": {
-                "os": "windows",
-                "browser": "messagify",
-                "device": "messagify"
-            },
-            "intents": 1 << 12 | 1 << 9 | 1 << 15 // GUILDS | DIRECT_MESSAGES | MESSAGE_CONTENT
-        }
-    });
+    let hello_reply = if session.can_resume() {
+        info!("Resuming session");
+        json!({
+            "op": OP_RESUME,
+            "d": {
+                "token": token,
+                "session_id": session.id,
+                "seq": session.sequence
+            }
+        })
+    } else {
+        json!({
+            "op": OP_IDENTIFY,
+            "d": {
+                "token": token,
+                "properties": {
+                    "os": "windows",
+                    "browser": "messagify",
+                    "device": "messagify"
+                },
+                "intents": 1 << 12 | 1 << 9 | 1 << 15 // GUILDS | DIRECT_MESSAGES | MESSAGE_CONTENT
+            }
+        })
+    };
 
-    write
-        .send(Message::Text(identify.to_string().into()))
+    if let Err(e) = write
+        .send(Message::Text(hello_reply.to_string().into()))
         .await
-        .map_err(|e| format!("Failed to send IDENTIFY: {}", e))?;
+    {
+        error!("Failed to send IDENTIFY/RESUME: {}", e);
+        return ConnectionEnd::Reconnect;
+    }
+    record(app_handle, Direction::Outbound, &hello_reply.to_string()).await;
 
-    info!("Sent IDENTIFY payload");
+    info!("Sent op {} payload", hello_reply["op"]);
 
     // Start heartbeat task
     let heartbeat_interval_ms = heartbeat_interval.unwrap_or(41250);
     let heartbeat_ack = heartbeat_ack_received.clone();
-    let (heartbeat_tx, mut heartbeat_rx) = mpsc::channel::<Value>(16);
+    let (heartbeat_tx, mut heartbeat_rx) = mpsc::channel::<HeartbeatSignal>(16);
 
     tokio::spawn(async move {
         let mut ticker = interval(Duration::from_millis(heartbeat_interval_ms));
@@ -197,20 +414,17 @@ async fn run_gateway(
             ticker.tick().await;
 
             // Check if we received ACK for the last heartbeat
-            {
+            let signal = {
                 let mut ack = heartbeat_ack.lock().await;
-                if !*ack {
-                    warn!("No heartbeat ACK received, connection may be zombied");
+                if *ack {
+                    *ack = false;
+                    HeartbeatSignal::Beat
+                } else {
+                    HeartbeatSignal::Zombie
                 }
-                *ack = false;
-            }
-
-            let heartbeat = json!({
-                "op": OP_HEARTBEAT,
-                "d": null // TODO: send last_sequence
-            });
+            };
 
-            if heartbeat_tx.send(heartbeat).await.is_err() {
+            if heartbeat_tx.send(signal).await.is_err() {
                 break;
             }
         }
@@ -218,87 +432,185 @@ async fn run_gateway(
 
     // Main event loop
     loop {
-        tokio::select! {
+        let flow = tokio::select! {
             // Check for shutdown signal
             Some(_) = shutdown_rx.recv() => {
                 info!("Gateway shutdown requested");
                 let _ = write.send(Message::Close(None)).await;
-                break;
+                return ConnectionEnd::Shutdown;
             }
 
             // Send heartbeats
-            Some(heartbeat) = heartbeat_rx.recv() => {
-                if let Err(e) = write.send(Message::Text(heartbeat.to_string().into())).await {
-                    error!("Failed to send heartbeat: {}", e);
-                    break;
+            Some(signal) = heartbeat_rx.recv() => match signal {
+                HeartbeatSignal::Beat => Flow::HeartbeatNow,
+                HeartbeatSignal::Zombie => {
+                    warn!("No heartbeat ACK received, reconnecting");
+                    Flow::Reconnect
                 }
-                debug!("Sent heartbeat");
+            },
+
+            // Send payloads queued through GatewayClient::send
+            Some(payload) = outbound_rx.recv() => {
+                if let Err(e) = write.send(Message::Text(payload.to_string().into())).await {
+                    error!("Failed to send payload: {}", e);
+                    return ConnectionEnd::Reconnect;
+                }
+                record(app_handle, Direction::Outbound, &payload.to_string()).await;
+                debug!("Sent op {}", payload["op"]);
+                Flow::Continue
             }
 
             // Read messages
             msg = read.next() => {
-                match msg {
-                    Some(Ok(Message::Text(text))) => {
-                        if let Err(e) = handle_message(&text, &app_handle, &mut last_sequence, &heartbeat_ack_received).await {
-                            error!("Error handling message: {}", e);
-                        }
-                    }
-                    Some(Ok(Message::Close(frame))) => {
-                        warn!("Gateway closed: {:?}", frame);
-                        break;
-                    }
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => Some(text.to_string()),
                     Some(Ok(Message::Binary(data))) => {
                         // Handle zlib-compressed messages if needed
-                        if let Ok(text) = decompress_zlib(&data) {
-                            if let Err(e) = handle_message(&text, &app_handle, &mut last_sequence, &heartbeat_ack_received).await {
-                                error!("Error handling compressed message: {}", e);
-                            }
-                        }
+                        decompress_zlib(&data).ok()
                     }
+                    Some(Ok(Message::Close(frame))) => return close_end(frame),
                     Some(Err(e)) => {
                         error!("WebSocket error: {}", e);
-                        break;
+                        return ConnectionEnd::Reconnect;
                     }
                     None => {
                         info!("WebSocket stream ended");
-                        break;
+                        return ConnectionEnd::Reconnect;
                     }
-                    _ => {}
+                    _ => None,
+                };
+                match text {
+                    Some(text) => {
+                        record(app_handle, Direction::Inbound, &text).await;
+                        handle_message(
+                            &text,
+                            app_handle,
+                            session,
+                            &heartbeat_ack_received,
+                            Source::Live,
+                        )
+                        .await
+                        .unwrap_or_else(|e| {
+                            error!("Error handling message: {}", e);
+                            Flow::Continue
+                        })
+                    }
+                    None => Flow::Continue,
                 }
             }
+        };
+
+        match flow {
+            Flow::Continue => {}
+            Flow::HeartbeatNow => {
+                let heartbeat = json!({
+                    "op": OP_HEARTBEAT,
+                    "d": session.sequence
+                });
+                if let Err(e) = write
+                    .send(Message::Text(heartbeat.to_string().into()))
+                    .await
+                {
+                    error!("Failed to send heartbeat: {}", e);
+                    return ConnectionEnd::Reconnect;
+                }
+                record(app_handle, Direction::Outbound, &heartbeat.to_string()).await;
+                debug!("Sent heartbeat");
+            }
+            // Dropping the socket without a close frame keeps the session
+            // resumable
+            Flow::Reconnect => return ConnectionEnd::Reconnect,
         }
     }
+}
 
-    *is_connected.lock().await = false;
-    let _ = app_handle.emit("discord-gateway", GatewayEvent::Disconnected);
-    info!("Gateway disconnected");
+async fn record<R: Runtime>(app_handle: &AppHandle<R>, direction: Direction, text: &str) {
+    let state = app_handle.state::<AppState>();
+    state.recorder.lock().await.record(direction, text).await;
+}
 
-    Ok(())
+/// Feeds what Discord sent in a recording to `handle_message` as if it came
+/// from a live socket, minus the side effects a `Source::Replay` skips. With
+/// `realtime` the recorded gaps between frames are kept. Returns how many
+/// frames were handled.
+pub async fn replay<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    frames: Vec<RecordedFrame>,
+    realtime: bool,
+) -> usize {
+    let mut session = Session::default();
+    let heartbeat_ack = Arc::new(Mutex::new(true));
+    let mut previous_at: Option<i64> = None;
+    let mut replayed = 0;
+
+    for frame in frames
+        .into_iter()
+        .filter(|frame| frame.direction == Direction::Inbound)
+    {
+        let gap = previous_at
+            .filter(|_| realtime)
+            .map(|previous| (frame.at_ms - previous).max(0) as u64);
+        previous_at = Some(frame.at_ms);
+        if let Some(gap) = gap {
+            tokio::time::sleep(Duration::from_millis(gap)).await;
+        }
+
+        // Reconnects and heartbeats have no socket to act on
+        let text = frame.payload.to_string();
+        match handle_message(
+            &text,
+            app_handle,
+            &mut session,
+            &heartbeat_ack,
+            Source::Replay,
+        )
+        .await
+        {
+            Ok(_) => replayed += 1,
+            Err(e) => warn!("Skipping recorded frame: {}", e),
+        }
+    }
+
+    info!("Replayed {} frames", replayed);
+    replayed
 }
 
-async fn handle_message(
+async fn handle_message<R: Runtime>(
     text: &str,
-    app_handle: &AppHandle,
-    last_sequence: &mut Option<u64>,
+    app_handle: &AppHandle<R>,
+    session: &mut Session,
     heartbeat_ack: &Arc<Mutex<bool>>,
-) -> Result<(), String> {
+    source: Source,
+) -> Result<Flow, String> {
     let payload: GatewayPayload =
         serde_json::from_str(text).map_err(|e| format!("Failed to parse payload: {}", e))?;
     // Update sequence number
     if let Some(s) = payload.s {
-        *last_sequence = Some(s);
+        session.sequence = Some(s);
     }
 
     match payload.op {
         OP_DISPATCH => {
             if let (Some(event_type), Some(data)) = (payload.t.as_deref(), payload.d) {
-                
-                handle_dispatch_event(event_type, data, app_handle).await?;
+                match event_type {
+                    "READY" => {
+                        session.id = data["session_id"].as_str().map(str::to_string);
+                        session.resume_url =
+                            data["resume_gateway_url"].as_str().map(str::to_string);
+                        session.failures = 0;
+                    }
+                    "RESUMED" => {
+                        info!("Session resumed");
+                        session.failures = 0;
+                    }
+                    _ => {}
+                }
+                handle_dispatch_event(event_type, data, app_handle, source).await?;
             }
         }
         OP_HEARTBEAT => {
             debug!("Server requested heartbeat");
-            // The heartbeat will be sent by the heartbeat task
+            return Ok(Flow::HeartbeatNow);
         }
         OP_HEARTBEAT_ACK => {
             debug!("Received heartbeat ACK");
@@ -306,42 +618,219 @@ async fn handle_message(
         }
         OP_RECONNECT => {
             warn!("Server requested reconnect");
-            return Err("Reconnect requested".to_string());
+            return Ok(Flow::Reconnect);
         }
         OP_INVALID_SESSION => {
-            error!("Invalid session");
-            return Err("Invalid session".to_string());
+            // `d` says whether the session can still be resumed
+            let resumable = payload.d.as_ref().and_then(Value::as_bool).unwrap_or(false);
+            warn!("Invalid session (resumable: {})", resumable);
+            if !resumable {
+                session.reset();
+            }
+            return Ok(Flow::Reconnect);
         }
         _ => {
             debug!("Received opcode: {}", payload.op);
         }
     }
 
-    Ok(())
+    Ok(Flow::Continue)
 }
 
-async fn handle_dispatch_event(
+async fn handle_dispatch_event<R: Runtime>(
     event_type: &str,
     data: Value,
-    app_handle: &AppHandle,
+    app_handle: &AppHandle<R>,
+    source: Source,
 ) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    let live = source == Source::Live;
+
     let event = match event_type {
         "READY" => {
             info!("Gateway READY");
-            Some(GatewayEvent::Ready(data))
+            state.cache.lock().await.apply_ready(&data);
+            if live {
+                refresh_profile(&state, &data["user"]).await;
+            }
+            emit(app_handle, GatewayEvent::Ready(data))?;
+            // Without a resume, events since the last connection are lost
+            if live {
+                start_backfill(app_handle).await;
+            }
+            None
+        }
+        "USER_UPDATE" => {
+            state.cache.lock().await.apply_user_update(&data);
+            if live {
+                refresh_profile(&state, &data).await;
+            }
+            None
         }
         "MESSAGE_CREATE" => {
             debug!("MESSAGE_CREATE: channel_id={}", data["channel_id"]);
-            Some(GatewayEvent::MessageCreate(data))
+            let (unread_channel, notification, own_message) = {
+                let mut cache = state.cache.lock().await;
+                let unread_channel = cache.apply_message_create(&data);
+                let notification = if live {
+                    state.notifications.lock().await.evaluate(&data, &cache)
+                } else {
+                    None
+                };
+                let own_message = cache
+                    .current_user
+                    .as_ref()
+                    .is_some_and(|me| Snowflake::from_value(&data["author"]["id"]) == Some(me.id));
+                (unread_channel, notification, own_message)
+            };
+            // Messages we sent from other sessions count towards slowmode too
+            let own_channel = Snowflake::from_value(&data["channel_id"]).filter(|_| own_message);
+            if let Some(channel_id) = own_channel.filter(|_| live) {
+                start_cooldown(app_handle, channel_id).await?;
+            }
+            if let Some(channel_id) = unread_channel {
+                emit(app_handle, GatewayEvent::UnreadUpdate(channel_id))?;
+            }
+            if let Some(notification) = notification {
+                emit(app_handle, GatewayEvent::Notify(notification))?;
+            }
+            let mut data = data;
+            if !live {
+                attach_cached(&state, std::slice::from_mut(&mut data)).await;
+                return emit(app_handle, GatewayEvent::MessageCreate(data));
+            }
+            // Our own sends may already have been confirmed by the REST response
+            let first = match data["nonce"].as_str() {
+                Some(nonce) => state.outbox.lock().await.confirm(nonce).await,
+                None => true,
+            };
+            if first {
+                attach_resolved(app_handle, std::slice::from_mut(&mut data)).await;
+                state.history.lock().await.emit_live(app_handle, data)?;
+            }
+            None
         }
-        "MESSAGE_UPDATE" => { 
+        "MESSAGE_UPDATE" => {
             debug!("MESSAGE_UPDATE: message_id={}", data["id"]);
+            if let Some(message_id) = Snowflake::from_value(&data["id"]) {
+                state.link_previews.lock().await.forget(message_id);
+            }
+            let mut data = data;
+            // Embed-only updates come without content
+            if data["content"].is_string() {
+                let messages = std::slice::from_mut(&mut data);
+                match source {
+                    Source::Live => attach_resolved(app_handle, messages).await,
+                    Source::Replay => attach_cached(&state, messages).await,
+                }
+            }
             Some(GatewayEvent::MessageUpdate(data))
         }
         "MESSAGE_DELETE" => {
             debug!("MESSAGE_DELETE: message_id={}", data["id"]);
+            if let Some(message_id) = Snowflake::from_value(&data["id"]) {
+                state.link_previews.lock().await.forget(message_id);
+            }
             Some(GatewayEvent::MessageDelete(data))
         }
+        "MESSAGE_ACK" => state
+            .cache
+            .lock()
+            .await
+            .apply_message_ack(&data)
+            .map(GatewayEvent::UnreadUpdate),
+        "GUILD_CREATE" => {
+            debug!("GUILD_CREATE: guild_id={}", data["id"]);
+            state.cache.lock().await.apply_guild_create(&data);
+            None
+        }
+        "GUILD_DELETE" => {
+            debug!("GUILD_DELETE: guild_id={}", data["id"]);
+            state.cache.lock().await.apply_guild_delete(&data);
+            None
+        }
+        "GUILD_EMOJIS_UPDATE" => {
+            state.cache.lock().await.apply_emojis_update(&data);
+            None
+        }
+        "GUILD_ROLE_CREATE" | "GUILD_ROLE_UPDATE" | "GUILD_ROLE_DELETE" => {
+            let guild_id = {
+                let mut cache = state.cache.lock().await;
+                if event_type == "GUILD_ROLE_DELETE" {
+                    cache.apply_role_delete(&data)
+                } else {
+                    cache.apply_role_upsert(&data)
+                }
+            };
+            // Role permissions decide which channels we can see
+            if let Some(guild_id) = guild_id {
+                emit(app_handle, GatewayEvent::ChannelTreeUpdate(guild_id))?;
+            }
+            guild_id.map(GatewayEvent::GuildMembersUpdate)
+        }
+        "CHANNEL_CREATE" | "CHANNEL_UPDATE" | "THREAD_CREATE" | "THREAD_UPDATE" => state
+            .cache
+            .lock()
+            .await
+            .apply_channel_upsert(&data)
+            .map(GatewayEvent::ChannelTreeUpdate),
+        "CHANNEL_DELETE" | "THREAD_DELETE" => state
+            .cache
+            .lock()
+            .await
+            .apply_channel_delete(&data)
+            .map(GatewayEvent::ChannelTreeUpdate),
+        "THREAD_LIST_SYNC" => state
+            .cache
+            .lock()
+            .await
+            .apply_thread_list_sync(&data)
+            .map(GatewayEvent::ChannelTreeUpdate),
+        "GUILD_MEMBER_ADD" | "GUILD_MEMBER_UPDATE" => {
+            let (guild_id, own_member) = {
+                let mut cache = state.cache.lock().await;
+                let own_member = cache
+                    .current_user
+                    .as_ref()
+                    .is_some_and(|me| Snowflake::from_value(&data["user"]["id"]) == Some(me.id));
+                (cache.apply_member_upsert(&data), own_member)
+            };
+            // So do our own roles
+            if let Some(guild_id) = guild_id.filter(|_| own_member) {
+                emit(app_handle, GatewayEvent::ChannelTreeUpdate(guild_id))?;
+            }
+            guild_id.map(GatewayEvent::GuildMembersUpdate)
+        }
+        "GUILD_MEMBER_REMOVE" => state
+            .cache
+            .lock()
+            .await
+            .apply_member_remove(&data)
+            .map(GatewayEvent::GuildMembersUpdate),
+        "GUILD_MEMBERS_CHUNK" => {
+            debug!(
+                "GUILD_MEMBERS_CHUNK: guild_id={} chunk={}/{}",
+                data["guild_id"], data["chunk_index"], data["chunk_count"]
+            );
+            state
+                .cache
+                .lock()
+                .await
+                .apply_members_chunk(&data)
+                .map(GatewayEvent::GuildMembersUpdate)
+        }
+        "GUILD_MEMBER_LIST_UPDATE" => state
+            .cache
+            .lock()
+            .await
+            .apply_member_list_update(&data)
+            .map(GatewayEvent::GuildMembersUpdate),
+        "PRESENCE_UPDATE" => state
+            .cache
+            .lock()
+            .await
+            .apply_presence_update(&data)
+            .map(GatewayEvent::GuildMembersUpdate),
         _ => {
             debug!("Unhandled event: {}", event_type);
             None
@@ -349,14 +838,21 @@ async fn handle_dispatch_event(
     };
 
     if let Some(evt) = event {
-        app_handle
-            .emit("discord-gateway", evt)
-            .map_err(|e| format!("Failed to emit event: {}", e))?;
+        emit(app_handle, evt)?;
     }
 
     Ok(())
 }
 
+pub(super) fn emit<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    event: GatewayEvent,
+) -> Result<(), String> {
+    app_handle
+        .emit("discord-gateway", event)
+        .map_err(|e| format!("Failed to emit event: {}", e))
+}
+
 fn decompress_zlib(data: &[u8]) -> Result<String, String> {
     use std::io::Read;
 
@@ -367,3 +863,42 @@ fn decompress_zlib(data: &[u8]) -> Result<String, String> {
         .map_err(|e| format!("Decompression failed: {}", e))?;
     Ok(output)
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn resumable() -> Session {
+        Session {
+            id: Some("session".to_string()),
+            resume_url: Some("wss://gateway-us-east1-b.discord.gg".to_string()),
+            sequence: Some(42),
+            failures: 0,
+        }
+    }
+
+    #[test]
+    fn resumes_through_the_url_from_ready() {
+        let endpoints = Endpoints::default();
+        assert_eq!(
+            resumable().url(&endpoints),
+            "wss://gateway-us-east1-b.discord.gg/?v=10&encoding=json"
+        );
+        assert_eq!(
+            Session::default().url(&endpoints),
+            "wss://gateway.discord.gg/?v=10&encoding=json"
+        );
+    }
+
+    #[test]
+    fn resumes_through_a_custom_gateway() {
+        let endpoints: Endpoints =
+            serde_json::from_value(json!({ "gateway_url": "ws://127.0.0.1:8080" })).unwrap();
+        assert_eq!(
+            resumable().url(&endpoints),
+            "ws://127.0.0.1:8080/?v=10&encoding=json"
+        );
+    }
+}
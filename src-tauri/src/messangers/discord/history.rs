@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Manager, Runtime, State};
+use tracing::{error, info, warn};
+
+use super::{
+    api::{fetch_resolved_messages, get_channel_messages},
+    gateway::{GatewayEvent, emit},
+    mentions::attach_resolved,
+    snowflake::Snowflake,
+};
+use crate::AppState;
+
+// Discord's page size limit
+const PAGE_SIZE: usize = 100;
+// Larger gaps are left for the user to scroll through
+const MAX_BACKFILL_PAGES: usize = 10;
+// Messages loaded around a jump target
+const CONTEXT_SIZE: usize = 50;
+
+fn message_id(message: &Value) -> Snowflake {
+    Snowflake::from_value(&message["id"]).unwrap_or_default()
+}
+
+/// The newest message the UI has for each channel it loaded, so the gap left
+/// by a gateway outage can be fetched after reconnecting.
+#[derive(Debug, Default)]
+pub struct MessageHistory {
+    newest: HashMap<Snowflake, Snowflake>,
+    // Channels being backfilled, with the live messages held back meanwhile
+    backfilling: HashMap<Snowflake, Vec<Value>>,
+}
+
+impl MessageHistory {
+    /// Starts tracking a channel once messages of it were loaded.
+    pub fn note_loaded(&mut self, channel_id: Snowflake, messages: &[Value]) {
+        let Some(id) = messages.iter().map(message_id).max() else {
+            return;
+        };
+        let entry = self.newest.entry(channel_id).or_insert(id);
+        *entry = id.max(*entry);
+    }
+
+    /// Moves a tracked channel's marker forward. Returns false for messages
+    /// the UI already has.
+    fn advance(&mut self, message: &Value) -> bool {
+        let (Some(channel_id), Some(id)) = (
+            Snowflake::from_value(&message["channel_id"]),
+            Snowflake::from_value(&message["id"]),
+        ) else {
+            return true;
+        };
+        match self.newest.get_mut(&channel_id) {
+            Some(newest) if id <= *newest => false,
+            Some(newest) => {
+                *newest = id;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Emits a live MESSAGE_CREATE, or holds it back while the gap before it
+    /// is being filled.
+    pub fn emit_live<R: Runtime>(
+        &mut self,
+        app_handle: &AppHandle<R>,
+        message: Value,
+    ) -> Result<(), String> {
+        let channel_id = Snowflake::from_value(&message["channel_id"]).unwrap_or_default();
+        if let Some(held) = self.backfilling.get_mut(&channel_id) {
+            held.push(message);
+            return Ok(());
+        }
+        self.advance(&message);
+        emit(app_handle, GatewayEvent::MessageCreate(message))
+    }
+
+    fn emit_backfilled<R: Runtime>(
+        &mut self,
+        app_handle: &AppHandle<R>,
+        message: Value,
+    ) -> Result<(), String> {
+        if self.advance(&message) {
+            emit(app_handle, GatewayEvent::MessageCreate(message))?;
+        }
+        Ok(())
+    }
+
+    /// Marks every tracked channel as being backfilled and returns where
+    /// each one's gap starts.
+    fn begin_backfill(&mut self) -> Vec<(Snowflake, Snowflake)> {
+        let gaps: Vec<(Snowflake, Snowflake)> = self
+            .newest
+            .iter()
+            .filter(|(channel_id, _)| !self.backfilling.contains_key(*channel_id))
+            .map(|(&channel_id, &id)| (channel_id, id))
+            .collect();
+        for &(channel_id, _) in &gaps {
+            self.backfilling.insert(channel_id, Vec::new());
+        }
+        gaps
+    }
+
+    /// Ends a channel's backfill and emits the live messages held back.
+    fn finish_backfill<R: Runtime>(
+        &mut self,
+        app_handle: &AppHandle<R>,
+        channel_id: Snowflake,
+    ) -> Result<(), String> {
+        let mut held = self.backfilling.remove(&channel_id).unwrap_or_default();
+        held.sort_by_key(message_id);
+        for message in held {
+            self.emit_backfilled(app_handle, message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fetches a channel's messages after `after`, oldest first, and emits them.
+async fn backfill_channel<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    token: &str,
+    channel_id: Snowflake,
+    mut after: Snowflake,
+) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    for _ in 0..MAX_BACKFILL_PAGES {
+        let query = [
+            ("after", after.to_string()),
+            ("limit", PAGE_SIZE.to_string()),
+        ];
+        let mut page = get_channel_messages(&state, token, channel_id, &query).await?;
+        page.sort_by_key(message_id);
+        let full_page = page.len() == PAGE_SIZE;
+        attach_resolved(app_handle, &mut page).await;
+
+        for message in page {
+            after = after.max(message_id(&message));
+            state
+                .history
+                .lock()
+                .await
+                .emit_backfilled(app_handle, message)?;
+        }
+        if !full_page {
+            return Ok(());
+        }
+    }
+    warn!(
+        "Channel {} missed more than {} pages",
+        channel_id, MAX_BACKFILL_PAGES
+    );
+    Ok(())
+}
+
+/// Called on READY. A READY after the first one means the session could
+/// not be resumed and events were lost, so every loaded channel is fetched
+/// from its newest known message on. Live messages for those channels are
+/// held back until their gap is filled, keeping the UI in order.
+pub async fn start_backfill<R: Runtime>(app_handle: &AppHandle<R>) {
+    let state = app_handle.state::<AppState>();
+    let gaps = state.history.lock().await.begin_backfill();
+    if gaps.is_empty() {
+        return;
+    }
+    info!("Backfilling {} channels", gaps.len());
+
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        let token = state.token.lock().await.clone();
+        for (channel_id, after) in gaps {
+            let result = match &token {
+                Some(token) => backfill_channel(&app_handle, token, channel_id, after).await,
+                None => Err("not_authenticated".to_string()),
+            };
+            if let Err(e) = result {
+                warn!("Backfill of channel {} failed: {}", channel_id, e);
+            }
+            let finished = state
+                .history
+                .lock()
+                .await
+                .finish_backfill(&app_handle, channel_id);
+            if let Err(e) = finished {
+                error!("Failed to emit held back messages: {}", e);
+            }
+        }
+    });
+}
+
+/// A page of a channel to jump into.
+#[derive(Debug, Serialize)]
+pub struct MessageContext {
+    // Newest first, like fetch_channel_messages
+    pub messages: Vec<Value>,
+    // The message to scroll to, None when there is nothing to show
+    pub target_id: Option<Snowflake>,
+}
+
+pub(super) async fn require_token(state: &AppState) -> Result<String, String> {
+    state
+        .token
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| "not_authenticated".to_string())
+}
+
+/// Loads the messages around a message, failing when it does not exist.
+pub async fn message_context<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    token: &str,
+    channel_id: Snowflake,
+    message_id: Snowflake,
+) -> Result<MessageContext, String> {
+    let query = [
+        ("around", message_id.to_string()),
+        ("limit", CONTEXT_SIZE.to_string()),
+    ];
+    let messages = fetch_resolved_messages(app_handle, token, channel_id, &query).await?;
+    if !messages.iter().any(|m| self::message_id(m) == message_id) {
+        return Err("message_not_found".to_string());
+    }
+    Ok(MessageContext {
+        messages,
+        target_id: Some(message_id),
+    })
+}
+
+/// Loads the messages around a message, e.g. one opened from a link.
+#[tauri::command]
+pub async fn jump_to_message<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    channel_id: Snowflake,
+    message_id: Snowflake,
+) -> Result<MessageContext, String> {
+    let token = require_token(&state).await?;
+    message_context(&app_handle, &token, channel_id, message_id).await
+}
+
+/// Loads the messages around a point in time, given in unix milliseconds.
+/// The target is the first message sent at or after it, or the last one
+/// before it when nothing newer exists.
+#[tauri::command]
+pub async fn jump_to_date<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    channel_id: Snowflake,
+    timestamp: i64,
+) -> Result<MessageContext, String> {
+    let token = require_token(&state).await?;
+    let cursor = Snowflake::from_timestamp_ms(timestamp);
+    let half = (CONTEXT_SIZE / 2).to_string();
+
+    let newer_query = [
+        ("after", Snowflake(cursor.0.saturating_sub(1)).to_string()),
+        ("limit", half.clone()),
+    ];
+    let older_query = [("before", cursor.to_string()), ("limit", half)];
+    let mut messages =
+        fetch_resolved_messages(&app_handle, &token, channel_id, &newer_query).await?;
+    let older = fetch_resolved_messages(&app_handle, &token, channel_id, &older_query).await?;
+
+    let target_id = messages
+        .iter()
+        .map(message_id)
+        .min()
+        .or_else(|| older.iter().map(message_id).max());
+
+    messages.sort_by_key(|m| std::cmp::Reverse(message_id(m)));
+    messages.extend(older);
+    Ok(MessageContext {
+        messages,
+        target_id,
+    })
+}
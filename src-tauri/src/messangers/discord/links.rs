@@ -0,0 +1,283 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::LazyLock,
+};
+
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Runtime, State};
+
+use super::{
+    api::get_channel_messages,
+    cache::DiscordCache,
+    history::{MessageContext, message_context, require_token},
+    mentions::{ResolvedUser, resolve_user},
+    models::User,
+    snowflake::Snowflake,
+};
+use crate::AppState;
+
+// https://discord.com/channels/<guild or @me>/<channel>[/<message>], also on
+// the ptb/canary hosts and the old discordapp.com domain
+static MESSAGE_LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^https://(?:(?:ptb|canary)\.)?discord(?:app)?\.com/channels/(@me|\d+)/(\d+)(?:/(\d+))?/?$",
+    )
+    .expect("valid message link pattern")
+});
+
+// Linked messages remembered, so a link is not fetched on every render
+const PREVIEW_CACHE_SIZE: usize = 256;
+// Characters of the linked message shown in its preview
+const SNIPPET_LENGTH: usize = 200;
+
+/// A link to a channel, or to a message in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLink {
+    // None for DMs (`@me`)
+    pub guild_id: Option<Snowflake>,
+    pub channel_id: Snowflake,
+    pub message_id: Option<Snowflake>,
+}
+
+impl MessageLink {
+    pub fn parse(url: &str) -> Option<Self> {
+        let captures = MESSAGE_LINK.captures(url.trim())?;
+        let guild_id = match &captures[1] {
+            "@me" => None,
+            id => Some(id.parse().ok()?),
+        };
+        Some(Self {
+            guild_id,
+            channel_id: captures[2].parse().ok()?,
+            message_id: match captures.get(3) {
+                Some(id) => Some(id.as_str().parse().ok()?),
+                None => None,
+            },
+        })
+    }
+}
+
+/// The part of a linked message shown in its preview.
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotedMessage {
+    pub author: ResolvedUser,
+    // Start of the content, cut at SNIPPET_LENGTH characters
+    pub snippet: String,
+    pub has_attachments: bool,
+    pub timestamp: String,
+}
+
+/// Preview of a message or channel link, sent to the UI in the message's
+/// `resolved.links`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkPreview {
+    pub url: String,
+    pub guild_id: Option<Snowflake>,
+    pub channel_id: Snowflake,
+    pub channel_name: Option<String>,
+    pub message_id: Option<Snowflake>,
+    // None for channel links and messages we cannot read
+    pub message: Option<QuotedMessage>,
+}
+
+/// Linked messages already looked up, including the ones that could not be
+/// read. Bounded, oldest lookups are dropped first.
+#[derive(Debug, Default)]
+pub struct LinkPreviews {
+    quoted: HashMap<Snowflake, Option<QuotedMessage>>,
+    order: VecDeque<Snowflake>,
+}
+
+impl LinkPreviews {
+    fn get(&self, message_id: Snowflake) -> Option<Option<QuotedMessage>> {
+        self.quoted.get(&message_id).cloned()
+    }
+
+    /// Remembers a fetched linked message, or that it cannot be read.
+    pub(super) fn store(
+        &mut self,
+        cache: &DiscordCache,
+        message_id: Snowflake,
+        message: Option<&Value>,
+    ) {
+        self.insert(message_id, message.and_then(|m| quote(cache, m)));
+    }
+
+    fn insert(&mut self, message_id: Snowflake, quoted: Option<QuotedMessage>) {
+        if self.quoted.insert(message_id, quoted).is_none() {
+            self.order.push_back(message_id);
+        }
+        while self.order.len() > PREVIEW_CACHE_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.quoted.remove(&oldest);
+            }
+        }
+    }
+
+    /// Drops a preview after the linked message was edited or deleted.
+    pub fn forget(&mut self, message_id: Snowflake) {
+        if self.quoted.remove(&message_id).is_some() {
+            self.order.retain(|id| *id != message_id);
+        }
+    }
+}
+
+fn quote(cache: &DiscordCache, message: &Value) -> Option<QuotedMessage> {
+    let author = serde_json::from_value::<User>(message["author"].clone()).ok()?;
+    let guild = Snowflake::from_value(&message["channel_id"])
+        .and_then(|id| cache.channel(id))
+        .map(|(guild, _)| guild);
+
+    let content = message["content"].as_str().unwrap_or_default();
+    let mut snippet: String = content.chars().take(SNIPPET_LENGTH).collect();
+    if snippet.len() < content.len() {
+        snippet.push('…');
+    }
+
+    Some(QuotedMessage {
+        author: resolve_user(&author, guild),
+        snippet,
+        has_attachments: message["attachments"]
+            .as_array()
+            .is_some_and(|a| !a.is_empty()),
+        timestamp: message["timestamp"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+/// Fetches a linked message, None when it no longer exists. User accounts
+/// cannot GET one message directly, so this asks for the page of one around
+/// it.
+pub(super) async fn fetch_linked(
+    state: &AppState,
+    token: &str,
+    channel_id: Snowflake,
+    message_id: Snowflake,
+) -> Result<Option<Value>, String> {
+    let query = [
+        ("around", message_id.to_string()),
+        ("limit", "1".to_string()),
+    ];
+    let messages = get_channel_messages(state, token, channel_id, &query).await?;
+    Ok(messages
+        .into_iter()
+        .find(|m| Snowflake::from_value(&m["id"]) == Some(message_id)))
+}
+
+/// Builds previews for the Discord links among the URLs of a message from
+/// what was already looked up. Returns the `(channel_id, message_id)` of the
+/// linked messages that still need fetching.
+pub(super) fn cached_previews(
+    cache: &DiscordCache,
+    previews: &LinkPreviews,
+    urls: &[String],
+) -> (Vec<LinkPreview>, Vec<(Snowflake, Snowflake)>) {
+    let mut links: Vec<LinkPreview> = Vec::new();
+    let mut missing = Vec::new();
+    for url in urls {
+        let Some(link) = MessageLink::parse(url) else {
+            continue;
+        };
+        if links.iter().any(|p| p.url == *url) {
+            continue;
+        }
+        let message = link.message_id.and_then(|message_id| {
+            let quoted = previews.get(message_id);
+            if quoted.is_none() {
+                missing.push((link.channel_id, message_id));
+            }
+            quoted.flatten()
+        });
+        links.push(LinkPreview {
+            url: url.clone(),
+            guild_id: link.guild_id,
+            channel_id: link.channel_id,
+            channel_name: cache
+                .any_channel(link.channel_id)
+                .and_then(|c| c.name.clone()),
+            message_id: link.message_id,
+            message,
+        });
+    }
+    (links, missing)
+}
+
+/// Where the UI should go for a link, with the linked message's surroundings
+/// already loaded.
+#[derive(Debug, Serialize)]
+pub struct LinkTarget {
+    pub guild_id: Option<Snowflake>,
+    pub channel_id: Snowflake,
+    // None for channel links
+    pub context: Option<MessageContext>,
+}
+
+#[tauri::command]
+pub async fn open_message_link<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    url: String,
+) -> Result<LinkTarget, String> {
+    let link = MessageLink::parse(&url).ok_or_else(|| "invalid_link".to_string())?;
+    let context = match link.message_id {
+        Some(message_id) => {
+            let token = require_token(&state).await?;
+            Some(message_context(&app_handle, &token, link.channel_id, message_id).await?)
+        }
+        None => None,
+    };
+    Ok(LinkTarget {
+        guild_id: link.guild_id,
+        channel_id: link.channel_id,
+        context,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_message_links() {
+        assert_eq!(
+            MessageLink::parse("https://discord.com/channels/1/2/3"),
+            Some(MessageLink {
+                guild_id: Some(Snowflake(1)),
+                channel_id: Snowflake(2),
+                message_id: Some(Snowflake(3)),
+            })
+        );
+        assert_eq!(
+            MessageLink::parse("https://canary.discordapp.com/channels/@me/2/"),
+            Some(MessageLink {
+                guild_id: None,
+                channel_id: Snowflake(2),
+                message_id: None,
+            })
+        );
+        assert_eq!(
+            MessageLink::parse(" https://ptb.discord.com/channels/1/2 ").map(|l| l.guild_id),
+            Some(Some(Snowflake(1)))
+        );
+    }
+
+    #[test]
+    fn rejects_other_urls() {
+        for url in [
+            "http://discord.com/channels/1/2/3",
+            "https://discord.com/channels/1",
+            "https://discord.com/channels/abc/2/3",
+            "https://evil.com/channels/1/2/3",
+            "https://discord.com.evil.com/channels/1/2/3",
+            "https://discord.com/channels/1/2/3/4",
+            // Too large for an id
+            "https://discord.com/channels/1/2/99999999999999999999",
+        ] {
+            assert_eq!(MessageLink::parse(url), None, "{}", url);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tauri::{AppHandle, Runtime, State};
+use tracing::{error, info, warn};
+
+use super::{account::add_account, endpoints::Endpoints};
+use crate::AppState;
+
+/// Why a login attempt did not produce a token. Sent to the UI tagged by
+/// `kind`, so it can show the matching prompt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LoginError {
+    InvalidCredentials,
+    // Discord wants a captcha solved first; it cannot be solved in-app
+    CaptchaRequired {
+        sitekey: Option<String>,
+        service: Option<String>,
+    },
+    // Login from a new location has to be approved from the email Discord sent
+    NewLocationVerification,
+    // Wrong TOTP or backup code
+    InvalidCode,
+    // No MFA login is waiting for a code
+    NoPendingMfa,
+    RateLimited {
+        retry_after_ms: i64,
+    },
+    // Discord accepted the login but the token was not usable
+    TokenRejected,
+    Request,
+    Parse,
+    Http {
+        status: u16,
+    },
+    // Saving the token failed, e.g. with the vault locked
+    Storage {
+        message: String,
+    },
+}
+
+/// Second factors Discord offers for an MFA ticket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MfaMethod {
+    Totp,
+    Backup,
+}
+
+/// An MFA ticket waiting for a code. Short-lived, and like the token it is
+/// only ever held in Rust.
+#[derive(Debug, Clone)]
+pub struct MfaTicket {
+    pub ticket: String,
+    pub methods: Vec<MfaMethod>,
+}
+
+/// Outcome of the first login step.
+#[derive(Debug, Clone)]
+pub enum LoginResponse {
+    Token(String),
+    Mfa(MfaTicket),
+}
+
+/// Client for Discord's user login endpoints. The endpoints can point at a
+/// local mock server.
+#[derive(Debug, Clone)]
+pub struct LoginClient {
+    endpoints: Endpoints,
+    http: reqwest::Client,
+}
+
+impl LoginClient {
+    pub fn new(endpoints: Endpoints) -> Self {
+        Self {
+            http: endpoints.http(),
+            endpoints,
+        }
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<Value, LoginError> {
+        let response = self
+            .http
+            .post(self.endpoints.api(path))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| {
+                error!("Login request failed: {}", e);
+                LoginError::Request
+            })?;
+
+        let status = response.status();
+        if status.is_success() {
+            return response.json().await.map_err(|e| {
+                error!("Failed to parse login response: {}", e);
+                LoginError::Parse
+            });
+        }
+        warn!("Login step {} failed: {}", path, status);
+        // Outages and proxies answer with HTML; those map to the status alone
+        let body: Value = response.json().await.unwrap_or_default();
+        Err(login_error(status.as_u16(), &body))
+    }
+
+    /// POST /auth/login. Either a token, or a ticket for `submit_mfa`.
+    pub async fn login(&self, email: &str, password: &str) -> Result<LoginResponse, LoginError> {
+        let body = self
+            .post(
+                "/auth/login",
+                json!({
+                    "login": email,
+                    "password": password,
+                    "undelete": false,
+                    "login_source": null,
+                    "gift_code_sku_id": null,
+                }),
+            )
+            .await?;
+
+        if let Some(token) = body["token"].as_str() {
+            return Ok(LoginResponse::Token(token.to_string()));
+        }
+        let ticket = body["ticket"].as_str().ok_or(LoginError::Parse)?;
+        let methods = [("totp", MfaMethod::Totp), ("backup", MfaMethod::Backup)]
+            .into_iter()
+            .filter(|(key, _)| body[*key].as_bool().unwrap_or(false))
+            .map(|(_, method)| method)
+            .collect();
+        Ok(LoginResponse::Mfa(MfaTicket {
+            ticket: ticket.to_string(),
+            methods,
+        }))
+    }
+
+    /// POST /auth/mfa/totp or /auth/mfa/backup with the code for a ticket.
+    pub async fn submit_mfa(
+        &self,
+        ticket: &str,
+        method: MfaMethod,
+        code: &str,
+    ) -> Result<String, LoginError> {
+        let path = match method {
+            MfaMethod::Totp => "/auth/mfa/totp",
+            MfaMethod::Backup => "/auth/mfa/backup",
+        };
+        let body = self
+            .post(
+                path,
+                json!({
+                    "code": code.trim(),
+                    "ticket": ticket,
+                    "login_source": null,
+                    "gift_code_sku_id": null,
+                }),
+            )
+            .await?;
+        body["token"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or(LoginError::Parse)
+    }
+}
+
+/// Maps an error response of the auth endpoints to a `LoginError`.
+fn login_error(status: u16, body: &Value) -> LoginError {
+    if status == 429 {
+        let retry_after = body["retry_after"].as_f64().unwrap_or(1.0);
+        return LoginError::RateLimited {
+            retry_after_ms: (retry_after * 1000.0).ceil() as i64,
+        };
+    }
+    if body.get("captcha_key").is_some() {
+        return LoginError::CaptchaRequired {
+            sitekey: body["captcha_sitekey"].as_str().map(str::to_string),
+            service: body["captcha_service"].as_str().map(str::to_string),
+        };
+    }
+    // Invalid form body: { "errors": { "login": { "_errors": [{ "code": ... }] } } }
+    let field_codes = body["errors"]
+        .as_object()
+        .into_iter()
+        .flat_map(|fields| fields.values())
+        .flat_map(|field| field["_errors"].as_array().into_iter().flatten())
+        .filter_map(|e| e["code"].as_str());
+    for code in field_codes {
+        match code {
+            "ACCOUNT_LOGIN_VERIFICATION_EMAIL" => return LoginError::NewLocationVerification,
+            "INVALID_LOGIN" | "INVALID_PASSWORD" => return LoginError::InvalidCredentials,
+            _ => {}
+        }
+    }
+    // 60008: invalid two-factor code
+    if body["code"].as_u64() == Some(60008) {
+        return LoginError::InvalidCode;
+    }
+    LoginError::Http { status }
+}
+
+async fn finish_login<R: Runtime>(
+    state: &AppState,
+    app_handle: AppHandle<R>,
+    token: String,
+) -> Result<(), LoginError> {
+    match add_account(state, app_handle, token).await {
+        Ok(true) => {
+            info!("Logged in with email and password");
+            Ok(())
+        }
+        Ok(false) => Err(LoginError::TokenRejected),
+        Err(message) => Err(LoginError::Storage { message }),
+    }
+}
+
+/// The step the UI shows next after `login`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LoginStep {
+    LoggedIn,
+    MfaRequired { methods: Vec<MfaMethod> },
+}
+
+#[tauri::command]
+pub async fn login<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    email: String,
+    password: String,
+) -> Result<LoginStep, LoginError> {
+    match LoginClient::new(state.endpoints.clone())
+        .login(&email, &password)
+        .await?
+    {
+        LoginResponse::Token(token) => {
+            finish_login(&state, app_handle, token).await?;
+            Ok(LoginStep::LoggedIn)
+        }
+        LoginResponse::Mfa(ticket) => {
+            let methods = ticket.methods.clone();
+            *state.pending_mfa.lock().await = Some(ticket);
+            Ok(LoginStep::MfaRequired { methods })
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn submit_mfa_code<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    method: MfaMethod,
+    code: String,
+) -> Result<(), LoginError> {
+    let ticket = state
+        .pending_mfa
+        .lock()
+        .await
+        .clone()
+        .ok_or(LoginError::NoPendingMfa)?;
+    let token = LoginClient::new(state.endpoints.clone())
+        .submit_mfa(&ticket.ticket, method, &code)
+        .await?;
+    state.pending_mfa.lock().await.take();
+    finish_login(&state, app_handle, token).await
+}
@@ -0,0 +1,768 @@
+use serde::Serialize;
+
+use super::snowflake::Snowflake;
+
+// Parser for the markdown dialect Discord renders in message content.
+// https://support.discord.com/hc/en-us/articles/210298617
+//
+// The text is parsed line by line for block constructs (quotes, code blocks,
+// headings, lists) and span by span for inline formatting. Nothing inside a
+// code block is parsed.
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Node {
+    Text {
+        content: String,
+    },
+    LineBreak,
+    Bold {
+        children: Vec<Node>,
+    },
+    Italic {
+        children: Vec<Node>,
+    },
+    Underline {
+        children: Vec<Node>,
+    },
+    Strikethrough {
+        children: Vec<Node>,
+    },
+    Spoiler {
+        children: Vec<Node>,
+    },
+    InlineCode {
+        content: String,
+    },
+    CodeBlock {
+        language: Option<String>,
+        content: String,
+    },
+    BlockQuote {
+        children: Vec<Node>,
+    },
+    Heading {
+        level: u8,
+        children: Vec<Node>,
+    },
+    List {
+        ordered: bool,
+        // Number of the first item for ordered lists
+        start: Option<u64>,
+        items: Vec<Vec<Node>>,
+    },
+    // Masked link: [children](url)
+    Link {
+        url: String,
+        children: Vec<Node>,
+    },
+    // Bare or <angle-bracketed> URL
+    Url {
+        url: String,
+    },
+    UserMention {
+        id: Snowflake,
+    },
+    ChannelMention {
+        id: Snowflake,
+    },
+    RoleMention {
+        id: Snowflake,
+    },
+    Everyone,
+    Here,
+    CustomEmoji {
+        name: String,
+        id: Snowflake,
+        animated: bool,
+    },
+    // <t:unix:style>; style is one of t, T, d, D, f, F, R
+    Timestamp {
+        timestamp: i64,
+        style: Option<char>,
+    },
+}
+
+/// Parses message content into a list of block and inline nodes.
+pub fn parse(content: &str) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    parse_blocks(content, true, &mut nodes);
+    nodes
+}
+
+fn code_block(raw: &str) -> Node {
+    let is_language = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_+-.#".contains(c))
+    };
+    let (language, body) = match raw.split_once('\n') {
+        Some((first, body)) if is_language(first) && !body.trim().is_empty() => {
+            (Some(first.to_string()), body)
+        }
+        _ => (None, raw),
+    };
+    Node::CodeBlock {
+        language,
+        content: body.trim_matches('\n').to_string(),
+    }
+}
+
+/// Splits off the first line of `text`, returning it and what follows.
+fn next_line(text: &str) -> (&str, Option<&str>) {
+    match text.split_once('\n') {
+        Some((line, rest)) => (line, Some(rest)),
+        None => (text, None),
+    }
+}
+
+fn parse_blocks(text: &str, allow_quotes: bool, nodes: &mut Vec<Node>) {
+    if text.is_empty() {
+        return;
+    }
+
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut rest = Some(text);
+
+    let flush = |paragraph: &mut Vec<&str>, nodes: &mut Vec<Node>| {
+        if !paragraph.is_empty() {
+            nodes.extend(parse_inline(&paragraph.join("\n")));
+            paragraph.clear();
+        }
+    };
+
+    while let Some(current) = rest {
+        let (line, next) = next_line(current);
+
+        // Quotes come before code blocks, so a quote can hold a fence
+        if allow_quotes {
+            // ">>> " quotes everything that follows
+            if let Some(quoted) = current.strip_prefix(">>> ") {
+                flush(&mut paragraph, nodes);
+                let mut children = Vec::new();
+                parse_blocks(quoted, false, &mut children);
+                nodes.push(Node::BlockQuote { children });
+                return;
+            }
+
+            if quote_line(line).is_some() {
+                flush(&mut paragraph, nodes);
+                let mut quoted = Vec::new();
+                while let Some(current) = rest {
+                    let (line, next) = next_line(current);
+                    let Some(content) = quote_line(line) else {
+                        break;
+                    };
+                    quoted.push(content);
+                    rest = next;
+                }
+                let mut children = Vec::new();
+                parse_blocks(&quoted.join("\n"), false, &mut children);
+                nodes.push(Node::BlockQuote { children });
+                continue;
+            }
+        }
+
+        // A fence may open mid-line and close on a later one; nothing inside
+        // is parsed
+        if let Some(start) = line.find("```") {
+            let after = &current[start + 3..];
+            if let Some(end) = after.find("```") {
+                if start > 0 {
+                    paragraph.push(&line[..start]);
+                }
+                flush(&mut paragraph, nodes);
+                nodes.push(code_block(&after[..end]));
+                let remainder = &after[end + 3..];
+                rest = match remainder.strip_prefix('\n') {
+                    Some(remainder) => Some(remainder),
+                    None => Some(remainder).filter(|r| !r.is_empty()),
+                };
+                continue;
+            }
+        }
+
+        if let Some((level, title)) = heading(line) {
+            flush(&mut paragraph, nodes);
+            nodes.push(Node::Heading {
+                level,
+                children: parse_inline(title),
+            });
+            rest = next;
+            continue;
+        }
+
+        if let Some((ordered, number, _)) = list_item(line) {
+            flush(&mut paragraph, nodes);
+            let mut items = Vec::new();
+            while let Some(current) = rest {
+                let (line, next) = next_line(current);
+                match list_item(line) {
+                    Some((item_ordered, _, content)) if item_ordered == ordered => {
+                        items.push(parse_inline(content));
+                        rest = next;
+                    }
+                    _ => break,
+                }
+            }
+            nodes.push(Node::List {
+                ordered,
+                start: number,
+                items,
+            });
+            continue;
+        }
+
+        paragraph.push(line);
+        rest = next;
+    }
+
+    flush(&mut paragraph, nodes);
+}
+
+fn quote_line(line: &str) -> Option<&str> {
+    if line == ">" {
+        return Some("");
+    }
+    line.strip_prefix("> ")
+}
+
+fn heading(line: &str) -> Option<(u8, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if !(1..=3).contains(&level) {
+        return None;
+    }
+    let title = line[level..].strip_prefix(' ')?.trim();
+    (!title.is_empty()).then_some((level as u8, title))
+}
+
+/// Returns (ordered, number, content) for "- item", "* item" and "1. item".
+fn list_item(line: &str) -> Option<(bool, Option<u64>, &str)> {
+    let trimmed = line.trim_start_matches(' ');
+    if let Some(content) = trimmed
+        .strip_prefix("- ")
+        .or_else(|| trimmed.strip_prefix("* "))
+    {
+        return (!content.trim().is_empty()).then_some((false, None, content));
+    }
+
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 || digits > 9 {
+        return None;
+    }
+    let content = trimmed[digits..].strip_prefix(". ")?;
+    let number = trimmed[..digits].parse().ok()?;
+    (!content.trim().is_empty()).then_some((true, Some(number), content))
+}
+
+fn parse_inline(text: &str) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut buffer = String::new();
+    let mut i = 0;
+
+    let flush = |buffer: &mut String, nodes: &mut Vec<Node>| {
+        if !buffer.is_empty() {
+            nodes.push(Node::Text {
+                content: std::mem::take(buffer),
+            });
+        }
+    };
+
+    while i < text.len() {
+        let rest = &text[i..];
+        let previous = text[..i].chars().next_back();
+
+        if let Some((node, consumed)) = inline_node(rest, previous) {
+            flush(&mut buffer, &mut nodes);
+            nodes.push(node);
+            i += consumed;
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+        let escaped = rest
+            .strip_prefix('\\')
+            .and_then(|after| after.chars().next())
+            .filter(char::is_ascii_punctuation);
+        if let Some(escaped) = escaped {
+            buffer.push(escaped);
+            i += 1 + escaped.len_utf8();
+            continue;
+        }
+        if c == '\n' {
+            flush(&mut buffer, &mut nodes);
+            nodes.push(Node::LineBreak);
+        } else {
+            buffer.push(c);
+        }
+        i += c.len_utf8();
+    }
+
+    flush(&mut buffer, &mut nodes);
+    nodes
+}
+
+/// Tries every inline construct at the start of `rest`. Returns the node and
+/// the number of bytes it spans.
+fn inline_node(rest: &str, previous: Option<char>) -> Option<(Node, usize)> {
+    let first = rest.chars().next()?;
+    match first {
+        '`' => inline_code(rest),
+        '<' => angle_bracketed(rest),
+        '[' => masked_link(rest),
+        '@' => {
+            if rest.starts_with("@everyone") {
+                Some((Node::Everyone, "@everyone".len()))
+            } else if rest.starts_with("@here") {
+                Some((Node::Here, "@here".len()))
+            } else {
+                None
+            }
+        }
+        'h' if rest.starts_with("http://") || rest.starts_with("https://") => bare_url(rest),
+        '|' => wrapped(rest, "||", |children| Node::Spoiler { children }),
+        '~' => wrapped(rest, "~~", |children| Node::Strikethrough { children }),
+        '*' => {
+            wrapped(rest, "**", |children| Node::Bold { children }).or_else(|| italic(rest, '*'))
+        }
+        '_' => wrapped(rest, "__", |children| Node::Underline { children }).or_else(|| {
+            // Intraword underscores (snake_case) are not emphasis
+            if previous.is_some_and(char::is_alphanumeric) {
+                None
+            } else {
+                italic(rest, '_')
+            }
+        }),
+        _ => None,
+    }
+}
+
+fn inline_code(rest: &str) -> Option<(Node, usize)> {
+    let ticks = rest.chars().take_while(|&c| c == '`').count();
+    if ticks == 0 {
+        return None;
+    }
+    let fence = &rest[..ticks];
+    let body = &rest[ticks..];
+
+    let mut search = 0;
+    while let Some(found) = body[search..].find(fence) {
+        let end = search + found;
+        let run = body[end..].chars().take_while(|&c| c == '`').count();
+        if run == ticks {
+            let content = &body[..end];
+            if content.is_empty() {
+                return None;
+            }
+            // A single space of padding lets code start or end with a backtick
+            let content = match content.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+                Some(inner) if !inner.trim().is_empty() => inner,
+                _ => content,
+            };
+            return Some((
+                Node::InlineCode {
+                    content: content.to_string(),
+                },
+                ticks + end + ticks,
+            ));
+        }
+        search = end + run;
+    }
+    None
+}
+
+fn angle_bracketed(rest: &str) -> Option<(Node, usize)> {
+    let end = rest.find('>')?;
+    let inner = &rest[1..end];
+    let consumed = end + 1;
+    let snowflake = |s: &str| {
+        s.chars()
+            .all(|c| c.is_ascii_digit())
+            .then(|| s.parse::<Snowflake>().ok())?
+    };
+
+    let node = if let Some(id) = inner.strip_prefix("@&") {
+        Node::RoleMention { id: snowflake(id)? }
+    } else if let Some(id) = inner.strip_prefix("@!").or_else(|| inner.strip_prefix('@')) {
+        Node::UserMention { id: snowflake(id)? }
+    } else if let Some(id) = inner.strip_prefix('#') {
+        Node::ChannelMention { id: snowflake(id)? }
+    } else if let Some(body) = inner.strip_prefix("t:") {
+        let (timestamp, style) = match body.split_once(':') {
+            Some((timestamp, style)) => {
+                let mut chars = style.chars();
+                let style = chars.next().filter(|c| "tTdDfFR".contains(*c))?;
+                if chars.next().is_some() {
+                    return None;
+                }
+                (timestamp, Some(style))
+            }
+            None => (body, None),
+        };
+        Node::Timestamp {
+            timestamp: timestamp.parse().ok()?,
+            style,
+        }
+    } else if inner.starts_with(':') || inner.starts_with("a:") {
+        let animated = inner.starts_with("a:");
+        let body = inner
+            .strip_prefix("a:")
+            .or_else(|| inner.strip_prefix(':'))?;
+        let (name, id) = body.split_once(':')?;
+        let valid_name =
+            name.len() >= 2 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return None;
+        }
+        Node::CustomEmoji {
+            name: name.to_string(),
+            id: snowflake(id)?,
+            animated,
+        }
+    } else if (inner.starts_with("http://") || inner.starts_with("https://"))
+        && !inner.contains(char::is_whitespace)
+    {
+        Node::Url {
+            url: inner.to_string(),
+        }
+    } else {
+        return None;
+    };
+
+    Some((node, consumed))
+}
+
+fn masked_link(rest: &str) -> Option<(Node, usize)> {
+    let label_end = rest.find(']')?;
+    let label = &rest[1..label_end];
+    let after = rest[label_end + 1..].strip_prefix('(')?;
+    let url_end = after.find(')')?;
+    let raw_url = &after[..url_end];
+    let url = raw_url
+        .strip_prefix('<')
+        .and_then(|u| u.strip_suffix('>'))
+        .unwrap_or(raw_url);
+
+    let valid_url = (url.starts_with("http://") || url.starts_with("https://"))
+        && !url.contains(char::is_whitespace);
+    if label.trim().is_empty() || !valid_url {
+        return None;
+    }
+
+    Some((
+        Node::Link {
+            url: url.to_string(),
+            children: parse_inline(label),
+        },
+        label_end + 2 + url_end + 1,
+    ))
+}
+
+fn bare_url(rest: &str) -> Option<(Node, usize)> {
+    let mut end = rest
+        .find(|c: char| c.is_whitespace() || c == '<')
+        .unwrap_or(rest.len());
+
+    // Trailing punctuation belongs to the sentence, not the URL, unless it
+    // closes a parenthesis opened inside the URL.
+    while let Some(last) = rest[..end].chars().next_back() {
+        let unbalanced_paren =
+            last == ')' && rest[..end].matches('(').count() < rest[..end].matches(')').count();
+        if ".,:;!?\"'".contains(last) || unbalanced_paren {
+            end -= last.len_utf8();
+        } else {
+            break;
+        }
+    }
+
+    let url = &rest[..end];
+    if url.ends_with("://") {
+        return None;
+    }
+    Some((
+        Node::Url {
+            url: url.to_string(),
+        },
+        end,
+    ))
+}
+
+/// Two-character delimiters: `**`, `__`, `~~`, `||`.
+fn wrapped(rest: &str, delimiter: &str, build: fn(Vec<Node>) -> Node) -> Option<(Node, usize)> {
+    let body = rest.strip_prefix(delimiter)?;
+    let end = find_closing(body, delimiter)?;
+    Some((build(parse_inline(&body[..end])), delimiter.len() * 2 + end))
+}
+
+fn italic(rest: &str, marker: char) -> Option<(Node, usize)> {
+    let body = &rest[1..];
+    if body.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let end = find_closing(body, &marker.to_string())?;
+    let inner = &body[..end];
+    if inner.ends_with(char::is_whitespace) {
+        return None;
+    }
+    if marker == '_' && body[end + 1..].starts_with(char::is_alphanumeric) {
+        return None;
+    }
+    Some((
+        Node::Italic {
+            children: parse_inline(inner),
+        },
+        end + 2,
+    ))
+}
+
+/// Finds the closing delimiter in `body`, skipping escapes and code spans.
+/// Returns its byte offset; the enclosed text is never empty.
+fn find_closing(body: &str, delimiter: &str) -> Option<usize> {
+    let marker = delimiter.chars().next()?;
+    let single = delimiter.len() == 1;
+    let mut i = 0;
+
+    while i < body.len() {
+        let rest = &body[i..];
+        if let Some(after) = rest.strip_prefix('\\') {
+            i += 1 + after.chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if let Some((_, consumed)) = inline_code(rest) {
+            i += consumed;
+            continue;
+        }
+        if rest.starts_with(delimiter) {
+            let run = rest.chars().take_while(|&c| c == marker).count();
+            if single && run >= 2 {
+                // A doubled marker belongs to a nested bold/underline
+                i += run;
+                continue;
+            }
+            if i > 0 {
+                // Close on the last possible position of a run, so that
+                // "***x***" nests italic inside bold
+                return Some(i + run - delimiter.len());
+            }
+            i += run;
+            continue;
+        }
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+#[tauri::command]
+pub fn parse_message_markdown(content: String) -> Vec<Node> {
+    parse(&content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(content: &str) -> Node {
+        Node::Text {
+            content: content.to_string(),
+        }
+    }
+
+    fn code(language: Option<&str>, content: &str) -> Node {
+        Node::CodeBlock {
+            language: language.map(str::to_string),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn nests_inline_formatting() {
+        assert_eq!(
+            parse("**bold *and italic***"),
+            [Node::Bold {
+                children: vec![
+                    text("bold "),
+                    Node::Italic {
+                        children: vec![text("and italic")]
+                    },
+                ]
+            }]
+        );
+        assert_eq!(
+            parse("***both***"),
+            [Node::Bold {
+                children: vec![Node::Italic {
+                    children: vec![text("both")]
+                }]
+            }]
+        );
+        assert_eq!(
+            parse("__~~under strike~~__"),
+            [Node::Underline {
+                children: vec![Node::Strikethrough {
+                    children: vec![text("under strike")]
+                }]
+            }]
+        );
+    }
+
+    #[test]
+    fn leaves_unclosed_markers_as_text() {
+        assert_eq!(parse("**not bold"), [text("**not bold")]);
+        assert_eq!(parse("a * b * c"), [text("a * b * c")]);
+        assert_eq!(parse("||no spoiler"), [text("||no spoiler")]);
+        assert_eq!(parse("`open"), [text("`open")]);
+        assert_eq!(parse("```never closed"), [text("```never closed")]);
+    }
+
+    #[test]
+    fn respects_escapes_and_intraword_underscores() {
+        assert_eq!(parse(r"\*not italic\*"), [text("*not italic*")]);
+        assert_eq!(parse(r"\\"), [text(r"\")]);
+        assert_eq!(parse("snake_case_name"), [text("snake_case_name")]);
+        // Markers inside code spans do not close anything
+        assert_eq!(
+            parse("*a `*` b*"),
+            [Node::Italic {
+                children: vec![
+                    text("a "),
+                    Node::InlineCode {
+                        content: "*".to_string()
+                    },
+                    text(" b"),
+                ]
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_code_blocks() {
+        assert_eq!(
+            parse("before\n```rust\nfn main() {}\n```\nafter"),
+            [
+                text("before"),
+                code(Some("rust"), "fn main() {}"),
+                text("after")
+            ]
+        );
+        // Nothing inside is parsed, not even quote markers
+        assert_eq!(parse("```\n> **x**\n```"), [code(None, "> **x**")]);
+    }
+
+    #[test]
+    fn parses_fences_inside_quotes() {
+        assert_eq!(
+            parse("> look:\n> ```\n> let x = 1;\n> ```\nafter"),
+            [
+                Node::BlockQuote {
+                    children: vec![text("look:"), code(None, "let x = 1;")]
+                },
+                text("after"),
+            ]
+        );
+        assert_eq!(
+            parse(">>> ```js\nquoted()\n```"),
+            [Node::BlockQuote {
+                children: vec![code(Some("js"), "quoted()")]
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_block_constructs() {
+        assert_eq!(
+            parse("# Title\n- one\n- two\n3. three"),
+            [
+                Node::Heading {
+                    level: 1,
+                    children: vec![text("Title")]
+                },
+                Node::List {
+                    ordered: false,
+                    start: None,
+                    items: vec![vec![text("one")], vec![text("two")]]
+                },
+                Node::List {
+                    ordered: true,
+                    start: Some(3),
+                    items: vec![vec![text("three")]]
+                },
+            ]
+        );
+        assert_eq!(
+            parse("a\n\nb"),
+            [text("a"), Node::LineBreak, Node::LineBreak, text("b")]
+        );
+    }
+
+    #[test]
+    fn parses_spoilers_links_and_urls() {
+        assert_eq!(
+            parse("||secret||"),
+            [Node::Spoiler {
+                children: vec![text("secret")]
+            }]
+        );
+        assert_eq!(
+            parse("[the **docs**](<https://example.com/a>)"),
+            [Node::Link {
+                url: "https://example.com/a".to_string(),
+                children: vec![
+                    text("the "),
+                    Node::Bold {
+                        children: vec![text("docs")]
+                    }
+                ]
+            }]
+        );
+        assert_eq!(
+            parse("see https://en.wikipedia.org/wiki/Rust_(language)."),
+            [
+                text("see "),
+                Node::Url {
+                    url: "https://en.wikipedia.org/wiki/Rust_(language)".to_string()
+                },
+                text("."),
+            ]
+        );
+        // Masked links need a real URL
+        assert_eq!(
+            parse("[x](javascript:alert)"),
+            [text("[x](javascript:alert)")]
+        );
+    }
+
+    #[test]
+    fn parses_mentions_emoji_and_timestamps() {
+        assert_eq!(
+            parse("<@1> <@!2> <@&3> <#4> @everyone @here"),
+            [
+                Node::UserMention { id: Snowflake(1) },
+                text(" "),
+                Node::UserMention { id: Snowflake(2) },
+                text(" "),
+                Node::RoleMention { id: Snowflake(3) },
+                text(" "),
+                Node::ChannelMention { id: Snowflake(4) },
+                text(" "),
+                Node::Everyone,
+                text(" "),
+                Node::Here,
+            ]
+        );
+        assert_eq!(
+            parse("<a:party_parrot:5><t:1700000000:R>"),
+            [
+                Node::CustomEmoji {
+                    name: "party_parrot".to_string(),
+                    id: Snowflake(5),
+                    animated: true
+                },
+                Node::Timestamp {
+                    timestamp: 1_700_000_000,
+                    style: Some('R')
+                },
+            ]
+        );
+        assert_eq!(parse("<@abc>"), [text("<@abc>")]);
+    }
+}
@@ -0,0 +1,223 @@
+use serde::Serialize;
+use serde_json::json;
+use tauri::State;
+use tracing::{error, info};
+
+use super::{
+    cache::{DiscordCache, GuildCache, MemberListRow},
+    gateway::OP_GUILD_SUBSCRIPTIONS,
+    models::Member,
+    permissions::capabilities_for,
+    snowflake::Snowflake,
+};
+use crate::AppState;
+
+// Members requested per subscription; the sidebar shows the top of the list
+const MEMBER_LIST_RANGE: [u32; 2] = [0, 99];
+
+/// One section of the member sidebar, in display order.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberListGroup {
+    // Role id for hoisted roles, otherwise "online" / "offline"
+    pub id: String,
+    pub name: String,
+    pub members: Vec<MemberListEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberListEntry {
+    pub user_id: Snowflake,
+    pub display_name: String,
+    pub username: String,
+    // Hash under /avatars/{user_id}/
+    pub avatar: Option<String>,
+    // Per-guild avatar, under /guilds/{guild_id}/users/{user_id}/avatars/
+    pub guild_avatar: Option<String>,
+    pub status: String,
+    // Colour of the highest coloured role, if any
+    pub color: Option<u32>,
+    pub bot: bool,
+}
+
+fn member_entry(guild: &GuildCache, user_id: Snowflake, member: &Member) -> MemberListEntry {
+    let color = member
+        .roles
+        .iter()
+        .filter_map(|id| guild.roles.get(id))
+        .filter(|r| r.color != 0)
+        .max_by_key(|r| r.position)
+        .map(|r| r.color);
+    let user = member.user.as_ref();
+    MemberListEntry {
+        user_id,
+        display_name: member.display_name(),
+        username: user.map(|u| u.username.clone()).unwrap_or_default(),
+        avatar: user.and_then(|u| u.avatar.clone()),
+        guild_avatar: member.avatar.clone(),
+        status: guild.status_of(user_id).to_string(),
+        color,
+        bot: user.is_some_and(|u| u.bot),
+    }
+}
+
+fn group_name(guild: &GuildCache, id: &str) -> String {
+    match id {
+        "online" => "Online".to_string(),
+        "offline" => "Offline".to_string(),
+        _ => id
+            .parse::<Snowflake>()
+            .ok()
+            .and_then(|id| guild.roles.get(&id))
+            .map(|r| r.name.clone())
+            .unwrap_or_default(),
+    }
+}
+
+/// Groups members the way the Discord client does: online members under their
+/// highest hoisted role (or "Online"), everyone else under "Offline". Uses
+/// Discord's own ordering once GUILD_MEMBER_LIST_UPDATE has sent one.
+pub fn build_member_list(guild: &GuildCache) -> Vec<MemberListGroup> {
+    if !guild.member_list.is_empty() {
+        return build_subscribed_list(guild);
+    }
+
+    let mut hoisted: Vec<_> = guild.roles.values().filter(|r| r.hoist).collect();
+    hoisted.sort_by(|a, b| b.position.cmp(&a.position).then(a.id.cmp(&b.id)));
+
+    let mut groups: Vec<MemberListGroup> = hoisted
+        .iter()
+        .map(|role| MemberListGroup {
+            id: role.id.to_string(),
+            name: role.name.clone(),
+            members: Vec::new(),
+        })
+        .chain(["online", "offline"].into_iter().map(|id| MemberListGroup {
+            id: id.to_string(),
+            name: group_name(guild, id),
+            members: Vec::new(),
+        }))
+        .collect();
+    let online_idx = hoisted.len();
+    let offline_idx = online_idx + 1;
+
+    for (user_id, member) in &guild.members {
+        let group_idx = if guild.status_of(*user_id) == "offline" {
+            offline_idx
+        } else {
+            member
+                .roles
+                .iter()
+                .filter_map(|id| guild.roles.get(id))
+                .filter(|r| r.hoist)
+                .max_by_key(|r| r.position)
+                .and_then(|top| hoisted.iter().position(|r| r.id == top.id))
+                .unwrap_or(online_idx)
+        };
+        groups[group_idx]
+            .members
+            .push(member_entry(guild, *user_id, member));
+    }
+
+    for group in &mut groups {
+        group
+            .members
+            .sort_by_cached_key(|m| (m.display_name.to_lowercase(), m.user_id));
+    }
+    groups.retain(|g| !g.members.is_empty());
+    groups
+}
+
+/// The list as Discord ordered it: each group header followed by its members.
+fn build_subscribed_list(guild: &GuildCache) -> Vec<MemberListGroup> {
+    let mut groups: Vec<MemberListGroup> = Vec::new();
+    for row in &guild.member_list {
+        match row {
+            MemberListRow::Group { id, .. } => groups.push(MemberListGroup {
+                id: id.clone(),
+                name: group_name(guild, id),
+                members: Vec::new(),
+            }),
+            MemberListRow::Member(user_id) => {
+                let (Some(group), Some(member)) = (groups.last_mut(), guild.members.get(user_id))
+                else {
+                    continue;
+                };
+                group.members.push(member_entry(guild, *user_id, member));
+            }
+        }
+    }
+    groups.retain(|g| !g.members.is_empty());
+    groups
+}
+
+/// The member list is per channel, since it only shows who can see it.
+/// Without a channel, the first text channel we can view stands in.
+fn list_channel(cache: &DiscordCache, guild: &GuildCache) -> Option<Snowflake> {
+    guild
+        .channels
+        .values()
+        .filter(|c| matches!(c.kind, 0 | 5))
+        .filter(|c| capabilities_for(cache, c.id).is_none_or(|caps| caps.view))
+        .min_by_key(|c| (c.position, c.id))
+        .map(|c| c.id)
+}
+
+/// Returns the grouped member list for a guild. User accounts cannot request
+/// the full member list (op 8), so this subscribes to the sidebar of
+/// `channel_id` with op 14; the frontend is told to refetch through
+/// `GuildMembersUpdate` events as GUILD_MEMBER_LIST_UPDATE arrives.
+#[tauri::command]
+pub async fn fetch_guild_members(
+    state: State<'_, AppState>,
+    guild_id: Snowflake,
+    channel_id: Option<Snowflake>,
+) -> Result<Vec<MemberListGroup>, String> {
+    let subscribe = {
+        let mut cache = state.cache.lock().await;
+        let guild = cache
+            .guild(guild_id)
+            .ok_or_else(|| "unknown_guild".to_string())?;
+        let channel_id = channel_id.or_else(|| list_channel(&cache, guild));
+        let guild = cache
+            .guilds
+            .get_mut(&guild_id)
+            .ok_or_else(|| "unknown_guild".to_string())?;
+        match channel_id {
+            Some(id) if guild.member_list_channel != Some(id) => {
+                guild.member_list_channel = Some(id);
+                guild.member_list.clear();
+                Some(id)
+            }
+            _ => None,
+        }
+    };
+
+    if let Some(channel_id) = subscribe {
+        info!(
+            "Subscribing to the member list of guild {} in channel {}",
+            guild_id, channel_id
+        );
+        let request = json!({
+            "op": OP_GUILD_SUBSCRIPTIONS,
+            "d": {
+                "guild_id": guild_id,
+                "typing": true,
+                "activities": true,
+                "threads": true,
+                "channels": { channel_id.to_string(): [MEMBER_LIST_RANGE] }
+            }
+        });
+        if let Err(e) = state.gateway.lock().await.send(request).await {
+            error!("Failed to subscribe to guild members: {}", e);
+            if let Some(guild) = state.cache.lock().await.guilds.get_mut(&guild_id) {
+                guild.member_list_channel = None;
+            }
+        }
+    }
+
+    let cache = state.cache.lock().await;
+    let guild = cache
+        .guild(guild_id)
+        .ok_or_else(|| "unknown_guild".to_string())?;
+    Ok(build_member_list(guild))
+}
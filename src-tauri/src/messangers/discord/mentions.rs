@@ -0,0 +1,552 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use futures::{StreamExt, stream};
+use serde::Serialize;
+use serde_json::{Value, json};
+use tauri::{AppHandle, Manager, Runtime};
+use tokio::time::Duration;
+use tracing::{error, warn};
+
+use super::{
+    api::fetch_user,
+    cache::{DiscordCache, GuildCache},
+    gateway::{GatewayEvent, emit},
+    links::{LinkPreview, LinkPreviews, cached_previews, fetch_linked},
+    markdown::{self, Node},
+    models::User,
+    snowflake::Snowflake,
+};
+use crate::AppState;
+
+// Messages kept waiting for lookups; the oldest are dropped first
+const MAX_WAITING: usize = 256;
+// Lookups sent at the same time
+const LOOKUP_CONCURRENCY: usize = 4;
+// Rate limited lookups are tried again after 2s, 4s, ... up to this many
+// attempts in all
+const MAX_LOOKUP_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedUser {
+    pub id: Snowflake,
+    pub display_name: String,
+    pub username: String,
+    pub avatar: Option<String>,
+    // Colour of the member's highest coloured role in this guild
+    pub color: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedChannel {
+    pub id: Snowflake,
+    pub name: Option<String>,
+    pub guild_id: Option<Snowflake>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedRole {
+    pub id: Snowflake,
+    pub name: String,
+    pub color: Option<u32>,
+}
+
+/// Names for the `<@id>`, `<#id>` and `<@&id>` mentions of a message and
+/// previews of its Discord links, sent to the UI as the message's `resolved`
+/// field. Unknown ids are left out.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResolvedEntities {
+    pub users: HashMap<Snowflake, ResolvedUser>,
+    pub channels: HashMap<Snowflake, ResolvedChannel>,
+    pub roles: HashMap<Snowflake, ResolvedRole>,
+    pub links: Vec<LinkPreview>,
+}
+
+#[derive(Debug, Default)]
+struct MentionIds {
+    users: BTreeSet<Snowflake>,
+    channels: BTreeSet<Snowflake>,
+    roles: BTreeSet<Snowflake>,
+    urls: Vec<String>,
+}
+
+/// Collects mentions and URLs from the parsed content, so ids inside code are
+/// ignored.
+fn collect_mentions(nodes: &[Node], ids: &mut MentionIds) {
+    for node in nodes {
+        match node {
+            Node::UserMention { id } => {
+                ids.users.insert(*id);
+            }
+            Node::ChannelMention { id } => {
+                ids.channels.insert(*id);
+            }
+            Node::RoleMention { id } => {
+                ids.roles.insert(*id);
+            }
+            Node::Url { url } => ids.urls.push(url.clone()),
+            Node::Link { url, children } => {
+                ids.urls.push(url.clone());
+                collect_mentions(children, ids);
+            }
+            Node::Bold { children }
+            | Node::Italic { children }
+            | Node::Underline { children }
+            | Node::Strikethrough { children }
+            | Node::Spoiler { children }
+            | Node::BlockQuote { children }
+            | Node::Heading { children, .. } => collect_mentions(children, ids),
+            Node::List { items, .. } => {
+                for item in items {
+                    collect_mentions(item, ids);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(super) fn resolve_user(user: &User, guild: Option<&GuildCache>) -> ResolvedUser {
+    let member = guild.and_then(|g| g.members.get(&user.id));
+    let color = guild.zip(member).and_then(|(guild, member)| {
+        member
+            .roles
+            .iter()
+            .filter_map(|id| guild.roles.get(id))
+            .filter(|r| r.color != 0)
+            .max_by_key(|r| r.position)
+            .map(|r| r.color)
+    });
+    ResolvedUser {
+        id: user.id,
+        display_name: member
+            .and_then(|m| m.nick.clone())
+            .or_else(|| user.global_name.clone())
+            .unwrap_or_else(|| user.username.clone()),
+        username: user.username.clone(),
+        avatar: user.avatar.clone(),
+        color,
+    }
+}
+
+fn message_guild<'a>(cache: &'a DiscordCache, message: &Value) -> Option<&'a GuildCache> {
+    Snowflake::from_value(&message["guild_id"])
+        .and_then(|id| cache.guild(id))
+        .or_else(|| {
+            let channel_id = Snowflake::from_value(&message["channel_id"])?;
+            cache.channel(channel_id).map(|(guild, _)| guild)
+        })
+}
+
+/// Resolves what the caches know and returns what they could not find.
+fn resolve_cached(
+    cache: &DiscordCache,
+    previews: &LinkPreviews,
+    message: &Value,
+    ids: &MentionIds,
+) -> (ResolvedEntities, Vec<Lookup>) {
+    let guild = message_guild(cache, message);
+
+    // Message payloads carry the users they mention
+    let mentioned: HashMap<Snowflake, User> = message["mentions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|u| serde_json::from_value::<User>(u.clone()).ok())
+        .map(|u| (u.id, u))
+        .collect();
+
+    let mut resolved = ResolvedEntities::default();
+    let mut missing = Vec::new();
+
+    for &id in &ids.users {
+        let user = mentioned
+            .get(&id)
+            .or_else(|| guild.and_then(|g| g.members.get(&id)?.user.as_ref()))
+            .or_else(|| cache.users.get(&id));
+        match user {
+            Some(user) => {
+                resolved.users.insert(id, resolve_user(user, guild));
+            }
+            None => missing.push(Lookup::User(id)),
+        }
+    }
+
+    for &id in &ids.channels {
+        if let Some(channel) = cache.any_channel(id) {
+            let guild_id = cache.channel(id).map(|(guild, _)| guild.id);
+            resolved.channels.insert(
+                id,
+                ResolvedChannel {
+                    id,
+                    name: channel.name.clone(),
+                    guild_id,
+                },
+            );
+        }
+    }
+
+    for &id in &ids.roles {
+        if let Some(role) = guild.and_then(|g| g.roles.get(&id)) {
+            resolved.roles.insert(
+                id,
+                ResolvedRole {
+                    id,
+                    name: role.name.clone(),
+                    color: (role.color != 0).then_some(role.color),
+                },
+            );
+        }
+    }
+
+    let (links, missing_links) = cached_previews(cache, previews, &ids.urls);
+    resolved.links = links;
+    missing.extend(
+        missing_links
+            .into_iter()
+            .map(|(channel_id, message_id)| Lookup::Message(channel_id, message_id)),
+    );
+
+    (resolved, missing)
+}
+
+fn mention_ids(message: &Value) -> MentionIds {
+    let mut ids = MentionIds::default();
+    collect_mentions(
+        &markdown::parse(message["content"].as_str().unwrap_or_default()),
+        &mut ids,
+    );
+    ids
+}
+
+/// Something a message refers to that the caches did not know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Lookup {
+    User(Snowflake),
+    // A linked message, by channel and message id
+    Message(Snowflake, Snowflake),
+}
+
+enum Fetched {
+    User(User),
+    // None when the linked message no longer exists
+    Message(Option<Value>),
+}
+
+/// Users and linked messages being fetched in the background, and the
+/// messages waiting for them. Users Discord would not return are remembered,
+/// so they are not asked for again; linked messages we cannot read are
+/// remembered by `LinkPreviews`.
+#[derive(Debug, Default)]
+pub struct Lookups {
+    in_flight: HashSet<Lookup>,
+    failed: HashSet<Lookup>,
+    // Message id -> the message and what it waits for
+    waiting: HashMap<Snowflake, (Value, Vec<Lookup>)>,
+    order: VecDeque<Snowflake>,
+}
+
+impl Lookups {
+    /// Holds a message until what it is missing was looked up. Returns the
+    /// lookups that are not running yet.
+    fn wait(&mut self, message: &Value, missing: Vec<Lookup>) -> Vec<Lookup> {
+        let missing: Vec<Lookup> = missing
+            .into_iter()
+            .filter(|lookup| !self.failed.contains(lookup))
+            .collect();
+        let Some(message_id) = Snowflake::from_value(&message["id"]) else {
+            return Vec::new();
+        };
+        if missing.is_empty() {
+            return Vec::new();
+        }
+
+        let new = missing
+            .iter()
+            .copied()
+            .filter(|lookup| self.in_flight.insert(*lookup))
+            .collect();
+        if self
+            .waiting
+            .insert(message_id, (message.clone(), missing))
+            .is_none()
+        {
+            self.order.push_back(message_id);
+        }
+        while self.order.len() > MAX_WAITING {
+            if let Some(oldest) = self.order.pop_front() {
+                self.waiting.remove(&oldest);
+            }
+        }
+        new
+    }
+
+    /// Ends a lookup. `failed` lookups are not run again.
+    fn finish(&mut self, lookup: Lookup, failed: bool) {
+        self.in_flight.remove(&lookup);
+        if failed {
+            self.failed.insert(lookup);
+        }
+    }
+
+    /// Takes the messages that no longer wait for any lookup.
+    fn settled(&mut self) -> Vec<Value> {
+        let ids: Vec<Snowflake> = self
+            .waiting
+            .iter()
+            .filter(|(_, (_, missing))| missing.iter().all(|l| !self.in_flight.contains(l)))
+            .map(|(id, _)| *id)
+            .collect();
+        self.order.retain(|id| !ids.contains(id));
+        ids.iter()
+            .filter_map(|id| self.waiting.remove(id))
+            .map(|(message, _)| message)
+            .collect()
+    }
+}
+
+/// The `MessageResolved` payload for a message whose lookups finished.
+fn resolved_update(cache: &DiscordCache, previews: &LinkPreviews, message: &Value) -> Value {
+    let (resolved, _) = resolve_cached(cache, previews, message, &mention_ids(message));
+    json!({
+        "id": message["id"],
+        "channel_id": message["channel_id"],
+        "resolved": resolved,
+    })
+}
+
+async fn fetch(state: &AppState, token: Option<&str>, lookup: Lookup) -> Result<Fetched, String> {
+    let token = token.ok_or_else(|| "not_authenticated".to_string())?;
+    match lookup {
+        Lookup::User(user_id) => fetch_user(state, token, user_id).await.map(Fetched::User),
+        Lookup::Message(channel_id, message_id) => {
+            fetch_linked(state, token, channel_id, message_id)
+                .await
+                .map(Fetched::Message)
+        }
+    }
+}
+
+/// Whether a failed lookup will fail again: the user or message is gone or
+/// hidden from us. Rate limits and network errors are not.
+fn is_permanent(error: &str) -> bool {
+    error.starts_with("http_4") && error != "http_429"
+}
+
+/// Runs lookups the caches could not answer, then sends the new `resolved`
+/// of every message that was waiting for them. Rate limited lookups are
+/// waited out and run again, keeping their messages waiting.
+async fn run_lookups<R: Runtime>(app_handle: AppHandle<R>, mut pending: Vec<Lookup>) {
+    let state = app_handle.state::<AppState>();
+
+    for attempt in 1..=MAX_LOOKUP_ATTEMPTS {
+        let token = state.token.lock().await.clone();
+        let results: Vec<(Lookup, Result<Fetched, String>)> = stream::iter(pending)
+            .map(|lookup| {
+                let (state, token) = (&*state, token.as_deref());
+                async move { (lookup, fetch(state, token, lookup).await) }
+            })
+            .buffer_unordered(LOOKUP_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut rate_limited = Vec::new();
+        let updates: Vec<Value> = {
+            let mut cache = state.cache.lock().await;
+            let mut previews = state.link_previews.lock().await;
+            let mut lookups = state.lookups.lock().await;
+            for (lookup, result) in results {
+                let mut failed = false;
+                match (lookup, result) {
+                    (Lookup::User(user_id), Ok(Fetched::User(user))) => {
+                        cache.users.insert(user_id, user);
+                    }
+                    (Lookup::Message(_, message_id), Ok(Fetched::Message(message))) => {
+                        previews.store(&cache, message_id, message.as_ref());
+                    }
+                    (_, Ok(_)) => {}
+                    (lookup, Err(e)) if e == "http_429" && attempt < MAX_LOOKUP_ATTEMPTS => {
+                        rate_limited.push(lookup);
+                        continue;
+                    }
+                    // Unknown users and messages we cannot read are
+                    // remembered, network errors are retried when they come
+                    // up again
+                    (lookup, Err(e)) => {
+                        warn!("Could not resolve {:?}: {}", lookup, e);
+                        if is_permanent(&e) {
+                            match lookup {
+                                Lookup::User(_) => failed = true,
+                                Lookup::Message(_, message_id) => {
+                                    previews.store(&cache, message_id, None)
+                                }
+                            }
+                        }
+                    }
+                }
+                lookups.finish(lookup, failed);
+            }
+            lookups
+                .settled()
+                .iter()
+                .map(|message| resolved_update(&cache, &previews, message))
+                .collect()
+        };
+
+        for update in updates {
+            if let Err(e) = emit(&app_handle, GatewayEvent::MessageResolved(update)) {
+                error!("{}", e);
+            }
+        }
+        if rate_limited.is_empty() {
+            return;
+        }
+        warn!("Rate limited, retrying {} lookups", rate_limited.len());
+        tokio::time::sleep(Duration::from_millis(1000 << attempt)).await;
+        pending = rate_limited;
+    }
+}
+
+/// Adds the `resolved` field to messages from what the caches know. Unknown
+/// users and linked messages are looked up in the background and sent in a
+/// `MessageResolved` event, so the gateway never waits on the API.
+pub async fn attach_resolved<R: Runtime>(app_handle: &AppHandle<R>, messages: &mut [Value]) {
+    let state = app_handle.state::<AppState>();
+    let mut lookups = Vec::new();
+    for message in messages.iter_mut() {
+        let missing = attach_from_cache(&state, message).await;
+        if !missing.is_empty() {
+            lookups.extend(state.lookups.lock().await.wait(message, missing));
+        }
+    }
+    if !lookups.is_empty() {
+        tauri::async_runtime::spawn(run_lookups(app_handle.clone(), lookups));
+    }
+}
+
+/// Attaches `resolved` from the caches alone, without looking anything up;
+/// for replayed messages, which must not cause requests.
+pub async fn attach_cached(state: &AppState, messages: &mut [Value]) {
+    for message in messages.iter_mut() {
+        attach_from_cache(state, message).await;
+    }
+}
+
+/// Sets `resolved` from the caches and returns what is missing from them.
+async fn attach_from_cache(state: &AppState, message: &mut Value) -> Vec<Lookup> {
+    let ids = mention_ids(message);
+    let (resolved, missing) = {
+        let cache = state.cache.lock().await;
+        let previews = state.link_previews.lock().await;
+        resolve_cached(&cache, &previews, message, &ids)
+    };
+    if let Ok(resolved) = serde_json::to_value(resolved) {
+        message["resolved"] = resolved;
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn message(id: u64, content: &str) -> Value {
+        json!({ "id": id.to_string(), "channel_id": "10", "content": content })
+    }
+
+    fn user(id: u64) -> Lookup {
+        Lookup::User(Snowflake(id))
+    }
+
+    #[test]
+    fn rate_limits_are_not_permanent() {
+        assert!(is_permanent("http_404"));
+        assert!(is_permanent("http_403"));
+        assert!(!is_permanent("http_429"));
+        assert!(!is_permanent("http_502"));
+        assert!(!is_permanent("request_failed"));
+    }
+
+    #[test]
+    fn looks_up_each_user_once() {
+        let mut lookups = Lookups::default();
+        assert_eq!(
+            lookups.wait(&message(1, "<@5> <@6>"), vec![user(5), user(6)]),
+            vec![user(5), user(6)]
+        );
+        assert_eq!(
+            lookups.wait(&message(2, "<@6> <@7>"), vec![user(6), user(7)]),
+            vec![user(7)]
+        );
+    }
+
+    #[test]
+    fn settles_messages_once_their_users_are_done() {
+        let mut lookups = Lookups::default();
+        lookups.wait(&message(1, "<@5>"), vec![user(5)]);
+        lookups.wait(&message(2, "<@5> <@6>"), vec![user(5), user(6)]);
+
+        lookups.finish(user(5), false);
+        let settled = lookups.settled();
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0]["id"], "1");
+
+        lookups.finish(user(6), false);
+        let settled = lookups.settled();
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0]["id"], "2");
+        assert!(lookups.settled().is_empty());
+    }
+
+    #[test]
+    fn does_not_retry_failed_users() {
+        let mut lookups = Lookups::default();
+        lookups.wait(&message(1, "<@5> <@6>"), vec![user(5), user(6)]);
+        lookups.finish(user(5), true);
+        lookups.finish(user(6), false);
+        lookups.settled();
+
+        // Failed lookups are skipped, network errors are tried again
+        assert_eq!(
+            lookups.wait(&message(2, "<@5> <@6>"), vec![user(5), user(6)]),
+            vec![user(6)]
+        );
+        assert!(lookups.wait(&message(3, "<@5>"), vec![user(5)]).is_empty());
+    }
+
+    #[test]
+    fn resolves_after_the_lookups() {
+        let mut cache = DiscordCache::default();
+        let mut previews = LinkPreviews::default();
+        let waiting = message(1, "hi <@5> https://discord.com/channels/@me/10/42");
+
+        let (resolved, missing) =
+            resolve_cached(&cache, &previews, &waiting, &mention_ids(&waiting));
+        assert!(resolved.users.is_empty());
+        assert!(resolved.links[0].message.is_none());
+        assert_eq!(
+            missing,
+            vec![user(5), Lookup::Message(Snowflake(10), Snowflake(42))]
+        );
+
+        let ann: User = serde_json::from_value(json!({ "id": "5", "username": "ann" })).unwrap();
+        cache.users.insert(ann.id, ann);
+        let linked = json!({
+            "id": "42",
+            "channel_id": "10",
+            "content": "the linked message",
+            "author": { "id": "5", "username": "ann" },
+        });
+        previews.store(&cache, Snowflake(42), Some(&linked));
+
+        let update = resolved_update(&cache, &previews, &waiting);
+        assert_eq!(update["id"], "1");
+        assert_eq!(update["channel_id"], "10");
+        assert_eq!(update["resolved"]["users"]["5"]["username"], "ann");
+        assert_eq!(
+            update["resolved"]["links"][0]["message"]["snippet"],
+            "the linked message"
+        );
+    }
+}
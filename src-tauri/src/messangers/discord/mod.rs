@@ -1,2 +1,23 @@
+pub mod account;
 pub mod api;
+pub mod auth;
+pub mod cache;
+pub mod channel_tree;
+pub mod composer;
+pub mod endpoints;
 pub mod gateway;
+pub mod history;
+pub mod links;
+pub mod login;
+pub mod markdown;
+pub mod members;
+pub mod mentions;
+pub mod models;
+pub mod notifications;
+pub mod outbox;
+pub mod permissions;
+pub mod recorder;
+pub mod remote_auth;
+pub mod slowmode;
+pub mod snowflake;
+pub mod unread;
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use super::snowflake::Snowflake;
+
+// Typed subsets of the Discord objects we keep in the cache.
+// https://discord.com/developers/docs/resources/user#user-object
+// Every optional field is defaulted since READY, GUILD_CREATE and REST all
+// send slightly different shapes of the same object.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub id: Snowflake,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub global_name: Option<String>,
+    #[serde(default)]
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub bot: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Role {
+    pub id: Snowflake,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub color: u32,
+    #[serde(default)]
+    pub hoist: bool,
+    #[serde(default)]
+    pub position: i64,
+    // Permission bitset, serialized by Discord as a decimal string.
+    #[serde(default)]
+    pub permissions: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Member {
+    #[serde(default)]
+    pub user: Option<User>,
+    #[serde(default)]
+    pub nick: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<Snowflake>,
+    #[serde(default)]
+    pub avatar: Option<String>,
+}
+
+impl Member {
+    pub fn user_id(&self) -> Option<Snowflake> {
+        self.user.as_ref().map(|u| u.id)
+    }
+
+    /// Name shown in the member list: nickname, then global name, then username.
+    pub fn display_name(&self) -> String {
+        if let Some(nick) = &self.nick {
+            return nick.clone();
+        }
+        match &self.user {
+            Some(user) => user
+                .global_name
+                .clone()
+                .unwrap_or_else(|| user.username.clone()),
+            None => String::new(),
+        }
+    }
+}
+
+// https://discord.com/developers/docs/resources/channel#channel-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Channel {
+    pub id: Snowflake,
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(default)]
+    pub guild_id: Option<Snowflake>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub parent_id: Option<Snowflake>,
+    #[serde(default)]
+    pub permission_overwrites: Vec<PermissionOverwrite>,
+    #[serde(default)]
+    pub rate_limit_per_user: Option<u32>,
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub nsfw: bool,
+    #[serde(default)]
+    pub last_message_id: Option<Snowflake>,
+    // Only set for DMs and group DMs
+    #[serde(default)]
+    pub recipients: Vec<User>,
+}
+
+impl Channel {
+    pub fn is_thread(&self) -> bool {
+        matches!(self.kind, 10..=12)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionOverwrite {
+    pub id: Snowflake,
+    // 0 = role, 1 = member
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(default)]
+    pub allow: String,
+    #[serde(default)]
+    pub deny: String,
+}
+
+// https://discord.com/developers/docs/resources/emoji#emoji-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Emoji {
+    // Unicode emoji have no id
+    #[serde(default)]
+    pub id: Option<Snowflake>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub animated: bool,
+    // False when the emoji was lost with a boost level
+    #[serde(default = "available_default")]
+    pub available: bool,
+}
+
+fn available_default() -> bool {
+    true
+}
+
+/// Per-channel read marker from READY `read_state` and MESSAGE_ACK.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReadState {
+    pub last_message_id: Option<Snowflake>,
+    #[serde(default)]
+    pub mention_count: u32,
+}
@@ -0,0 +1,512 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use chrono::{DateTime, Local, Timelike, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::State;
+use tracing::{error, warn};
+
+use super::{cache::DiscordCache, snowflake::Snowflake};
+use crate::AppState;
+
+/// User-configurable notification rules, persisted as JSON in the app data
+/// directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationRules {
+    pub direct_messages: bool,
+    pub direct_mentions: bool,
+    pub role_mentions: bool,
+    pub everyone_mentions: bool,
+    // Case-insensitive whole-word highlights; may start or end with
+    // punctuation, like "c++"
+    pub keywords: Vec<String>,
+    // Regular expressions matched against message content
+    pub patterns: Vec<String>,
+    pub muted_guilds: HashMap<Snowflake, Mute>,
+    pub muted_channels: HashMap<Snowflake, Mute>,
+    pub do_not_disturb: Option<DndSchedule>,
+}
+
+impl Default for NotificationRules {
+    fn default() -> Self {
+        Self {
+            direct_messages: true,
+            direct_mentions: true,
+            role_mentions: true,
+            everyone_mentions: true,
+            keywords: Vec::new(),
+            patterns: Vec::new(),
+            muted_guilds: HashMap::new(),
+            muted_channels: HashMap::new(),
+            do_not_disturb: None,
+        }
+    }
+}
+
+/// A server mute still lets direct mentions through, like Discord's own; a
+/// channel mute, DMs included, silences everything. `until` is a unix
+/// timestamp in milliseconds; `None` mutes until turned off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mute {
+    pub until: Option<i64>,
+}
+
+impl Mute {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.until
+            .is_none_or(|until| now.timestamp_millis() < until)
+    }
+}
+
+/// Daily quiet hours in local time, as minutes since midnight. A window whose
+/// end is before its start wraps past midnight.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DndSchedule {
+    pub start_minute: u16,
+    pub end_minute: u16,
+}
+
+impl DndSchedule {
+    fn is_active(&self, now: DateTime<Local>) -> bool {
+        let minute = (now.hour() * 60 + now.minute()) as u16;
+        if self.start_minute <= self.end_minute {
+            (self.start_minute..self.end_minute).contains(&minute)
+        } else {
+            minute >= self.start_minute || minute < self.end_minute
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotifyReason {
+    DirectMessage,
+    DirectMention,
+    RoleMention { role_id: Snowflake },
+    Everyone,
+    Keyword { keyword: String },
+    Pattern { pattern: String },
+}
+
+impl NotifyReason {
+    /// Only direct mentions get through a server mute.
+    fn bypasses_guild_mute(&self) -> bool {
+        matches!(self, Self::DirectMention)
+    }
+}
+
+/// Payload of the `Notify` gateway event.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub channel_id: Snowflake,
+    pub guild_id: Option<Snowflake>,
+    pub message_id: Snowflake,
+    pub author: String,
+    pub content: String,
+    pub reason: NotifyReason,
+}
+
+pub struct NotificationEngine {
+    rules: NotificationRules,
+    keywords: Vec<(String, Regex)>,
+    patterns: Vec<(String, Regex)>,
+    path: Option<PathBuf>,
+}
+
+impl NotificationEngine {
+    pub fn new(rules: NotificationRules, path: Option<PathBuf>) -> Self {
+        let mut engine = Self {
+            rules: NotificationRules::default(),
+            keywords: Vec::new(),
+            patterns: Vec::new(),
+            path,
+        };
+        engine.set_rules(rules);
+        engine
+    }
+
+    /// Loads persisted rules, falling back to defaults.
+    pub async fn load(path: PathBuf) -> Self {
+        let rules = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                error!("Failed to parse notification rules: {}", e);
+                NotificationRules::default()
+            }),
+            Err(_) => NotificationRules::default(),
+        };
+        Self::new(rules, Some(path))
+    }
+
+    pub fn rules(&self) -> &NotificationRules {
+        &self.rules
+    }
+
+    /// Replaces the rules and recompiles highlights. Invalid patterns are
+    /// logged and skipped.
+    pub fn set_rules(&mut self, rules: NotificationRules) {
+        self.keywords = rules
+            .keywords
+            .iter()
+            .filter(|k| !k.trim().is_empty())
+            .filter_map(|keyword| {
+                // Not `\b`, which never matches next to a keyword's punctuation
+                let whole_word = format!(r"(?:^|\W){}(?:\W|$)", regex::escape(keyword.trim()));
+                RegexBuilder::new(&whole_word)
+                    .case_insensitive(true)
+                    .build()
+                    .ok()
+                    .map(|re| (keyword.clone(), re))
+            })
+            .collect();
+        self.patterns = rules
+            .patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(re) => Some((pattern.clone(), re)),
+                Err(e) => {
+                    warn!("Ignoring invalid highlight pattern {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        self.rules = rules;
+    }
+
+    pub async fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| format!("Failed to create data dir: {}", e))?;
+        }
+        let json = serde_json::to_string_pretty(&self.rules)
+            .map_err(|e| format!("Failed to serialize rules: {}", e))?;
+        tokio::fs::write(path, json)
+            .await
+            .map_err(|e| format!("Failed to write rules: {}", e))
+    }
+
+    /// Decides whether a MESSAGE_CREATE should notify, and why. At most one
+    /// reason is returned, the most specific one.
+    pub fn evaluate(&self, message: &Value, cache: &DiscordCache) -> Option<Notification> {
+        let now = Utc::now();
+        let me = cache.current_user.as_ref()?;
+        let channel_id = Snowflake::from_value(&message["channel_id"])?;
+        if Snowflake::from_value(&message["author"]["id"]) == Some(me.id) {
+            return None;
+        }
+
+        let quiet_hours = self.rules.do_not_disturb.as_ref();
+        if quiet_hours.is_some_and(|dnd| dnd.is_active(now.with_timezone(&Local))) {
+            return None;
+        }
+
+        let reason = self.match_reason(message, cache, me.id)?;
+
+        let guild_id = cache.channel(channel_id).map(|(guild, _)| guild.id);
+        let channel_muted = self
+            .rules
+            .muted_channels
+            .get(&channel_id)
+            .is_some_and(|m| m.is_active(now));
+        let guild_muted = guild_id
+            .as_ref()
+            .and_then(|id| self.rules.muted_guilds.get(id))
+            .is_some_and(|m| m.is_active(now));
+        if channel_muted || (guild_muted && !reason.bypasses_guild_mute()) {
+            return None;
+        }
+
+        let author = &message["author"];
+        Some(Notification {
+            channel_id,
+            guild_id,
+            message_id: Snowflake::from_value(&message["id"]).unwrap_or_default(),
+            author: author["global_name"]
+                .as_str()
+                .or_else(|| author["username"].as_str())
+                .unwrap_or_default()
+                .to_string(),
+            content: message["content"].as_str().unwrap_or_default().to_string(),
+            reason,
+        })
+    }
+
+    fn match_reason(
+        &self,
+        message: &Value,
+        cache: &DiscordCache,
+        me: Snowflake,
+    ) -> Option<NotifyReason> {
+        let channel_id = Snowflake::from_value(&message["channel_id"])?;
+        let rules = &self.rules;
+
+        if cache.private_channels.contains_key(&channel_id) {
+            return rules.direct_messages.then_some(NotifyReason::DirectMessage);
+        }
+
+        let mentioned = message["mentions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|u| Snowflake::from_value(&u["id"]) == Some(me));
+        if rules.direct_mentions && mentioned {
+            return Some(NotifyReason::DirectMention);
+        }
+
+        if rules.role_mentions {
+            let my_roles = cache
+                .channel(channel_id)
+                .and_then(|(guild, _)| guild.members.get(&me))
+                .map(|m| m.roles.as_slice())
+                .unwrap_or_default();
+            let role = message["mention_roles"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Snowflake::from_value)
+                .find(|role| my_roles.contains(role));
+            if let Some(role_id) = role {
+                return Some(NotifyReason::RoleMention { role_id });
+            }
+        }
+
+        if rules.everyone_mentions && message["mention_everyone"].as_bool().unwrap_or(false) {
+            return Some(NotifyReason::Everyone);
+        }
+
+        let content = message["content"].as_str().unwrap_or_default();
+        if let Some((keyword, _)) = self.keywords.iter().find(|(_, re)| re.is_match(content)) {
+            return Some(NotifyReason::Keyword {
+                keyword: keyword.clone(),
+            });
+        }
+        if let Some((pattern, _)) = self.patterns.iter().find(|(_, re)| re.is_match(content)) {
+            return Some(NotifyReason::Pattern {
+                pattern: pattern.clone(),
+            });
+        }
+
+        None
+    }
+}
+
+impl Default for NotificationEngine {
+    fn default() -> Self {
+        Self::new(NotificationRules::default(), None)
+    }
+}
+
+fn mute_for(duration_secs: Option<u64>) -> Mute {
+    Mute {
+        until: duration_secs.map(|secs| Utc::now().timestamp_millis() + secs as i64 * 1000),
+    }
+}
+
+#[tauri::command]
+pub async fn get_notification_rules(
+    state: State<'_, AppState>,
+) -> Result<NotificationRules, String> {
+    Ok(state.notifications.lock().await.rules().clone())
+}
+
+#[tauri::command]
+pub async fn set_notification_rules(
+    state: State<'_, AppState>,
+    rules: NotificationRules,
+) -> Result<(), String> {
+    let mut engine = state.notifications.lock().await;
+    engine.set_rules(rules);
+    engine.save().await
+}
+
+/// Mutes a guild for `duration_secs`, or until unmuted when omitted.
+#[tauri::command]
+pub async fn set_guild_mute(
+    state: State<'_, AppState>,
+    guild_id: Snowflake,
+    muted: bool,
+    duration_secs: Option<u64>,
+) -> Result<(), String> {
+    let mut engine = state.notifications.lock().await;
+    let mut rules = engine.rules().clone();
+    if muted {
+        rules.muted_guilds.insert(guild_id, mute_for(duration_secs));
+    } else {
+        rules.muted_guilds.remove(&guild_id);
+    }
+    engine.set_rules(rules);
+    engine.save().await
+}
+
+/// Mutes a channel for `duration_secs`, or until unmuted when omitted.
+#[tauri::command]
+pub async fn set_channel_mute(
+    state: State<'_, AppState>,
+    channel_id: Snowflake,
+    muted: bool,
+    duration_secs: Option<u64>,
+) -> Result<(), String> {
+    let mut engine = state.notifications.lock().await;
+    let mut rules = engine.rules().clone();
+    if muted {
+        rules
+            .muted_channels
+            .insert(channel_id, mute_for(duration_secs));
+    } else {
+        rules.muted_channels.remove(&channel_id);
+    }
+    engine.set_rules(rules);
+    engine.save().await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const ME: &str = "2";
+    const DM: Snowflake = Snowflake(5);
+    const GUILD: Snowflake = Snowflake(1);
+    const CHANNEL: Snowflake = Snowflake(10);
+    const ROLE: &str = "3";
+
+    fn cache() -> DiscordCache {
+        let mut cache = DiscordCache::default();
+        cache.apply_ready(&json!({
+            "user": { "id": ME },
+            "private_channels": [{ "id": DM, "type": 1 }],
+            "guilds": [{
+                "id": GUILD,
+                "channels": [{ "id": CHANNEL, "type": 0 }],
+                "members": [{ "user": { "id": ME }, "roles": [ROLE] }],
+            }],
+        }));
+        cache
+    }
+
+    fn message(channel_id: Snowflake, content: &str) -> Value {
+        json!({
+            "id": "100",
+            "channel_id": channel_id,
+            "author": { "id": "7", "username": "friend" },
+            "content": content,
+            "mentions": [],
+            "mention_roles": [],
+            "mention_everyone": false,
+        })
+    }
+
+    fn mention(channel_id: Snowflake) -> Value {
+        let mut message = message(channel_id, "hey");
+        message["mentions"] = json!([{ "id": ME }]);
+        message
+    }
+
+    fn engine(rules: NotificationRules) -> NotificationEngine {
+        NotificationEngine::new(rules, None)
+    }
+
+    fn reason(engine: &NotificationEngine, message: &Value) -> Option<NotifyReason> {
+        engine.evaluate(message, &cache()).map(|n| n.reason)
+    }
+
+    fn muted(mutes: &[Snowflake]) -> HashMap<Snowflake, Mute> {
+        mutes.iter().map(|&id| (id, Mute { until: None })).collect()
+    }
+
+    #[test]
+    fn notifies_for_dms_and_mentions() {
+        let engine = engine(NotificationRules::default());
+        assert!(matches!(
+            reason(&engine, &message(DM, "hi")),
+            Some(NotifyReason::DirectMessage)
+        ));
+        assert!(matches!(
+            reason(&engine, &mention(CHANNEL)),
+            Some(NotifyReason::DirectMention)
+        ));
+        let mut role_mention = message(CHANNEL, "mods?");
+        role_mention["mention_roles"] = json!([ROLE]);
+        assert!(matches!(
+            reason(&engine, &role_mention),
+            Some(NotifyReason::RoleMention { .. })
+        ));
+        assert!(reason(&engine, &message(CHANNEL, "chatter")).is_none());
+    }
+
+    #[test]
+    fn muted_dm_stays_silent() {
+        let engine = engine(NotificationRules {
+            muted_channels: muted(&[DM]),
+            ..Default::default()
+        });
+        assert!(reason(&engine, &message(DM, "hi")).is_none());
+    }
+
+    #[test]
+    fn guild_mute_lets_direct_mentions_through() {
+        let engine = engine(NotificationRules {
+            muted_guilds: muted(&[GUILD]),
+            ..Default::default()
+        });
+        assert!(reason(&engine, &mention(CHANNEL)).is_some());
+        let mut everyone = message(CHANNEL, "@everyone");
+        everyone["mention_everyone"] = json!(true);
+        assert!(reason(&engine, &everyone).is_none());
+    }
+
+    #[test]
+    fn channel_mute_silences_mentions() {
+        let engine = engine(NotificationRules {
+            muted_channels: muted(&[CHANNEL]),
+            ..Default::default()
+        });
+        assert!(reason(&engine, &mention(CHANNEL)).is_none());
+    }
+
+    #[test]
+    fn expired_mute_is_ignored() {
+        let engine = engine(NotificationRules {
+            muted_channels: HashMap::from([(CHANNEL, Mute { until: Some(0) })]),
+            ..Default::default()
+        });
+        assert!(reason(&engine, &mention(CHANNEL)).is_some());
+    }
+
+    #[test]
+    fn keywords_match_whole_words() {
+        let engine = engine(NotificationRules {
+            keywords: vec!["rust".to_string(), "c++".to_string(), ".NET".to_string()],
+            ..Default::default()
+        });
+        let keyword = |content: &str| match reason(&engine, &message(CHANNEL, content)) {
+            Some(NotifyReason::Keyword { keyword }) => Some(keyword),
+            _ => None,
+        };
+        assert_eq!(keyword("Rust is nice").as_deref(), Some("rust"));
+        assert_eq!(keyword("I trust you"), None);
+        assert_eq!(keyword("learning c++, slowly").as_deref(), Some("c++"));
+        assert_eq!(keyword("c++").as_deref(), Some("c++"));
+        assert_eq!(keyword("abc++"), None);
+        assert_eq!(keyword("moving to .net").as_deref(), Some(".NET"));
+        assert_eq!(keyword("asp.net"), None);
+    }
+
+    #[test]
+    fn invalid_patterns_are_skipped() {
+        let engine = engine(NotificationRules {
+            patterns: vec!["(".to_string(), r"deploy \d+".to_string()],
+            ..Default::default()
+        });
+        assert!(matches!(
+            reason(&engine, &message(CHANNEL, "deploy 42 done")),
+            Some(NotifyReason::Pattern { .. })
+        ));
+    }
+}
@@ -0,0 +1,649 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tauri::{AppHandle, Manager, Runtime, State};
+use tokio::{sync::Notify, time::Duration};
+use tracing::{debug, error, info, warn};
+
+use super::{
+    account::current_user_id,
+    api::{PostError, post_message, upload_attachment},
+    gateway::{GatewayEvent, emit},
+    mentions::attach_resolved,
+    slowmode::start_cooldown,
+    snowflake::Snowflake,
+};
+use crate::AppState;
+
+// Confirmed nonces remembered to drop the later of REST and gateway
+const DELIVERED_HISTORY: usize = 256;
+// Backoff for network and server errors: 2s, 4s, ... up to a minute
+const MAX_BACKOFF_MS: i64 = 60_000;
+
+static NONCE_INCREMENT: AtomicU64 = AtomicU64::new(0);
+
+/// A snowflake for the current time, like the nonces of the official client.
+pub fn generate_nonce() -> Snowflake {
+    let increment = NONCE_INCREMENT.fetch_add(1, Ordering::Relaxed) & 0x3F_FFFF;
+    Snowflake(Snowflake::from_datetime(Utc::now()).0 | increment)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SendState {
+    // Waiting for its turn, a rate limit or connectivity
+    Pending,
+    Sending,
+    // Rejected by Discord; only sent again through `retry_message`. Holds
+    // back the rest of its channel until then
+    Failed,
+}
+
+/// A message that was sent but not yet confirmed by Discord.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxItem {
+    pub nonce: String,
+    pub channel_id: Snowflake,
+    pub content: String,
+    // Sent as a message.txt attachment instead of as text
+    #[serde(default)]
+    pub as_attachment: bool,
+    author: Value,
+    // The account that queued it; only sent while that account is signed in
+    #[serde(default)]
+    author_id: Option<Snowflake>,
+    timestamp: String,
+    state: SendState,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    attempts: u32,
+    // Unix milliseconds before which the item is not sent
+    #[serde(default)]
+    not_before: i64,
+}
+
+impl OutboxItem {
+    pub fn new(
+        channel_id: Snowflake,
+        content: String,
+        as_attachment: bool,
+        author_id: Snowflake,
+        author: Value,
+    ) -> Self {
+        let nonce = generate_nonce();
+        Self {
+            nonce: nonce.to_string(),
+            channel_id,
+            content,
+            as_attachment,
+            author,
+            author_id: Some(author_id),
+            timestamp: nonce.datetime().to_rfc3339(),
+            state: SendState::Pending,
+            error: None,
+            attempts: 0,
+            not_before: 0,
+        }
+    }
+
+    /// The local echo shown until the real message arrives. Its id is the
+    /// nonce, which the confirmed message carries as `nonce`.
+    pub fn echo(&self) -> Value {
+        json!({
+            "id": self.nonce,
+            "nonce": self.nonce,
+            "channel_id": self.channel_id,
+            "content": self.content,
+            "timestamp": self.timestamp,
+            "author": self.author,
+            "send_state": self.state,
+            "send_error": self.error,
+            "retry_at": (self.not_before > Utc::now().timestamp_millis())
+                .then_some(self.not_before),
+        })
+    }
+}
+
+/// Unsent messages, persisted so they survive restarts. Items of a channel
+/// are sent strictly in order; channels do not hold each other up.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    items: Vec<OutboxItem>,
+    delivered: VecDeque<String>,
+    // channel id -> unix ms when our slowmode cooldown ends
+    cooldowns: HashMap<Snowflake, i64>,
+    path: Option<PathBuf>,
+    wake: Arc<Notify>,
+}
+
+impl Outbox {
+    /// Loads the persisted queue. Items that were mid-send are sent again;
+    /// the nonce keeps Discord from creating them twice.
+    pub async fn load(path: PathBuf) -> Self {
+        let mut items: Vec<OutboxItem> = Vec::new();
+        let mut writable = true;
+        if let Ok(content) = tokio::fs::read_to_string(&path).await {
+            match serde_json::from_str(&content) {
+                Ok(loaded) => items = loaded,
+                Err(e) => {
+                    error!("Failed to parse outbox: {}", e);
+                    // Kept aside so the next save does not overwrite it
+                    let corrupt = path.with_extension("json.corrupt");
+                    if let Err(e) = tokio::fs::rename(&path, &corrupt).await {
+                        error!("Failed to move the outbox aside, not saving it: {}", e);
+                        writable = false;
+                    }
+                }
+            }
+        }
+        for item in &mut items {
+            if item.state == SendState::Sending {
+                item.state = SendState::Pending;
+            }
+            // Saved before items kept their author's id
+            if item.author_id.is_none() {
+                item.author_id = Snowflake::from_value(&item.author["id"]);
+            }
+        }
+        if !items.is_empty() {
+            info!("Loaded {} unsent messages", items.len());
+        }
+        Self {
+            items,
+            path: writable.then_some(path),
+            ..Default::default()
+        }
+    }
+
+    async fn save(&self) {
+        if let Err(e) = self.write().await {
+            error!("{}", e);
+        }
+    }
+
+    async fn write(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| format!("Failed to create data dir: {}", e))?;
+        }
+        let json = serde_json::to_string(&self.items)
+            .map_err(|e| format!("Failed to serialize outbox: {}", e))?;
+        // Written next to the outbox and renamed over it, so a crash never
+        // leaves a half-written queue behind
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json)
+            .await
+            .map_err(|e| format!("Failed to write outbox: {}", e))?;
+        tokio::fs::rename(&tmp, path)
+            .await
+            .map_err(|e| format!("Failed to write outbox: {}", e))
+    }
+
+    fn item_mut(&mut self, nonce: &str) -> Option<&mut OutboxItem> {
+        self.items.iter_mut().find(|item| item.nonce == nonce)
+    }
+
+    /// Local echoes of a channel's unsent messages, newest first.
+    pub fn echoes(&self, channel_id: Snowflake) -> Vec<Value> {
+        self.items
+            .iter()
+            .rev()
+            .filter(|item| item.channel_id == channel_id)
+            .map(OutboxItem::echo)
+            .collect()
+    }
+
+    /// Drops every unsent message.
+    pub async fn clear(&mut self) {
+        self.items.clear();
+        self.save().await;
+    }
+
+    pub async fn push(&mut self, item: OutboxItem) {
+        self.items.push(item);
+        self.save().await;
+        self.wake.notify_one();
+    }
+
+    /// Marks the message with this nonce as delivered. Returns false when it
+    /// already was, i.e. for the second of the REST response and the
+    /// MESSAGE_CREATE. Nonces we did not send always pass.
+    pub async fn confirm(&mut self, nonce: &str) -> bool {
+        if self.delivered.iter().any(|n| n == nonce) {
+            return false;
+        }
+        let before = self.items.len();
+        self.items.retain(|item| item.nonce != nonce);
+        if self.items.len() != before {
+            self.delivered.push_back(nonce.to_string());
+            if self.delivered.len() > DELIVERED_HISTORY {
+                self.delivered.pop_front();
+            }
+            self.save().await;
+            // The channel's next message may go now
+            self.wake.notify_one();
+        }
+        true
+    }
+
+    /// Puts a failed message back in the queue.
+    pub async fn retry(&mut self, nonce: &str) -> Option<OutboxItem> {
+        let item = self.item_mut(nonce)?;
+        if item.state != SendState::Failed {
+            return None;
+        }
+        item.state = SendState::Pending;
+        item.error = None;
+        item.attempts = 0;
+        item.not_before = 0;
+        let item = item.clone();
+        self.save().await;
+        self.wake.notify_one();
+        Some(item)
+    }
+
+    pub async fn remove(&mut self, nonce: &str) -> Option<OutboxItem> {
+        let index = self.items.iter().position(|item| item.nonce == nonce)?;
+        let item = self.items.remove(index);
+        self.save().await;
+        self.wake.notify_one();
+        Some(item)
+    }
+
+    /// Connectivity is back: retry waiting items now instead of after their
+    /// backoff. Rate limits are kept.
+    pub fn resume(&mut self) {
+        let now = Utc::now().timestamp_millis();
+        for item in &mut self.items {
+            let backing_off = item.error.as_deref().is_some_and(|e| e != "http_429");
+            if item.state == SendState::Pending && backing_off {
+                item.not_before = now;
+            }
+        }
+        self.wake.notify_one();
+    }
+
+    /// Holds back the channel's messages until `until`, for slowmode.
+    pub fn start_cooldown(&mut self, channel_id: Snowflake, until: i64) {
+        let entry = self.cooldowns.entry(channel_id).or_default();
+        *entry = (*entry).max(until);
+        self.wake.notify_one();
+    }
+
+    /// When the channel's running cooldown ends, if there is one.
+    pub fn cooldown_until(&self, channel_id: Snowflake) -> Option<i64> {
+        let now = Utc::now().timestamp_millis();
+        self.cooldowns
+            .get(&channel_id)
+            .copied()
+            .filter(|&until| until > now)
+    }
+
+    /// Picks the next item of `user_id` to send: the first item of a channel,
+    /// if it is pending and its wait is over. A channel whose first item is
+    /// sending, failed or backing off sends nothing else. Items queued by
+    /// other accounts wait until they sign in again. Returns the item, marked
+    /// as sending, or when to look again.
+    fn next_ready(&mut self, user_id: Snowflake, now: i64) -> Result<OutboxItem, Option<i64>> {
+        let mut seen_channels = HashSet::new();
+        let mut wake_at: Option<i64> = None;
+        let mut ready = None;
+        for (index, item) in self.items.iter().enumerate() {
+            if item.author_id != Some(user_id)
+                || !seen_channels.insert(item.channel_id)
+                || item.state != SendState::Pending
+            {
+                continue;
+            }
+            let cooldown = self.cooldowns.get(&item.channel_id).copied().unwrap_or(0);
+            let not_before = item.not_before.max(cooldown);
+            if not_before <= now {
+                ready = Some(index);
+                break;
+            }
+            wake_at = Some(wake_at.map_or(not_before, |at| at.min(not_before)));
+        }
+        match ready {
+            Some(index) => {
+                let item = &mut self.items[index];
+                item.state = SendState::Sending;
+                item.attempts += 1;
+                Ok(item.clone())
+            }
+            None => Err(wake_at),
+        }
+    }
+
+    /// Records a failed attempt. Network errors, server errors and rate
+    /// limits are retried; anything else fails the item until the user
+    /// retries it. Returns the updated item unless it was delivered meanwhile.
+    fn record_failure(&mut self, nonce: &str, error: &PostError) -> Option<OutboxItem> {
+        let now = Utc::now().timestamp_millis();
+        let item = self.item_mut(nonce)?;
+        item.error = Some(error.code());
+        match error {
+            PostError::RateLimited { retry_after_ms } => {
+                item.state = SendState::Pending;
+                item.not_before = now + retry_after_ms;
+            }
+            // A 401 waits as well, for the sign out check or a new sign in
+            PostError::Request | PostError::Parse | PostError::Http(401 | 500..) => {
+                item.state = SendState::Pending;
+                let backoff = 1000i64 << item.attempts.min(6);
+                item.not_before = now + backoff.min(MAX_BACKOFF_MS);
+            }
+            PostError::Http(_) => item.state = SendState::Failed,
+        }
+        Some(item.clone())
+    }
+}
+
+fn status_event(item: &OutboxItem) -> GatewayEvent {
+    match item.state {
+        SendState::Failed => GatewayEvent::MessageSendFailed(item.echo()),
+        SendState::Pending | SendState::Sending => GatewayEvent::MessagePending(item.echo()),
+    }
+}
+
+/// Posts one item and reports the outcome: the confirmed message as
+/// `MessageCreate` (unless the gateway was first), or the item's new status.
+async fn deliver<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    token: &str,
+    item: &OutboxItem,
+) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    emit(app_handle, status_event(item))?;
+
+    let result = if item.as_attachment {
+        match upload_attachment(&state, token, item.channel_id, "message.txt", &item.content).await
+        {
+            Ok(attachment) => {
+                post_message(
+                    &state,
+                    token,
+                    item.channel_id,
+                    "",
+                    &item.nonce,
+                    &[attachment],
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        }
+    } else {
+        post_message(
+            &state,
+            token,
+            item.channel_id,
+            &item.content,
+            &item.nonce,
+            &[],
+        )
+        .await
+    };
+
+    match result {
+        Ok(mut sent) => {
+            start_cooldown(app_handle, item.channel_id).await?;
+            if state.outbox.lock().await.confirm(&item.nonce).await {
+                attach_resolved(app_handle, std::slice::from_mut(&mut sent)).await;
+                state.history.lock().await.emit_live(app_handle, sent)?;
+            }
+        }
+        Err(e) => {
+            warn!("Sending {} failed: {}", item.nonce, e.code());
+            let updated = {
+                let mut outbox = state.outbox.lock().await;
+                let updated = outbox.record_failure(&item.nonce, &e);
+                outbox.save().await;
+                updated
+            };
+            if let Some(updated) = updated {
+                emit(app_handle, status_event(&updated))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends queued messages for as long as the app runs.
+pub async fn run_outbox<R: Runtime>(app_handle: AppHandle<R>) {
+    let state = app_handle.state::<AppState>();
+    let wake = state.outbox.lock().await.wake.clone();
+
+    loop {
+        let token = state.token.lock().await.clone();
+        let user_id = current_user_id(&state).await;
+        let next = match (&token, user_id) {
+            (Some(_), Some(user_id)) => state
+                .outbox
+                .lock()
+                .await
+                .next_ready(user_id, Utc::now().timestamp_millis()),
+            // Nothing can be sent until we are signed in again
+            _ => Err(None),
+        };
+
+        match (token, next) {
+            (Some(token), Ok(item)) => {
+                debug!("Sending queued message {}", item.nonce);
+                if let Err(e) = deliver(&app_handle, &token, &item).await {
+                    error!("Failed to report outbox status: {}", e);
+                }
+            }
+            (_, Err(Some(wake_at))) => {
+                let wait = (wake_at - Utc::now().timestamp_millis()).max(0) as u64;
+                tokio::select! {
+                    _ = wake.notified() => {}
+                    _ = tokio::time::sleep(Duration::from_millis(wait)) => {}
+                }
+            }
+            _ => wake.notified().await,
+        }
+    }
+}
+
+/// Queues a message and shows it right away as pending.
+pub async fn enqueue<R: Runtime>(
+    state: &AppState,
+    app_handle: &AppHandle<R>,
+    channel_id: Snowflake,
+    content: String,
+    as_attachment: bool,
+) -> Result<Value, String> {
+    let author_id = current_user_id(state)
+        .await
+        .ok_or_else(|| "not_authenticated".to_string())?;
+    let author = {
+        let cache = state.cache.lock().await;
+        serde_json::to_value(&cache.current_user).unwrap_or_default()
+    };
+    let item = OutboxItem::new(channel_id, content, as_attachment, author_id, author);
+    let echo = item.echo();
+    emit(app_handle, GatewayEvent::MessagePending(echo.clone()))?;
+    state.outbox.lock().await.push(item).await;
+    Ok(echo)
+}
+
+/// Queues a failed message again with the same nonce, so Discord does not
+/// create it twice if an earlier attempt did get through.
+#[tauri::command]
+pub async fn retry_message<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    nonce: String,
+) -> Result<String, String> {
+    let item = state
+        .outbox
+        .lock()
+        .await
+        .retry(&nonce)
+        .await
+        .ok_or_else(|| "unknown_message".to_string())?;
+
+    let echo = item.echo();
+    emit(&app_handle, GatewayEvent::MessagePending(echo.clone()))?;
+    Ok(echo.to_string())
+}
+
+/// Drops an unsent message the user no longer wants to send.
+#[tauri::command]
+pub async fn discard_message(state: State<'_, AppState>, nonce: String) -> Result<(), String> {
+    state.outbox.lock().await.remove(&nonce).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const NOW: i64 = 1_000_000;
+    const USER: Snowflake = Snowflake(7);
+
+    fn item(channel_id: u64, author_id: Snowflake) -> OutboxItem {
+        let content = format!("to {}", channel_id);
+        OutboxItem::new(Snowflake(channel_id), content, false, author_id, json!({}))
+    }
+
+    fn outbox(channels: &[u64]) -> Outbox {
+        Outbox {
+            items: channels.iter().map(|&id| item(id, USER)).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn next_content(outbox: &mut Outbox) -> Option<String> {
+        outbox.next_ready(USER, NOW).ok().map(|item| item.content)
+    }
+
+    #[test]
+    fn sends_a_channel_in_order() {
+        let mut outbox = outbox(&[1, 1, 2]);
+
+        assert_eq!(next_content(&mut outbox).as_deref(), Some("to 1"));
+        // Channel 1 is busy, channel 2 is not held up by it
+        assert_eq!(next_content(&mut outbox).as_deref(), Some("to 2"));
+        assert_eq!(outbox.next_ready(USER, NOW).unwrap_err(), None);
+    }
+
+    #[test]
+    fn failed_messages_hold_back_their_channel() {
+        let mut outbox = outbox(&[1, 1, 2]);
+        outbox.items[0].state = SendState::Failed;
+
+        assert_eq!(next_content(&mut outbox).as_deref(), Some("to 2"));
+        assert_eq!(outbox.next_ready(USER, NOW).unwrap_err(), None);
+    }
+
+    #[test]
+    fn sends_only_the_signed_in_accounts_messages() {
+        let other = Snowflake(8);
+        let mut outbox = Outbox {
+            items: vec![item(1, other), item(1, USER), item(2, other)],
+            ..Default::default()
+        };
+
+        // Another account's unsent message does not hold back the channel
+        let nonce = outbox.items[1].nonce.clone();
+        assert_eq!(outbox.next_ready(USER, NOW).unwrap().nonce, nonce);
+        assert_eq!(outbox.next_ready(USER, NOW).unwrap_err(), None);
+        assert_eq!(
+            outbox.next_ready(other, NOW).unwrap().nonce,
+            outbox.items[0].nonce
+        );
+    }
+
+    #[test]
+    fn backing_off_messages_hold_back_their_channel() {
+        let mut outbox = outbox(&[1, 1]);
+        outbox.items[0].not_before = NOW + 500;
+        assert_eq!(outbox.next_ready(USER, NOW).unwrap_err(), Some(NOW + 500));
+
+        outbox.start_cooldown(Snowflake(1), NOW + 900);
+        assert_eq!(outbox.next_ready(USER, NOW).unwrap_err(), Some(NOW + 900));
+        assert_eq!(
+            outbox.next_ready(USER, NOW + 900).unwrap().nonce,
+            outbox.items[0].nonce
+        );
+    }
+
+    #[test]
+    fn retries_rate_limits_and_server_errors() {
+        let mut outbox = outbox(&[1]);
+        let nonce = outbox.items[0].nonce.clone();
+        outbox.next_ready(USER, NOW).unwrap();
+
+        let before = Utc::now().timestamp_millis();
+        let item = outbox
+            .record_failure(
+                &nonce,
+                &PostError::RateLimited {
+                    retry_after_ms: 3000,
+                },
+            )
+            .unwrap();
+        assert_eq!(item.state, SendState::Pending);
+        assert_eq!(item.error.as_deref(), Some("http_429"));
+        assert!(item.not_before >= before + 3000);
+
+        // The backoff doubles with every attempt
+        let item = outbox
+            .record_failure(&nonce, &PostError::Http(502))
+            .unwrap();
+        assert_eq!(item.state, SendState::Pending);
+        assert!(item.not_before >= before + 2000);
+        assert!(item.not_before < before + 3000 + 1000);
+    }
+
+    #[test]
+    fn fails_rejected_messages() {
+        let mut outbox = outbox(&[1]);
+        let nonce = outbox.items[0].nonce.clone();
+        outbox.next_ready(USER, NOW).unwrap();
+
+        let item = outbox
+            .record_failure(&nonce, &PostError::Http(403))
+            .unwrap();
+        assert_eq!(item.state, SendState::Failed);
+        assert_eq!(item.error.as_deref(), Some("http_403"));
+        assert!(
+            outbox
+                .record_failure("unknown", &PostError::Request)
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_a_corrupt_outbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("outbox.json");
+        tokio::fs::write(&path, "not json").await.unwrap();
+
+        let mut outbox = Outbox::load(path.clone()).await;
+        assert!(outbox.items.is_empty());
+        outbox.push(item(1, USER)).await;
+
+        let corrupt = tokio::fs::read_to_string(dir.path().join("outbox.json.corrupt"));
+        assert_eq!(corrupt.await.unwrap(), "not json");
+        let loaded = Outbox::load(path).await;
+        assert_eq!(loaded.items.len(), 1);
+        assert_eq!(loaded.items[0].author_id, Some(USER));
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tauri::State;
+
+use super::{
+    cache::{DiscordCache, GuildCache},
+    models::Channel,
+    snowflake::Snowflake,
+};
+use crate::AppState;
+
+// Permission bits we care about
+// https://discord.com/developers/docs/topics/permissions#permissions-bitwise-permission-flags
+pub const ADMINISTRATOR: u64 = 1 << 3;
+pub const MANAGE_CHANNELS: u64 = 1 << 4;
+pub const ADD_REACTIONS: u64 = 1 << 6;
+pub const VIEW_CHANNEL: u64 = 1 << 10;
+pub const SEND_MESSAGES: u64 = 1 << 11;
+pub const MANAGE_MESSAGES: u64 = 1 << 13;
+pub const ATTACH_FILES: u64 = 1 << 15;
+pub const READ_MESSAGE_HISTORY: u64 = 1 << 16;
+pub const MANAGE_THREADS: u64 = 1 << 34;
+pub const SEND_MESSAGES_IN_THREADS: u64 = 1 << 38;
+pub const ALL: u64 = u64::MAX;
+
+/// What the current user may do in a channel, as consumed by the UI.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ChannelCapabilities {
+    pub view: bool,
+    pub send: bool,
+    pub attach: bool,
+    pub react: bool,
+    pub manage_messages: bool,
+    // Not held back by the channel's slowmode
+    pub bypass_slowmode: bool,
+}
+
+impl ChannelCapabilities {
+    pub fn from_permissions(permissions: u64, is_thread: bool) -> Self {
+        let has = |bit: u64| permissions & bit == bit;
+        let send_bit = if is_thread {
+            SEND_MESSAGES_IN_THREADS
+        } else {
+            SEND_MESSAGES
+        };
+        let view = has(VIEW_CHANNEL);
+        let send = view && has(send_bit);
+        Self {
+            view,
+            send,
+            attach: send && has(ATTACH_FILES),
+            react: view && has(READ_MESSAGE_HISTORY) && has(ADD_REACTIONS),
+            manage_messages: view && has(MANAGE_MESSAGES),
+            bypass_slowmode: view
+                && (has(MANAGE_MESSAGES)
+                    || has(MANAGE_CHANNELS)
+                    || (is_thread && has(MANAGE_THREADS))),
+        }
+    }
+}
+
+fn parse_bits(bits: &str) -> u64 {
+    bits.parse().unwrap_or(0)
+}
+
+/// Guild-level permissions: @everyone plus every role the member has.
+/// Returns `None` when the member is not cached.
+pub fn base_permissions(guild: &GuildCache, user_id: Snowflake) -> Option<u64> {
+    if guild.owner_id == Some(user_id) {
+        return Some(ALL);
+    }
+
+    let member = guild.members.get(&user_id)?;
+    // The @everyone role shares its id with the guild
+    let mut permissions = guild
+        .roles
+        .get(&guild.id)
+        .map(|r| parse_bits(&r.permissions))
+        .unwrap_or(0);
+    for role in member.roles.iter().filter_map(|id| guild.roles.get(id)) {
+        permissions |= parse_bits(&role.permissions);
+    }
+
+    if permissions & ADMINISTRATOR == ADMINISTRATOR {
+        return Some(ALL);
+    }
+    Some(permissions)
+}
+
+/// Applies channel overwrites in Discord's order: @everyone, roles, member.
+/// Threads inherit the overwrites of their parent channel.
+pub fn channel_permissions(
+    guild: &GuildCache,
+    channel: &Channel,
+    user_id: Snowflake,
+) -> Option<u64> {
+    let mut permissions = base_permissions(guild, user_id)?;
+    if permissions == ALL {
+        return Some(ALL);
+    }
+
+    let overwrite_source = match &channel.parent_id {
+        Some(parent_id) if channel.is_thread() => guild.channels.get(parent_id).unwrap_or(channel),
+        _ => channel,
+    };
+    let overwrites = &overwrite_source.permission_overwrites;
+    let member = guild.members.get(&user_id)?;
+
+    if let Some(everyone) = overwrites.iter().find(|o| o.id == guild.id) {
+        permissions &= !parse_bits(&everyone.deny);
+        permissions |= parse_bits(&everyone.allow);
+    }
+
+    let (mut allow, mut deny) = (0, 0);
+    for overwrite in overwrites
+        .iter()
+        .filter(|o| o.kind == 0 && member.roles.contains(&o.id))
+    {
+        allow |= parse_bits(&overwrite.allow);
+        deny |= parse_bits(&overwrite.deny);
+    }
+    permissions &= !deny;
+    permissions |= allow;
+
+    if let Some(own) = overwrites.iter().find(|o| o.kind == 1 && o.id == user_id) {
+        permissions &= !parse_bits(&own.deny);
+        permissions |= parse_bits(&own.allow);
+    }
+
+    // Without VIEW_CHANNEL no other permission applies
+    if permissions & VIEW_CHANNEL == 0 {
+        return Some(0);
+    }
+    Some(permissions)
+}
+
+/// Capabilities of the current user in a guild channel. `None` means we lack
+/// the data to decide (unknown channel, DM, or member not cached yet), in
+/// which case callers should not restrict anything.
+pub fn capabilities_for(
+    cache: &DiscordCache,
+    channel_id: Snowflake,
+) -> Option<ChannelCapabilities> {
+    let user_id = cache.current_user.as_ref()?.id;
+    let (guild, channel) = cache.channel(channel_id)?;
+    let permissions = channel_permissions(guild, channel, user_id)?;
+    Some(ChannelCapabilities::from_permissions(
+        permissions,
+        channel.is_thread(),
+    ))
+}
+
+#[tauri::command]
+pub async fn fetch_channel_capabilities(
+    state: State<'_, AppState>,
+    guild_id: Snowflake,
+) -> Result<HashMap<Snowflake, ChannelCapabilities>, String> {
+    let cache = state.cache.lock().await;
+    let guild = cache
+        .guild(guild_id)
+        .ok_or_else(|| "unknown_guild".to_string())?;
+
+    Ok(guild
+        .channels
+        .keys()
+        .filter_map(|&id| capabilities_for(&cache, id).map(|caps| (id, caps)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    const GUILD: Snowflake = Snowflake(1);
+    const ME: Snowflake = Snowflake(2);
+    const MODS: Snowflake = Snowflake(3);
+    const MUTED: Snowflake = Snowflake(4);
+    const CHANNEL: Snowflake = Snowflake(10);
+    const THREAD: Snowflake = Snowflake(11);
+
+    fn bits(permissions: u64) -> String {
+        permissions.to_string()
+    }
+
+    fn overwrite(id: Snowflake, kind: u8, allow: u64, deny: u64) -> Value {
+        json!({ "id": id, "type": kind, "allow": bits(allow), "deny": bits(deny) })
+    }
+
+    /// A guild where @everyone can view and send, with me holding `roles`.
+    fn guild_cache(owner: bool, roles: &[Snowflake], overwrites: Vec<Value>) -> DiscordCache {
+        let mut cache = DiscordCache::default();
+        cache.apply_user_update(&json!({ "id": ME }));
+        cache.apply_guild_create(&json!({
+            "id": GUILD,
+            "owner_id": if owner { ME } else { Snowflake(99) },
+            "roles": [
+                { "id": GUILD, "permissions": bits(VIEW_CHANNEL | SEND_MESSAGES | ATTACH_FILES) },
+                { "id": MODS, "permissions": bits(MANAGE_MESSAGES) },
+                { "id": MUTED, "permissions": "0" },
+            ],
+            "channels": [
+                { "id": CHANNEL, "type": 0, "permission_overwrites": overwrites },
+                { "id": THREAD, "type": 11, "parent_id": CHANNEL },
+            ],
+            "members": [{ "user": { "id": ME }, "roles": roles }],
+        }));
+        cache
+    }
+
+    fn permissions(cache: &DiscordCache, channel_id: Snowflake) -> u64 {
+        let (guild, channel) = cache.channel(channel_id).unwrap();
+        channel_permissions(guild, channel, ME).unwrap()
+    }
+
+    #[test]
+    fn owner_has_every_permission() {
+        let deny_all = overwrite(GUILD, 0, 0, ALL);
+        let cache = guild_cache(true, &[], vec![deny_all]);
+        assert_eq!(permissions(&cache, CHANNEL), ALL);
+    }
+
+    #[test]
+    fn administrator_ignores_overwrites() {
+        let mut cache = guild_cache(false, &[MODS], vec![overwrite(ME, 1, 0, ALL)]);
+        let guild = cache.guilds.get_mut(&GUILD).unwrap();
+        guild.roles.get_mut(&MODS).unwrap().permissions = bits(ADMINISTRATOR);
+        assert_eq!(permissions(&cache, CHANNEL), ALL);
+    }
+
+    #[test]
+    fn overwrites_apply_everyone_then_roles_then_member() {
+        // @everyone loses send, a role gives it back
+        let everyone = overwrite(GUILD, 0, 0, SEND_MESSAGES);
+        let mods = overwrite(MODS, 0, SEND_MESSAGES, 0);
+        let cache = guild_cache(false, &[MODS], vec![everyone.clone(), mods.clone()]);
+        assert_ne!(permissions(&cache, CHANNEL) & SEND_MESSAGES, 0);
+
+        // Between roles, an allow beats a deny
+        let muted = overwrite(MUTED, 0, 0, SEND_MESSAGES);
+        let cache = guild_cache(false, &[MODS, MUTED], vec![muted, mods.clone()]);
+        assert_ne!(permissions(&cache, CHANNEL) & SEND_MESSAGES, 0);
+
+        // The member overwrite has the last word
+        let own = overwrite(ME, 1, 0, SEND_MESSAGES);
+        let cache = guild_cache(false, &[MODS], vec![everyone, mods, own]);
+        assert_eq!(permissions(&cache, CHANNEL) & SEND_MESSAGES, 0);
+    }
+
+    #[test]
+    fn missing_view_channel_denies_everything() {
+        let hidden = overwrite(GUILD, 0, 0, VIEW_CHANNEL);
+        let cache = guild_cache(false, &[MODS], vec![hidden]);
+        assert_eq!(permissions(&cache, CHANNEL), 0);
+        assert!(!capabilities_for(&cache, CHANNEL).unwrap().view);
+    }
+
+    #[test]
+    fn capabilities_follow_implicit_denies() {
+        // Attaching needs send; reacting needs history
+        let caps = ChannelCapabilities::from_permissions(VIEW_CHANNEL | ATTACH_FILES, false);
+        assert!(!caps.send);
+        assert!(!caps.attach);
+        let caps = ChannelCapabilities::from_permissions(VIEW_CHANNEL | ADD_REACTIONS, false);
+        assert!(!caps.react);
+
+        // Threads have their own send bit
+        let caps = ChannelCapabilities::from_permissions(VIEW_CHANNEL | SEND_MESSAGES, true);
+        assert!(!caps.send);
+        let caps =
+            ChannelCapabilities::from_permissions(VIEW_CHANNEL | SEND_MESSAGES_IN_THREADS, true);
+        assert!(caps.send);
+    }
+
+    #[test]
+    fn threads_use_the_parent_overwrites() {
+        let hidden = overwrite(GUILD, 0, 0, VIEW_CHANNEL);
+        let cache = guild_cache(false, &[], vec![hidden]);
+        assert_eq!(permissions(&cache, THREAD), 0);
+    }
+
+    #[test]
+    fn unknown_member_is_undecided() {
+        let mut cache = guild_cache(false, &[], vec![]);
+        cache.guilds.get_mut(&GUILD).unwrap().members.clear();
+        assert!(capabilities_for(&cache, CHANNEL).is_none());
+    }
+}
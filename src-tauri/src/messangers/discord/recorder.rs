@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tauri::{AppHandle, Runtime, State};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tracing::{error, info};
+
+use super::gateway::replay;
+use crate::AppState;
+
+const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    // Sent by Discord
+    Inbound,
+    // Sent by us
+    Outbound,
+}
+
+/// One line of a recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub at_ms: i64,
+    pub direction: Direction,
+    pub payload: Value,
+}
+
+struct Recording {
+    path: PathBuf,
+    file: File,
+}
+
+/// Writes every gateway frame to a JSON lines file while switched on.
+/// Tokens are blanked before anything is written.
+pub struct GatewayRecorder {
+    dir: PathBuf,
+    recording: Option<Recording>,
+}
+
+impl GatewayRecorder {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            recording: None,
+        }
+    }
+
+    /// Starts a new recording file, or returns the one already being written.
+    pub async fn start(&mut self) -> Result<PathBuf, String> {
+        if let Some(recording) = &self.recording {
+            return Ok(recording.path.clone());
+        }
+
+        tokio::fs::create_dir_all(&self.dir).await.map_err(|e| {
+            error!("Failed to create recordings dir: {}", e);
+            "recording_failed".to_string()
+        })?;
+        let path = self.dir.join(format!(
+            "gateway-{}.jsonl",
+            Utc::now().format("%Y%m%d-%H%M%S")
+        ));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|e| {
+                error!("Failed to open recording: {}", e);
+                "recording_failed".to_string()
+            })?;
+
+        info!("Recording gateway traffic to {}", path.display());
+        self.recording = Some(Recording {
+            path: path.clone(),
+            file,
+        });
+        Ok(path)
+    }
+
+    pub fn stop(&mut self) -> Option<PathBuf> {
+        let recording = self.recording.take()?;
+        info!("Stopped recording gateway traffic");
+        Some(recording.path)
+    }
+
+    pub async fn record(&mut self, direction: Direction, text: &str) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+
+        let mut payload =
+            serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()));
+        redact(&mut payload);
+        let frame = RecordedFrame {
+            at_ms: Utc::now().timestamp_millis(),
+            direction,
+            payload,
+        };
+        let mut line = json!(frame).to_string();
+        line.push('\n');
+
+        // Flushed per frame so a crash keeps everything up to it
+        let written = match recording.file.write_all(line.as_bytes()).await {
+            Ok(()) => recording.file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            error!("Failed to write recording, stopping: {}", e);
+            self.recording = None;
+        }
+    }
+}
+
+/// Blanks every "token" field, as in IDENTIFY and RESUME.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if key == "token" && field.is_string() {
+                    *field = json!(REDACTED);
+                } else {
+                    redact(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+pub async fn load_recording(path: &Path) -> Result<Vec<RecordedFrame>, String> {
+    let content = tokio::fs::read_to_string(path).await.map_err(|e| {
+        error!("Failed to read recording {}: {}", path.display(), e);
+        "recording_not_found".to_string()
+    })?;
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                error!("Invalid recording line {}: {}", index + 1, e);
+                "invalid_recording".to_string()
+            })
+        })
+        .collect()
+}
+
+/// Returns the path of the recording file.
+#[tauri::command]
+pub async fn start_gateway_recording(state: State<'_, AppState>) -> Result<String, String> {
+    let path = state.recorder.lock().await.start().await?;
+    Ok(path.display().to_string())
+}
+
+/// Returns the path of the finished recording, if one was running.
+#[tauri::command]
+pub async fn stop_gateway_recording(state: State<'_, AppState>) -> Result<Option<String>, String> {
+    let path = state.recorder.lock().await.stop();
+    Ok(path.map(|path| path.display().to_string()))
+}
+
+/// Replays what Discord sent in a recording through the normal event
+/// handling, so the UI sees it like live traffic. Only while the gateway is
+/// disconnected, to keep live and recorded events apart. Replayed events
+/// update the cache only: the vault, the outbox and the network are left
+/// alone, even while signed in. Returns how many frames were replayed.
+#[tauri::command]
+pub async fn replay_gateway_recording<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    path: String,
+    realtime: Option<bool>,
+) -> Result<usize, String> {
+    if state.gateway.lock().await.is_connected().await {
+        return Err("gateway_connected".to_string());
+    }
+
+    let frames = load_recording(Path::new(&path)).await?;
+    info!("Replaying {} recorded frames from {}", frames.len(), path);
+    Ok(replay(&app_handle, frames, realtime.unwrap_or(false)).await)
+}
@@ -0,0 +1,502 @@
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL},
+};
+use futures::{SinkExt, StreamExt};
+use qrcode::{QrCode, render::svg};
+use rand_core::OsRng;
+use rsa::{Oaep, RsaPrivateKey, pkcs8::EncodePublicKey};
+use serde::Serialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tokio::{
+    sync::mpsc,
+    time::{Duration, interval},
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Message, client::IntoClientRequest, http::HeaderValue},
+};
+use tracing::{debug, error, info, warn};
+
+use super::{account::add_account, endpoints::Endpoints, snowflake::Snowflake};
+use crate::AppState;
+
+// The remote auth gateway only accepts connections from the web client
+const ORIGIN: &str = "https://discord.com";
+const KEY_BITS: usize = 2048;
+
+/// The account that scanned the code, before it confirmed the login.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RemoteAuthUser {
+    pub id: Snowflake,
+    pub discriminator: String,
+    pub avatar: Option<String>,
+    pub username: String,
+}
+
+impl RemoteAuthUser {
+    /// The decrypted payload is `id:discriminator:avatar:username`; the
+    /// username may itself contain colons.
+    fn parse(payload: &str) -> Option<Self> {
+        let mut parts = payload.splitn(4, ':');
+        let id = parts.next()?.parse().ok()?;
+        let discriminator = parts.next()?.to_string();
+        let avatar = Some(parts.next()?)
+            .filter(|a| !a.is_empty() && *a != "0")
+            .map(str::to_string);
+        let username = parts.next()?.to_string();
+        Some(Self {
+            id,
+            discriminator,
+            avatar,
+            username,
+        })
+    }
+}
+
+/// Progress of a QR login, emitted on "discord-remote-auth".
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum RemoteAuthEvent {
+    // Show this to the phone
+    QrCode { url: String, svg: String },
+    // Scanned; waiting for the user to confirm on the phone
+    Scanned(RemoteAuthUser),
+    LoggedIn,
+    // Cancelled on the phone, or the code timed out
+    Cancelled,
+    Failed(String),
+}
+
+/// The keypair of one remote auth session. Discord encrypts everything it
+/// sends back to its public key with RSA-OAEP/SHA-256.
+pub struct RemoteAuthKeys {
+    private_key: RsaPrivateKey,
+    // SubjectPublicKeyInfo DER
+    public_key: Vec<u8>,
+}
+
+impl RemoteAuthKeys {
+    /// Generates a fresh keypair. Takes a while, so run off the async runtime.
+    pub async fn generate() -> Result<Self, String> {
+        tokio::task::spawn_blocking(|| {
+            let private_key = RsaPrivateKey::new(&mut OsRng, KEY_BITS)
+                .map_err(|e| format!("Failed to generate key: {}", e))?;
+            let public_key = private_key
+                .to_public_key()
+                .to_public_key_der()
+                .map_err(|e| format!("Failed to encode key: {}", e))?
+                .into_vec();
+            Ok(Self {
+                private_key,
+                public_key,
+            })
+        })
+        .await
+        .map_err(|e| format!("Key generation task failed: {}", e))?
+    }
+
+    pub fn encoded_public_key(&self) -> String {
+        BASE64.encode(&self.public_key)
+    }
+
+    /// What the QR code carries: the URL-safe SHA-256 of the public key.
+    pub fn fingerprint(&self) -> String {
+        BASE64_URL.encode(Sha256::digest(&self.public_key))
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> Result<Vec<u8>, String> {
+        let ciphertext = BASE64
+            .decode(encrypted)
+            .map_err(|_| "invalid_ciphertext".to_string())?;
+        self.private_key
+            .decrypt(Oaep::new::<Sha256>(), &ciphertext)
+            .map_err(|_| "decrypt_failed".to_string())
+    }
+
+    fn decrypt_str(&self, encrypted: &str) -> Result<String, String> {
+        String::from_utf8(self.decrypt(encrypted)?).map_err(|_| "decrypt_failed".to_string())
+    }
+}
+
+fn qr_svg(url: &str) -> Result<String, String> {
+    let code = QrCode::new(url.as_bytes()).map_err(|e| format!("Failed to render QR: {}", e))?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .quiet_zone(true)
+        .build())
+}
+
+/// Trades the ticket from `pending_login` for the encrypted token.
+async fn exchange_ticket(
+    endpoints: &Endpoints,
+    keys: &RemoteAuthKeys,
+    ticket: &str,
+) -> Result<String, String> {
+    let response = endpoints
+        .http()
+        .post(endpoints.api("/users/@me/remote-auth/login"))
+        .json(&json!({ "ticket": ticket }))
+        .send()
+        .await
+        .map_err(|e| {
+            error!("Request failed: {}", e);
+            "request_failed".to_string()
+        })?;
+
+    if !response.status().is_success() {
+        error!("Remote auth ticket exchange failed: {}", response.status());
+        return Err(format!("http_{}", response.status().as_u16()));
+    }
+
+    let body: Value = response.json().await.map_err(|e| {
+        error!("Failed to parse remote auth response: {}", e);
+        "parse_failed".to_string()
+    })?;
+    let encrypted = body["encrypted_token"]
+        .as_str()
+        .ok_or_else(|| "parse_failed".to_string())?;
+    keys.decrypt_str(encrypted)
+}
+
+/// Runs the remote auth protocol against `endpoints.remote_auth_url` until
+/// the phone confirms, then returns the token. `on_event` sees the QR code
+/// and scan progress. Ok(None) when the login was cancelled, timed out or
+/// `shutdown_rx` fired.
+pub async fn run_remote_auth(
+    endpoints: &Endpoints,
+    keys: &RemoteAuthKeys,
+    mut shutdown_rx: mpsc::Receiver<()>,
+    mut on_event: impl FnMut(RemoteAuthEvent),
+) -> Result<Option<String>, String> {
+    let mut request = endpoints
+        .remote_auth_url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| format!("Invalid remote auth URL: {}", e))?;
+    request
+        .headers_mut()
+        .insert("Origin", HeaderValue::from_static(ORIGIN));
+
+    let (ws_stream, _) = connect_async(request).await.map_err(|e| {
+        error!("Remote auth connection failed: {}", e);
+        "connection_failed".to_string()
+    })?;
+    let (mut write, mut read) = ws_stream.split();
+    info!("Connected to remote auth gateway");
+
+    // Replaced by the interval from hello
+    let mut heartbeat = interval(Duration::from_secs(3600));
+    heartbeat.reset();
+
+    loop {
+        let text = tokio::select! {
+            Some(_) = shutdown_rx.recv() => {
+                info!("Remote auth cancelled");
+                return Ok(None);
+            }
+            _ = heartbeat.tick() => {
+                write
+                    .send(Message::Text(json!({ "op": "heartbeat" }).to_string()))
+                    .await
+                    .map_err(|e| format!("Failed to send heartbeat: {}", e))?;
+                continue;
+            }
+            msg = read.next() => match msg {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Close(frame))) => {
+                    // Also how the server ends a session that timed out
+                    info!("Remote auth closed: {:?}", frame);
+                    return Ok(None);
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(format!("Remote auth connection error: {}", e)),
+                None => return Ok(None),
+            },
+        };
+
+        let payload: Value =
+            serde_json::from_str(&text).map_err(|e| format!("Invalid payload: {}", e))?;
+        let reply = match payload["op"].as_str().unwrap_or_default() {
+            "hello" => {
+                let period = payload["heartbeat_interval"].as_u64().unwrap_or(41_250);
+                heartbeat = interval(Duration::from_millis(period));
+                heartbeat.reset();
+                Some(json!({ "op": "init", "encoded_public_key": keys.encoded_public_key() }))
+            }
+            "nonce_proof" => {
+                let nonce =
+                    keys.decrypt(payload["encrypted_nonce"].as_str().unwrap_or_default())?;
+                let proof = BASE64_URL.encode(Sha256::digest(&nonce));
+                Some(json!({ "op": "nonce_proof", "proof": proof }))
+            }
+            "pending_remote_init" => {
+                let fingerprint = payload["fingerprint"].as_str().unwrap_or_default();
+                if fingerprint != keys.fingerprint() {
+                    return Err("fingerprint_mismatch".to_string());
+                }
+                let url = format!("https://discord.com/ra/{}", fingerprint);
+                let svg = qr_svg(&url)?;
+                on_event(RemoteAuthEvent::QrCode { url, svg });
+                None
+            }
+            "pending_ticket" => {
+                let payload = keys.decrypt_str(
+                    payload["encrypted_user_payload"]
+                        .as_str()
+                        .unwrap_or_default(),
+                )?;
+                let user = RemoteAuthUser::parse(&payload)
+                    .ok_or_else(|| "invalid_user_payload".to_string())?;
+                on_event(RemoteAuthEvent::Scanned(user));
+                None
+            }
+            "pending_login" => {
+                let ticket = payload["ticket"]
+                    .as_str()
+                    .ok_or_else(|| "parse_failed".to_string())?;
+                let token = exchange_ticket(endpoints, keys, ticket).await?;
+                let _ = write.close().await;
+                return Ok(Some(token));
+            }
+            "cancel" => {
+                info!("Remote auth cancelled on the phone");
+                return Ok(None);
+            }
+            "heartbeat_ack" => None,
+            op => {
+                debug!("Unhandled remote auth op: {}", op);
+                None
+            }
+        };
+
+        if let Some(reply) = reply {
+            write
+                .send(Message::Text(reply.to_string()))
+                .await
+                .map_err(|e| format!("Failed to send to remote auth: {}", e))?;
+        }
+    }
+}
+
+fn emit_remote_auth<R: Runtime>(app_handle: &AppHandle<R>, event: RemoteAuthEvent) {
+    if let Err(e) = app_handle.emit("discord-remote-auth", event) {
+        error!("Failed to emit remote auth event: {}", e);
+    }
+}
+
+/// Runs one QR login at a time; starting another cancels the running one.
+#[derive(Default)]
+pub struct RemoteAuth {
+    shutdown_tx: Option<mpsc::Sender<()>>,
+}
+
+impl RemoteAuth {
+    pub async fn start<R: Runtime>(&mut self, endpoints: Endpoints, app_handle: AppHandle<R>) {
+        self.cancel().await;
+        let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>(1);
+        self.shutdown_tx = Some(shutdown_tx);
+
+        tokio::spawn(async move {
+            let result = match RemoteAuthKeys::generate().await {
+                Ok(keys) => {
+                    run_remote_auth(&endpoints, &keys, shutdown_rx, |event| {
+                        emit_remote_auth(&app_handle, event)
+                    })
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+
+            let event = match result {
+                Ok(Some(token)) => {
+                    let state = app_handle.state::<AppState>();
+                    match add_account(&state, app_handle.clone(), token).await {
+                        Ok(true) => RemoteAuthEvent::LoggedIn,
+                        Ok(false) => RemoteAuthEvent::Failed("token_rejected".to_string()),
+                        Err(e) => RemoteAuthEvent::Failed(e),
+                    }
+                }
+                Ok(None) => RemoteAuthEvent::Cancelled,
+                Err(e) => {
+                    warn!("Remote auth failed: {}", e);
+                    RemoteAuthEvent::Failed(e)
+                }
+            };
+            emit_remote_auth(&app_handle, event);
+        });
+    }
+
+    pub async fn cancel(&mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(()).await;
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn start_remote_auth<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+) -> Result<(), String> {
+    state
+        .remote_auth
+        .lock()
+        .await
+        .start(state.endpoints.clone(), app_handle)
+        .await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn cancel_remote_auth(state: State<'_, AppState>) -> Result<(), String> {
+    state.remote_auth.lock().await.cancel().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{Json, Router, extract::State as AxumState, routing::post};
+    use rsa::{RsaPublicKey, pkcs8::DecodePublicKey};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{WebSocketStream, accept_async};
+
+    use super::*;
+
+    const TICKET: &str = "remote-auth-ticket";
+    const TOKEN: &str = "remote-auth-token";
+    const NONCE: &[u8] = b"remote-auth-nonce";
+
+    type PublicKey = Arc<Mutex<Option<RsaPublicKey>>>;
+
+    fn encrypt(key: &PublicKey, data: &[u8]) -> String {
+        let key = key.lock().unwrap().clone().expect("init was not sent");
+        BASE64.encode(
+            key.encrypt(&mut OsRng, Oaep::new::<Sha256>(), data)
+                .unwrap(),
+        )
+    }
+
+    async fn send(ws: &mut WebSocketStream<tokio::net::TcpStream>, payload: Value) {
+        ws.send(Message::Text(payload.to_string())).await.unwrap();
+    }
+
+    async fn receive(ws: &mut WebSocketStream<tokio::net::TcpStream>) -> Value {
+        loop {
+            match ws.next().await.unwrap().unwrap() {
+                Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Walks one client through the remote auth gateway, up to the ticket.
+    async fn serve_gateway(listener: TcpListener, public_key: PublicKey) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = accept_async(stream).await.unwrap();
+
+        send(
+            &mut ws,
+            json!({ "op": "hello", "heartbeat_interval": 41_250 }),
+        )
+        .await;
+        let init = receive(&mut ws).await;
+        assert_eq!(init["op"], "init");
+        let der = BASE64
+            .decode(init["encoded_public_key"].as_str().unwrap())
+            .unwrap();
+        *public_key.lock().unwrap() = Some(RsaPublicKey::from_public_key_der(&der).unwrap());
+
+        let encrypted_nonce = encrypt(&public_key, NONCE);
+        send(
+            &mut ws,
+            json!({ "op": "nonce_proof", "encrypted_nonce": encrypted_nonce }),
+        )
+        .await;
+        let proof = receive(&mut ws).await;
+        assert_eq!(proof["proof"], BASE64_URL.encode(Sha256::digest(NONCE)));
+
+        let fingerprint = BASE64_URL.encode(Sha256::digest(&der));
+        send(
+            &mut ws,
+            json!({ "op": "pending_remote_init", "fingerprint": fingerprint }),
+        )
+        .await;
+        let user = encrypt(&public_key, b"175928847299117063:0:0:ann");
+        send(
+            &mut ws,
+            json!({ "op": "pending_ticket", "encrypted_user_payload": user }),
+        )
+        .await;
+        send(&mut ws, json!({ "op": "pending_login", "ticket": TICKET })).await;
+        while ws.next().await.is_some() {}
+    }
+
+    async fn exchange(
+        AxumState(public_key): AxumState<PublicKey>,
+        Json(body): Json<Value>,
+    ) -> Json<Value> {
+        assert_eq!(body["ticket"], TICKET);
+        Json(json!({ "encrypted_token": encrypt(&public_key, TOKEN.as_bytes()) }))
+    }
+
+    #[test]
+    fn parses_the_scanned_user() {
+        let user = RemoteAuthUser::parse("175928847299117063:0:a1b2:ann:the:great").unwrap();
+        assert_eq!(user.id, Snowflake(175928847299117063));
+        assert_eq!(user.avatar.as_deref(), Some("a1b2"));
+        assert_eq!(user.username, "ann:the:great");
+
+        assert!(
+            RemoteAuthUser::parse("175928847299117063:0:0:ann")
+                .unwrap()
+                .avatar
+                .is_none()
+        );
+        assert!(RemoteAuthUser::parse("not-an-id:0:0:ann").is_none());
+    }
+
+    #[tokio::test]
+    async fn logs_in_against_a_local_stand_in() {
+        let public_key = PublicKey::default();
+        let gateway = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoints: Endpoints = serde_json::from_value(json!({
+            "api_base": format!("http://{}/api", api.local_addr().unwrap()),
+            "remote_auth_url": format!("ws://{}", gateway.local_addr().unwrap()),
+        }))
+        .unwrap();
+        tokio::spawn(serve_gateway(gateway, public_key.clone()));
+        let routes = Router::new()
+            .route("/api/v10/users/@me/remote-auth/login", post(exchange))
+            .with_state(public_key);
+        tokio::spawn(async move { axum::serve(api, routes).await });
+
+        let keys = RemoteAuthKeys::generate().await.unwrap();
+        let (_shutdown_tx, shutdown_rx) = mpsc::channel(1);
+        let mut events = Vec::new();
+        let token = run_remote_auth(&endpoints, &keys, shutdown_rx, |event| events.push(event))
+            .await
+            .unwrap();
+
+        assert_eq!(token.as_deref(), Some(TOKEN));
+        match &events[..] {
+            [
+                RemoteAuthEvent::QrCode { url, .. },
+                RemoteAuthEvent::Scanned(user),
+            ] => {
+                assert_eq!(
+                    url,
+                    &format!("https://discord.com/ra/{}", keys.fingerprint())
+                );
+                assert_eq!(user.username, "ann");
+            }
+            events => panic!("unexpected events: {:?}", events),
+        }
+    }
+}
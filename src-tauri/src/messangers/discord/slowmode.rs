@@ -0,0 +1,85 @@
+use chrono::Utc;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use super::{
+    cache::DiscordCache,
+    gateway::{GatewayEvent, emit},
+    permissions::capabilities_for,
+    snowflake::Snowflake,
+};
+use crate::AppState;
+
+/// Slowmode of a channel as it applies to the current user.
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowmodeStatus {
+    pub channel_id: Snowflake,
+    // Seconds between messages, 0 when the channel has no slowmode
+    pub rate_limit_per_user: u32,
+    pub bypass: bool,
+    // Milliseconds until the next message can be sent
+    pub remaining_ms: u64,
+}
+
+/// Seconds the current user has to wait between messages in a channel.
+/// Users with Manage Messages or Manage Channel(s) are not limited.
+pub fn slowmode_secs(cache: &DiscordCache, channel_id: Snowflake) -> u32 {
+    let bypass = capabilities_for(cache, channel_id).is_some_and(|caps| caps.bypass_slowmode);
+    if bypass {
+        return 0;
+    }
+    cache
+        .any_channel(channel_id)
+        .and_then(|c| c.rate_limit_per_user)
+        .unwrap_or(0)
+}
+
+async fn status(state: &AppState, channel_id: Snowflake) -> SlowmodeStatus {
+    let (rate_limit_per_user, bypass) = {
+        let cache = state.cache.lock().await;
+        let rate_limit = cache
+            .any_channel(channel_id)
+            .and_then(|c| c.rate_limit_per_user)
+            .unwrap_or(0);
+        (
+            rate_limit,
+            rate_limit > 0 && slowmode_secs(&cache, channel_id) == 0,
+        )
+    };
+    let until = state.outbox.lock().await.cooldown_until(channel_id);
+    SlowmodeStatus {
+        channel_id,
+        rate_limit_per_user,
+        bypass,
+        remaining_ms: until.map_or(0, |until| {
+            (until - Utc::now().timestamp_millis()).max(0) as u64
+        }),
+    }
+}
+
+/// Starts the channel's cooldown after one of our messages went out, from
+/// this or any other session.
+pub async fn start_cooldown<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    channel_id: Snowflake,
+) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    let secs = slowmode_secs(&*state.cache.lock().await, channel_id);
+    if secs == 0 {
+        return Ok(());
+    }
+    let until = Utc::now().timestamp_millis() + secs as i64 * 1000;
+    state.outbox.lock().await.start_cooldown(channel_id, until);
+    emit(
+        app_handle,
+        GatewayEvent::SlowmodeUpdate(status(&state, channel_id).await),
+    )
+}
+
+#[tauri::command]
+pub async fn fetch_slowmode(
+    state: State<'_, AppState>,
+    channel_id: Snowflake,
+) -> Result<SlowmodeStatus, String> {
+    Ok(status(&state, channel_id).await)
+}
@@ -0,0 +1,143 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use serde_json::Value;
+
+// 2015-01-01T00:00:00Z in unix milliseconds
+pub const DISCORD_EPOCH: i64 = 1_420_070_400_000;
+
+/// A Discord id. The upper 42 bits are milliseconds since the Discord epoch,
+/// so ids sort by creation time.
+/// https://discord.com/developers/docs/reference#snowflakes
+///
+/// Serialized as a string like Discord does, since JavaScript numbers cannot
+/// hold 64 bits; deserializes from a string or a bare number.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Snowflake(pub u64);
+
+impl Snowflake {
+    /// The lowest id Discord could have created at a unix time in
+    /// milliseconds. Works as a cursor for paging by date.
+    pub fn from_timestamp_ms(timestamp_ms: i64) -> Self {
+        Self(((timestamp_ms - DISCORD_EPOCH).max(0) as u64) << 22)
+    }
+
+    pub fn from_datetime(datetime: DateTime<Utc>) -> Self {
+        Self::from_timestamp_ms(datetime.timestamp_millis())
+    }
+
+    /// Unix time in milliseconds at which the id was created.
+    pub fn timestamp_ms(self) -> i64 {
+        (self.0 >> 22) as i64 + DISCORD_EPOCH
+    }
+
+    pub fn datetime(self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(self.timestamp_ms()).unwrap_or_default()
+    }
+
+    /// Reads an id out of a raw gateway or REST payload.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(id) => id.parse().ok(),
+            Value::Number(id) => id.as_u64().map(Self),
+            _ => None,
+        }
+    }
+}
+
+impl From<u64> for Snowflake {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl FromStr for Snowflake {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .map(Self)
+            .map_err(|_| "invalid_snowflake".to_string())
+    }
+}
+
+impl fmt::Display for Snowflake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Serialize for Snowflake {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Snowflake {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SnowflakeVisitor;
+
+        impl de::Visitor<'_> for SnowflakeVisitor {
+            type Value = Snowflake;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a snowflake as a string or number")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse()
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(Snowflake(v))
+            }
+        }
+
+        deserializer.deserialize_any(SnowflakeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn round_trips_timestamps() {
+        // The example from Discord's documentation
+        let id = Snowflake(175928847299117063);
+        assert_eq!(id.timestamp_ms(), 1462015105796);
+        assert_eq!(id.datetime().to_rfc3339(), "2016-04-30T11:18:25.796+00:00");
+
+        let cursor = Snowflake::from_timestamp_ms(id.timestamp_ms());
+        assert_eq!(cursor.timestamp_ms(), id.timestamp_ms());
+        assert!(cursor <= id);
+        assert_eq!(Snowflake::from_datetime(id.datetime()), cursor);
+    }
+
+    #[test]
+    fn clamps_times_before_the_epoch() {
+        assert_eq!(Snowflake::from_timestamp_ms(0), Snowflake(0));
+    }
+
+    #[test]
+    fn deserializes_strings_and_numbers() {
+        let from_string: Snowflake = serde_json::from_value(json!("175928847299117063")).unwrap();
+        let from_number: Snowflake = serde_json::from_value(json!(175928847299117063u64)).unwrap();
+        assert_eq!(from_string, Snowflake(175928847299117063));
+        assert_eq!(from_number, Snowflake(175928847299117063));
+        assert!(serde_json::from_value::<Snowflake>(json!("abc")).is_err());
+        assert!(serde_json::from_value::<Snowflake>(json!(-1)).is_err());
+    }
+
+    #[test]
+    fn serializes_as_a_string() {
+        assert_eq!(
+            serde_json::to_value(Snowflake(175928847299117063)).unwrap(),
+            json!("175928847299117063")
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::json;
+use tauri::State;
+use tracing::error;
+
+use super::{
+    auth::http_error, cache::DiscordCache, permissions::capabilities_for, snowflake::Snowflake,
+};
+use crate::AppState;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct UnreadBadge {
+    pub unread: bool,
+    pub mention_count: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UnreadSummary {
+    pub channels: HashMap<Snowflake, UnreadBadge>,
+    pub guilds: HashMap<Snowflake, UnreadBadge>,
+}
+
+fn channel_badge(cache: &DiscordCache, channel_id: Snowflake) -> UnreadBadge {
+    UnreadBadge {
+        unread: cache.is_unread(channel_id),
+        mention_count: cache
+            .read_states
+            .get(&channel_id)
+            .map(|r| r.mention_count)
+            .unwrap_or(0),
+    }
+}
+
+/// Badges for every known channel plus per-guild rollups. Channels the user
+/// cannot view do not count towards their guild.
+pub fn unread_summary(cache: &DiscordCache) -> UnreadSummary {
+    let mut summary = UnreadSummary::default();
+
+    for &channel_id in cache.private_channels.keys() {
+        summary
+            .channels
+            .insert(channel_id, channel_badge(cache, channel_id));
+    }
+
+    for (guild_id, guild) in &cache.guilds {
+        let mut guild_badge = UnreadBadge::default();
+        for &channel_id in guild.channels.keys() {
+            if capabilities_for(cache, channel_id).is_some_and(|caps| !caps.view) {
+                continue;
+            }
+            let badge = channel_badge(cache, channel_id);
+            guild_badge.unread |= badge.unread;
+            guild_badge.mention_count += badge.mention_count;
+            summary.channels.insert(channel_id, badge);
+        }
+        summary.guilds.insert(*guild_id, guild_badge);
+    }
+
+    summary
+}
+
+#[tauri::command]
+pub async fn fetch_unread_summary(state: State<'_, AppState>) -> Result<UnreadSummary, String> {
+    Ok(unread_summary(&*state.cache.lock().await))
+}
+
+/// Marks a channel read up to `message_id`, locally and on Discord.
+#[tauri::command]
+pub async fn ack_message(
+    state: State<'_, AppState>,
+    channel_id: Snowflake,
+    message_id: Snowflake,
+) -> Result<UnreadBadge, String> {
+    let token = state
+        .token
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| "not_authenticated".to_string())?;
+
+    let url = state.endpoints.api(&format!(
+        "/channels/{}/messages/{}/ack",
+        channel_id, message_id
+    ));
+
+    let response = state
+        .endpoints
+        .http()
+        .post(&url)
+        .header("Authorization", &token)
+        .json(&json!({ "token": null }))
+        .send()
+        .await
+        .map_err(|e| {
+            error!("Request failed: {}", e);
+            "request_failed".to_string()
+        })?;
+
+    if !response.status().is_success() {
+        error!("Message ack failed: {}", response.status());
+        state.auth.report_status(response.status());
+        return Err(http_error(response.status()));
+    }
+
+    let mut cache = state.cache.lock().await;
+    cache.mark_read(channel_id, message_id);
+    Ok(channel_badge(&cache, channel_id))
+}
+
+/// Acks every unread channel of a guild in one bulk request.
+#[tauri::command]
+pub async fn mark_guild_read(
+    state: State<'_, AppState>,
+    guild_id: Snowflake,
+) -> Result<UnreadBadge, String> {
+    let token = state
+        .token
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| "not_authenticated".to_string())?;
+
+    let acks: Vec<(Snowflake, Snowflake)> = {
+        let cache = state.cache.lock().await;
+        let guild = cache
+            .guild(guild_id)
+            .ok_or_else(|| "unknown_guild".to_string())?;
+        guild
+            .channels
+            .values()
+            .filter(|c| cache.is_unread(c.id))
+            .filter_map(|c| Some((c.id, c.last_message_id?)))
+            .collect()
+    };
+
+    if acks.is_empty() {
+        return Ok(UnreadBadge::default());
+    }
+
+    let payload = json!({
+        "read_states": acks
+            .iter()
+            .map(|(channel_id, message_id)| json!({
+                "channel_id": channel_id,
+                "message_id": message_id,
+                "read_state_type": 0
+            }))
+            .collect::<Vec<_>>()
+    });
+
+    let response = state
+        .endpoints
+        .http()
+        .post(state.endpoints.api("/read-states/ack-bulk"))
+        .header("Authorization", &token)
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| {
+            error!("Request failed: {}", e);
+            "request_failed".to_string()
+        })?;
+
+    if !response.status().is_success() {
+        error!("Bulk ack failed: {}", response.status());
+        state.auth.report_status(response.status());
+        return Err(http_error(response.status()));
+    }
+
+    let mut cache = state.cache.lock().await;
+    for &(channel_id, message_id) in &acks {
+        cache.mark_read(channel_id, message_id);
+    }
+    Ok(unread_summary(&cache)
+        .guilds
+        .remove(&guild_id)
+        .unwrap_or_default())
+}
@@ -1,2 +1,3 @@
 pub mod discord;
 pub mod token_storage;
+pub mod vault;
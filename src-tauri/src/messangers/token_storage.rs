@@ -1,7 +1,8 @@
-use std::path::PathBuf;
-use tracing::error;
+use std::path::{Path, PathBuf};
 
-fn get_tokens_file_path() -> PathBuf {
+// Plaintext token file of older versions, only read to migrate it into the
+// vault
+pub fn tokens_file_path() -> PathBuf {
     // Store in project root, outside src-tauri to avoid rebuilds
     PathBuf::from("../tokens.json")
 }
@@ -11,13 +12,12 @@ struct StoredDiscordToken {
     token: Option<String>,
 }
 
-pub async fn load_discord_token_from_file() -> std::io::Result<Option<String>> {
-    let path = get_tokens_file_path();
+pub async fn load_discord_token_from_file(path: &Path) -> std::io::Result<Option<String>> {
     if !path.exists() {
         return Ok(None);
     }
 
-    let content = tokio::fs::read_to_string(&path).await?;
+    let content = tokio::fs::read_to_string(path).await?;
 
     // New format: { "token": "..." } (or null)
     if let Ok(stored) = serde_json::from_str::<StoredDiscordToken>(&content) {
@@ -37,29 +37,21 @@ pub async fn load_discord_token_from_file() -> std::io::Result<Option<String>> {
             Ok(token)
         }
         Ok(_) => Ok(None),
-        Err(e) => {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("Parse error: {}", e),
-            ))
-        }
+        Err(e) => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Parse error: {}", e),
+        )),
     }
 }
 
-pub async fn save_discord_token_to_file(token: Option<&str>) -> std::io::Result<()> {
-    let payload = StoredDiscordToken {
-        token: token.map(|s| s.to_string()),
-    };
-
-    let json = serde_json::to_string_pretty(&payload).map_err(|e| {
-        error!("Failed to serialize token: {}", e);
-        std::io::Error::new(std::io::ErrorKind::Other, format!("Serialize error: {}", e))
-    })?;
+pub fn token_file_exists(path: &Path) -> bool {
+    path.exists()
+}
 
-    tokio::fs::write(get_tokens_file_path(), json)
-        .await
-        .map_err(|e| {
-            error!("Failed to write tokens to file: {}", e);
-            e
-        })
+/// Deletes the plaintext token file once its token is in the vault.
+pub async fn remove_token_file(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
 }
@@ -0,0 +1,612 @@
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    AeadCore, ChaCha20Poly1305, Key, KeyInit, Nonce,
+    aead::{Aead, OsRng, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tokio::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use zeroize::Zeroizing;
+
+use super::{
+    discord::{
+        account::{SavedAccount, record_check},
+        api::check_discord_token,
+        snowflake::Snowflake,
+    },
+    token_storage,
+};
+use crate::AppState;
+
+const VAULT_VERSION: u32 = 1;
+const MIN_PASSWORD_LENGTH: usize = 8;
+// Locked after this long without activity reported by the UI
+const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Argon2id parameters the key was derived with. Kept in the file so they
+/// can be raised later without breaking existing vaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KdfParams {
+    // Memory in KiB
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+}
+
+impl KdfParams {
+    fn generate() -> Self {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        // OWASP's recommended minimum for Argon2id
+        Self {
+            m_cost: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+            salt: BASE64.encode(salt),
+        }
+    }
+
+    /// Derives the vault key. Deliberately slow, so run off the async runtime.
+    async fn derive_key(&self, password: &str) -> Result<Zeroizing<[u8; 32]>, String> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| format!("Invalid vault parameters: {}", e))?;
+        let salt = BASE64
+            .decode(&self.salt)
+            .map_err(|_| "vault_corrupted".to_string())?;
+        let password = Zeroizing::new(password.as_bytes().to_vec());
+
+        tokio::task::spawn_blocking(move || {
+            let mut key = Zeroizing::new([0u8; 32]);
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(&password, &salt, key.as_mut())
+                .map_err(|e| format!("Failed to derive vault key: {}", e))?;
+            Ok(key)
+        })
+        .await
+        .map_err(|e| format!("Key derivation task failed: {}", e))?
+    }
+}
+
+/// The vault as written to disk. Only the KDF parameters are readable
+/// without the password.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    kdf: KdfParams,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// What the vault protects.
+#[derive(Default, Serialize, Deserialize)]
+pub struct VaultContents {
+    // Most recently used first; the first one is signed in on unlock
+    #[serde(default)]
+    pub accounts: Vec<SavedAccount>,
+}
+
+impl VaultContents {
+    pub fn active(&self) -> Option<&SavedAccount> {
+        self.accounts.first()
+    }
+
+    pub fn account(&self, token: &str) -> Option<&SavedAccount> {
+        self.accounts.iter().find(|a| a.token == token)
+    }
+
+    pub fn account_mut(&mut self, token: &str) -> Option<&mut SavedAccount> {
+        self.accounts.iter_mut().find(|a| a.token == token)
+    }
+
+    pub fn account_by_id_mut(&mut self, user_id: Snowflake) -> Option<&mut SavedAccount> {
+        self.accounts
+            .iter_mut()
+            .find(|a| a.profile.as_ref().is_some_and(|p| p.id == user_id))
+    }
+
+    /// Adds an account as the most recently used, replacing an older entry
+    /// with the same token or for the same user.
+    pub fn upsert(&mut self, account: SavedAccount) {
+        let user_id = account.profile.as_ref().map(|p| p.id);
+        self.accounts.retain(|a| {
+            a.token != account.token
+                && (user_id.is_none() || a.profile.as_ref().map(|p| p.id) != user_id)
+        });
+        self.accounts.insert(0, account);
+    }
+}
+
+struct Unlocked {
+    key: Zeroizing<[u8; 32]>,
+    kdf: KdfParams,
+    contents: VaultContents,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultState {
+    // No password set yet
+    Missing,
+    // vault.json exists but cannot be read; it is left for the user to
+    // restore and never overwritten
+    Corrupt,
+    Locked,
+    Unlocked,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VaultStatus {
+    pub state: VaultState,
+    // A plaintext tokens.json is waiting to be moved into the vault
+    pub has_legacy_token: bool,
+}
+
+/// Credentials encrypted with a key derived from the user's password:
+/// Argon2id for the key, ChaCha20-Poly1305 for the contents. The key only
+/// lives in memory while the vault is unlocked.
+pub struct Vault {
+    path: PathBuf,
+    file: Option<VaultFile>,
+    corrupt: bool,
+    unlocked: Option<Unlocked>,
+    last_activity: Instant,
+}
+
+fn seal(key: &[u8; 32], kdf: KdfParams, contents: &VaultContents) -> Result<VaultFile, String> {
+    let plaintext = Zeroizing::new(
+        serde_json::to_vec(contents).map_err(|e| format!("Failed to serialize vault: {}", e))?,
+    );
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| "Failed to encrypt vault".to_string())?;
+    Ok(VaultFile {
+        version: VAULT_VERSION,
+        kdf,
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+fn open(key: &[u8; 32], file: &VaultFile) -> Result<VaultContents, String> {
+    let nonce = BASE64
+        .decode(&file.nonce)
+        .ok()
+        .filter(|n| n.len() == 12)
+        .ok_or_else(|| "vault_corrupted".to_string())?;
+    let ciphertext = BASE64
+        .decode(&file.ciphertext)
+        .map_err(|_| "vault_corrupted".to_string())?;
+    // A wrong password and a tampered file look the same here
+    let plaintext = Zeroizing::new(
+        ChaCha20Poly1305::new(Key::from_slice(key))
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "wrong_password".to_string())?,
+    );
+    serde_json::from_slice(&plaintext).map_err(|_| "vault_corrupted".to_string())
+}
+
+fn check_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err("password_too_short".to_string());
+    }
+    Ok(())
+}
+
+impl Vault {
+    /// Reads the vault file, if there is one. The vault always starts locked.
+    pub async fn load(path: PathBuf) -> Self {
+        let file = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => Some(
+                serde_json::from_str::<VaultFile>(&content)
+                    .map_err(|e| error!("Failed to parse vault: {}", e)),
+            ),
+            Err(_) => None,
+        };
+        Self {
+            path,
+            corrupt: matches!(file, Some(Err(()))),
+            file: file.and_then(Result::ok),
+            unlocked: None,
+            last_activity: Instant::now(),
+        }
+    }
+
+    pub fn state(&self) -> VaultState {
+        match (&self.file, &self.unlocked) {
+            _ if self.corrupt => VaultState::Corrupt,
+            (None, _) => VaultState::Missing,
+            (Some(_), None) => VaultState::Locked,
+            (Some(_), Some(_)) => VaultState::Unlocked,
+        }
+    }
+
+    pub fn contents(&self) -> Option<&VaultContents> {
+        self.unlocked.as_ref().map(|u| &u.contents)
+    }
+
+    /// Sets the password of a new vault, which starts out unlocked. Never
+    /// replaces a vault file, even one that cannot be read.
+    pub async fn create(&mut self, password: &str, contents: VaultContents) -> Result<(), String> {
+        let on_disk = tokio::fs::try_exists(&self.path).await.unwrap_or(true);
+        if self.file.is_some() || self.corrupt || on_disk {
+            return Err("vault_exists".to_string());
+        }
+        check_password(password)?;
+        let kdf = KdfParams::generate();
+        let key = kdf.derive_key(password).await?;
+        self.unlocked = Some(Unlocked { key, kdf, contents });
+        self.save().await?;
+        self.touch();
+        Ok(())
+    }
+
+    pub async fn unlock(&mut self, password: &str) -> Result<(), String> {
+        if self.corrupt {
+            return Err("vault_corrupted".to_string());
+        }
+        let file = self
+            .file
+            .as_ref()
+            .ok_or_else(|| "vault_missing".to_string())?;
+        let key = file.kdf.derive_key(password).await?;
+        let contents = open(&key, file)?;
+        self.unlocked = Some(Unlocked {
+            key,
+            kdf: file.kdf.clone(),
+            contents,
+        });
+        self.touch();
+        Ok(())
+    }
+
+    /// Forgets the key and the decrypted contents.
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+    }
+
+    /// Re-encrypts the vault under a new password, with a fresh salt.
+    pub async fn change_password(&mut self, old: &str, new: &str) -> Result<(), String> {
+        check_password(new)?;
+        let file = self
+            .file
+            .as_ref()
+            .ok_or_else(|| "vault_missing".to_string())?;
+        let old_key = file.kdf.derive_key(old).await?;
+        let contents = open(&old_key, file)?;
+
+        let kdf = KdfParams::generate();
+        let key = kdf.derive_key(new).await?;
+        self.unlocked = Some(Unlocked { key, kdf, contents });
+        self.save().await?;
+        self.touch();
+        Ok(())
+    }
+
+    /// Changes the contents and writes them. Only possible while unlocked.
+    pub async fn update<T>(
+        &mut self,
+        f: impl FnOnce(&mut VaultContents) -> T,
+    ) -> Result<T, String> {
+        let unlocked = self
+            .unlocked
+            .as_mut()
+            .ok_or_else(|| "vault_locked".to_string())?;
+        let result = f(&mut unlocked.contents);
+        self.save().await?;
+        Ok(result)
+    }
+
+    async fn save(&mut self) -> Result<(), String> {
+        let unlocked = self
+            .unlocked
+            .as_ref()
+            .ok_or_else(|| "vault_locked".to_string())?;
+        let file = seal(&unlocked.key, unlocked.kdf.clone(), &unlocked.contents)?;
+
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| format!("Failed to create data dir: {}", e))?;
+        }
+        let json = serde_json::to_string_pretty(&file)
+            .map_err(|e| format!("Failed to serialize vault: {}", e))?;
+        // Written next to the vault and renamed over it, so a crash never
+        // leaves a half-written vault behind
+        let tmp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json)
+            .await
+            .map_err(|e| format!("Failed to write vault: {}", e))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| format!("Failed to write vault: {}", e))?;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Records user activity, postponing the idle lock.
+    pub fn touch(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    fn is_idle(&self) -> bool {
+        self.unlocked.is_some() && self.last_activity.elapsed() >= IDLE_TIMEOUT
+    }
+}
+
+/// Moves a token from the plaintext tokens.json (either format) into the
+/// unlocked vault, then deletes the file. A vault that already has other
+/// accounts is left alone, and so is the file, so its token is not lost.
+async fn migrate_legacy_token(vault: &mut Vault, path: &Path) {
+    let legacy = match token_storage::load_discord_token_from_file(path).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("Could not read legacy token file: {}", e);
+            return;
+        }
+    };
+    let (saved, empty) = match vault.contents() {
+        Some(contents) => (
+            contents.account(&legacy).is_some(),
+            contents.accounts.is_empty(),
+        ),
+        None => return,
+    };
+    if !saved && !empty {
+        warn!("The vault already has accounts, keeping tokens.json");
+        return;
+    }
+    if !saved {
+        let account = SavedAccount::new(legacy);
+        if let Err(e) = vault.update(|c| c.upsert(account)).await {
+            error!("Failed to migrate token into the vault: {}", e);
+            return;
+        }
+        info!("Migrated token from tokens.json into the vault");
+    }
+    if let Err(e) = token_storage::remove_token_file(path).await {
+        error!("Failed to remove legacy token file: {}", e);
+    }
+}
+
+/// Validates the most recently used account and connects with it. A token
+/// Discord rejects stays saved as invalid; a network error signs in anyway.
+async fn sign_in<R: Runtime>(state: &AppState, app_handle: AppHandle<R>) {
+    let token = {
+        let vault = state.vault.lock().await;
+        match vault.contents().and_then(|c| c.active()) {
+            Some(account) => account.token.clone(),
+            None => return,
+        }
+    };
+
+    let result = check_discord_token(&state.endpoints, &token).await;
+    record_check(state, &token, &result).await;
+    match result {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            info!("Stored token is invalid, not signing in");
+            return;
+        }
+        Err(()) => warn!("Could not validate stored token, using it anyway"),
+    }
+
+    *state.token.lock().await = Some(token.clone());
+    if let Err(e) = state.gateway.lock().await.connect(token, app_handle).await {
+        error!("Failed to start Gateway: {}", e);
+    }
+}
+
+/// Locks the vault and drops everything that was unlocked with it.
+pub async fn lock<R: Runtime>(state: &AppState, app_handle: &AppHandle<R>) {
+    state.vault.lock().await.lock();
+    state.token.lock().await.take();
+    state.gateway.lock().await.disconnect().await;
+    if let Err(e) = app_handle.emit("vault-locked", ()) {
+        error!("Failed to emit vault lock: {}", e);
+    }
+}
+
+/// Locks the vault once the user has been idle for IDLE_TIMEOUT.
+pub async fn run_idle_lock<R: Runtime>(app_handle: AppHandle<R>) {
+    let state = app_handle.state::<AppState>();
+    loop {
+        tokio::time::sleep(IDLE_CHECK_INTERVAL).await;
+        if state.vault.lock().await.is_idle() {
+            info!("Locking vault after inactivity");
+            lock(&state, &app_handle).await;
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn vault_status(state: State<'_, AppState>) -> Result<VaultStatus, String> {
+    Ok(VaultStatus {
+        state: state.vault.lock().await.state(),
+        has_legacy_token: token_storage::token_file_exists(&token_storage::tokens_file_path()),
+    })
+}
+
+#[tauri::command]
+pub async fn create_vault<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    password: String,
+) -> Result<(), String> {
+    let password = Zeroizing::new(password);
+    {
+        let mut vault = state.vault.lock().await;
+        vault.create(&password, VaultContents::default()).await?;
+        migrate_legacy_token(&mut vault, &token_storage::tokens_file_path()).await;
+    }
+    sign_in(&state, app_handle).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unlock_vault<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+    password: String,
+) -> Result<(), String> {
+    let password = Zeroizing::new(password);
+    {
+        let mut vault = state.vault.lock().await;
+        vault.unlock(&password).await?;
+        migrate_legacy_token(&mut vault, &token_storage::tokens_file_path()).await;
+    }
+    sign_in(&state, app_handle).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn lock_vault<R: Runtime>(
+    state: State<'_, AppState>,
+    app_handle: AppHandle<R>,
+) -> Result<(), String> {
+    lock(&state, &app_handle).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn change_vault_password(
+    state: State<'_, AppState>,
+    old_password: String,
+    new_password: String,
+) -> Result<(), String> {
+    let (old_password, new_password) = (Zeroizing::new(old_password), Zeroizing::new(new_password));
+    state
+        .vault
+        .lock()
+        .await
+        .change_password(&old_password, &new_password)
+        .await
+}
+
+/// Called by the UI on user input, throttled, to keep the vault unlocked.
+#[tauri::command]
+pub async fn vault_activity(state: State<'_, AppState>) -> Result<(), String> {
+    state.vault.lock().await.touch();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse";
+
+    fn contents(token: &str) -> VaultContents {
+        VaultContents {
+            accounts: vec![SavedAccount::new(token.to_string())],
+        }
+    }
+
+    fn tokens(vault: &Vault) -> Vec<String> {
+        let accounts = &vault.contents().unwrap().accounts;
+        accounts.iter().map(|a| a.token.clone()).collect()
+    }
+
+    #[test]
+    fn seals_and_opens_contents() {
+        let key = [7u8; 32];
+        let file = seal(&key, KdfParams::generate(), &contents("abc")).unwrap();
+        let opened = open(&key, &file).unwrap();
+        assert_eq!(opened.accounts[0].token, "abc");
+
+        assert_eq!(
+            open(&[8u8; 32], &file).err().as_deref(),
+            Some("wrong_password")
+        );
+    }
+
+    #[tokio::test]
+    async fn unlocks_with_the_right_password_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.json");
+        let mut vault = Vault::load(path.clone()).await;
+        assert_eq!(vault.state(), VaultState::Missing);
+        vault.create(PASSWORD, contents("abc")).await.unwrap();
+
+        let mut vault = Vault::load(path).await;
+        assert_eq!(vault.state(), VaultState::Locked);
+        assert_eq!(
+            vault.unlock("wrong password").await.err().as_deref(),
+            Some("wrong_password")
+        );
+        assert_eq!(vault.state(), VaultState::Locked);
+        vault.unlock(PASSWORD).await.unwrap();
+        assert_eq!(tokens(&vault), ["abc"]);
+    }
+
+    #[tokio::test]
+    async fn never_overwrites_a_vault_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.json");
+        tokio::fs::write(&path, "{ not a vault").await.unwrap();
+
+        let mut vault = Vault::load(path.clone()).await;
+        assert_eq!(vault.state(), VaultState::Corrupt);
+        assert_eq!(
+            vault
+                .create(PASSWORD, contents("abc"))
+                .await
+                .err()
+                .as_deref(),
+            Some("vault_exists")
+        );
+        assert_eq!(
+            vault.unlock(PASSWORD).await.err().as_deref(),
+            Some("vault_corrupted")
+        );
+        assert_eq!(
+            tokio::fs::read_to_string(&path).await.unwrap(),
+            "{ not a vault"
+        );
+
+        // A vault created by another instance since loading is kept too
+        let other = dir.path().join("other.json");
+        let mut vault = Vault::load(other.clone()).await;
+        tokio::fs::write(&other, "{}").await.unwrap();
+        assert!(vault.create(PASSWORD, contents("abc")).await.is_err());
+    }
+
+    /// The vault's tokens after migrating into one holding `saved`, and
+    /// whether tokens.json is left.
+    async fn migrate(saved: VaultContents, legacy: &str) -> (Vec<String>, bool) {
+        let dir = tempfile::tempdir().unwrap();
+        let tokens_path = dir.path().join("tokens.json");
+        tokio::fs::write(&tokens_path, legacy).await.unwrap();
+
+        let mut vault = Vault::load(dir.path().join("vault.json")).await;
+        vault.create(PASSWORD, saved).await.unwrap();
+        migrate_legacy_token(&mut vault, &tokens_path).await;
+        (tokens(&vault), tokens_path.exists())
+    }
+
+    #[tokio::test]
+    async fn migrates_legacy_token_files() {
+        let migrated = (vec!["abc".to_string()], false);
+        let legacy = r#"{ "token": "abc" }"#;
+        assert_eq!(migrate(VaultContents::default(), legacy).await, migrated);
+        let migrated = (vec!["def".to_string()], false);
+        let legacy = r#"[{ "token": "def", "messanger_type": "Discord" }]"#;
+        assert_eq!(migrate(VaultContents::default(), legacy).await, migrated);
+    }
+
+    #[tokio::test]
+    async fn keeps_legacy_tokens_the_vault_does_not_have() {
+        let legacy = r#"{ "token": "abc" }"#;
+        let already_saved = (vec!["abc".to_string()], false);
+        assert_eq!(migrate(contents("abc"), legacy).await, already_saved);
+        let kept = (vec!["xyz".to_string()], true);
+        assert_eq!(migrate(contents("xyz"), legacy).await, kept);
+    }
+}
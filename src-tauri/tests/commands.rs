@@ -0,0 +1,141 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use common::{CHANNEL_ID, GUILD_ID, MockDiscord, TOKEN, TestApp, USER_ID, wait_until};
+use serde_json::{Value, json};
+
+#[test]
+fn set_token_rejects_unknown_tokens() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.create_vault();
+
+    let accepted: bool = app
+        .invoke("set_token", json!({ "token": "not-a-token" }))
+        .unwrap();
+
+    assert!(!accepted);
+    assert_eq!(mock.requests(), ["GET /users/@me"]);
+    assert_eq!(mock.connections(), 0);
+}
+
+#[test]
+fn set_token_requires_an_unlocked_vault() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+
+    let error = app
+        .invoke::<bool>("set_token", json!({ "token": TOKEN }))
+        .unwrap_err();
+
+    assert_eq!(error, "vault_locked");
+    assert_eq!(mock.connections(), 0);
+}
+
+#[test]
+fn account_summary_masks_the_token() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+
+    let summary: Value = app.invoke("get_account_summary", json!({})).unwrap();
+
+    assert_eq!(summary["user_id"], USER_ID);
+    assert_eq!(summary["username"], "mock");
+    assert_eq!(summary["validity"], "valid");
+    assert!(!summary["masked_token"].as_str().unwrap().contains(TOKEN));
+}
+
+#[test]
+fn fetches_guilds_and_channels() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+
+    let guilds: String = app.invoke("fetch_user_guilds", json!({})).unwrap();
+    let guilds: Value = serde_json::from_str(&guilds).unwrap();
+    assert_eq!(guilds[0]["id"], GUILD_ID);
+
+    let channels: String = app
+        .invoke("fetch_guild_channels", json!({ "guildId": GUILD_ID }))
+        .unwrap();
+    let channels: Value = serde_json::from_str(&channels).unwrap();
+    assert_eq!(channels[0]["id"], CHANNEL_ID);
+}
+
+#[test]
+fn send_message_posts_through_the_outbox() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+
+    let echoes: String = app
+        .invoke(
+            "send_message",
+            json!({ "channelId": CHANNEL_ID, "content": "hello" }),
+        )
+        .unwrap();
+    let echoes: Value = serde_json::from_str(&echoes).unwrap();
+    let nonce = echoes[0]["nonce"].clone();
+    assert_eq!(echoes[0]["content"], "hello");
+
+    wait_until(|| !mock.messages().is_empty());
+    let posted = &mock.messages()[0];
+    assert_eq!(posted["content"], "hello");
+    assert_eq!(posted["nonce"], nonce);
+
+    let created = app.expect_event("MessageCreate");
+    assert_eq!(created["content"], "hello");
+    assert_eq!(created["nonce"], nonce);
+}
+
+#[test]
+fn signs_out_when_a_request_is_unauthorized() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    app.expect_event("Ready");
+
+    mock.revoke_token();
+    let error = app
+        .invoke::<String>("fetch_user_guilds", json!({}))
+        .unwrap_err();
+
+    assert_eq!(error, "http_401");
+    assert_eq!(app.expect_event("SignedOut"), "token_revoked");
+    // The 401 was confirmed against /users/@me before signing out
+    let requests = mock.requests();
+    assert_eq!(
+        requests[requests.len() - 2..],
+        ["GET /users/@me/guilds", "GET /users/@me"]
+    );
+    assert_eq!(
+        app.invoke::<Value>("get_account_summary", json!({}))
+            .unwrap(),
+        Value::Null
+    );
+}
+
+#[test]
+fn stays_signed_in_when_the_token_check_fails() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    app.expect_event("Ready");
+
+    mock.fail("GET /users/@me/guilds", StatusCode::UNAUTHORIZED);
+    mock.fail("GET /users/@me", StatusCode::SERVICE_UNAVAILABLE);
+    let error = app
+        .invoke::<String>("fetch_user_guilds", json!({}))
+        .unwrap_err();
+    assert_eq!(error, "http_401");
+
+    // The watch checks the token, gets the outage and leaves it at that
+    wait_until(|| mock.requests().last().map(String::as_str) == Some("GET /users/@me"));
+    std::thread::sleep(Duration::from_millis(200));
+    let accounts: Value = app.invoke("list_accounts", json!({})).unwrap();
+    assert_eq!(accounts[0]["active"], true);
+    assert_eq!(accounts[0]["validity"], "valid");
+}
@@ -0,0 +1,567 @@
+//! An in-process stand-in for Discord: the REST routes the client uses and a
+//! gateway that speaks HELLO, IDENTIFY/RESUME, heartbeats and dispatches.
+//! `TestApp` runs the real commands on tauri's mock runtime against it.
+
+// Each test binary uses a different part of this module
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json, Router,
+    extract::{
+        Path, Request, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use messagify_lib::{AppState, spawn_background_tasks, with_commands};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use tauri::{
+    App, Listener, Manager, WebviewWindow, WebviewWindowBuilder,
+    ipc::{CallbackFn, InvokeBody},
+    test::{INVOKE_KEY, MockRuntime, get_ipc_response, mock_builder, mock_context, noop_assets},
+    webview::InvokeRequest,
+};
+use tempfile::TempDir;
+use tokio::{net::TcpListener, sync::broadcast};
+
+pub const TOKEN: &str = "mock-token";
+pub const USER_ID: &str = "100000000000000001";
+pub const GUILD_ID: &str = "200000000000000001";
+pub const CHANNEL_ID: &str = "300000000000000001";
+pub const PASSWORD: &str = "correct horse battery staple";
+
+// Accounts `/auth/login` knows: one without and one with MFA, and one whose
+// login hits an outage
+pub const EMAIL: &str = "mock@example.com";
+pub const MFA_EMAIL: &str = "mfa@example.com";
+pub const OUTAGE_EMAIL: &str = "outage@example.com";
+pub const ACCOUNT_PASSWORD: &str = "hunter22";
+pub const TOTP_CODE: &str = "123456";
+pub const BACKUP_CODE: &str = "abcd-efgh";
+const MFA_TICKET: &str = "mock-mfa-ticket";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_HEARTBEAT_INTERVAL: u64 = 41_250;
+
+/// Something the test makes the gateway send on the open connection.
+#[derive(Debug, Clone)]
+pub enum ServerFrame {
+    // An op 0 event; the mock numbers it
+    Dispatch(&'static str, Value),
+    Op(u8, Value),
+    Close(u16),
+}
+
+#[derive(Clone)]
+struct Shared {
+    gateway_url: String,
+    heartbeat_interval: u64,
+    frames: broadcast::Sender<ServerFrame>,
+    received: mpsc::Sender<Value>,
+    requests: Arc<Mutex<Vec<String>>>,
+    messages: Arc<Mutex<Vec<Value>>>,
+    connections: Arc<AtomicU64>,
+    next_id: Arc<AtomicU64>,
+    // Set once `TOKEN` stops being accepted
+    revoked: Arc<AtomicBool>,
+    // Routes answering with an error status instead, by "METHOD /path"
+    failures: Arc<Mutex<HashMap<String, StatusCode>>>,
+}
+
+pub struct MockDiscord {
+    addr: SocketAddr,
+    shared: Shared,
+    received: mpsc::Receiver<Value>,
+}
+
+impl MockDiscord {
+    pub fn start() -> Self {
+        Self::with_heartbeat_interval(DEFAULT_HEARTBEAT_INTERVAL)
+    }
+
+    pub fn with_heartbeat_interval(heartbeat_interval: u64) -> Self {
+        let listener = tauri::async_runtime::block_on(TcpListener::bind("127.0.0.1:0"))
+            .expect("failed to bind mock Discord");
+        let addr = listener.local_addr().unwrap();
+        let (received_tx, received) = mpsc::channel();
+        let shared = Shared {
+            gateway_url: format!("ws://{}", addr),
+            heartbeat_interval,
+            frames: broadcast::channel(16).0,
+            received: received_tx,
+            requests: Arc::default(),
+            messages: Arc::default(),
+            connections: Arc::default(),
+            next_id: Arc::new(AtomicU64::new(400_000_000_000_000_001)),
+            revoked: Arc::default(),
+            failures: Arc::default(),
+        };
+
+        let router = router(shared.clone());
+        tauri::async_runtime::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        Self {
+            addr,
+            shared,
+            received,
+        }
+    }
+
+    /// `endpoints.json` pointing the client at this server.
+    pub fn endpoints(&self) -> Value {
+        json!({
+            "api_base": format!("http://{}/api", self.addr),
+            "gateway_url": self.shared.gateway_url,
+        })
+    }
+
+    /// Sends a frame on the open gateway connection.
+    pub fn send(&self, frame: ServerFrame) {
+        self.shared
+            .frames
+            .send(frame)
+            .expect("no gateway connection");
+    }
+
+    /// The next payload the client sent with `op`, skipping heartbeats.
+    pub fn expect_op(&self, op: u8) -> Value {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let payload = self
+                .received
+                .recv_timeout(remaining)
+                .unwrap_or_else(|_| panic!("client never sent op {}", op));
+            if payload["op"] == op {
+                return payload;
+            }
+            assert_eq!(payload["op"], 1, "expected op {}, got {}", op, payload);
+        }
+    }
+
+    /// Gateway connections opened so far.
+    pub fn connections(&self) -> u64 {
+        self.shared.connections.load(Ordering::SeqCst)
+    }
+
+    /// REST requests so far, as "METHOD /path" below the API version.
+    pub fn requests(&self) -> Vec<String> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+
+    /// Rejects `TOKEN` from now on, as if it was revoked.
+    pub fn revoke_token(&self) {
+        self.shared.revoked.store(true, Ordering::SeqCst);
+    }
+
+    /// Answers `route`, e.g. "GET /users/@me", with `status` from now on.
+    pub fn fail(&self, route: &str, status: StatusCode) {
+        self.shared
+            .failures
+            .lock()
+            .unwrap()
+            .insert(route.to_string(), status);
+    }
+
+    /// Bodies posted to a channel's messages.
+    pub fn messages(&self) -> Vec<Value> {
+        self.shared.messages.lock().unwrap().clone()
+    }
+}
+
+pub fn user() -> Value {
+    json!({
+        "id": USER_ID,
+        "username": "mock",
+        "global_name": "Mock User",
+        "discriminator": "0",
+        "avatar": null,
+        "flags": 0,
+        "premium_type": 0,
+    })
+}
+
+pub fn other_user() -> Value {
+    json!({
+        "id": "100000000000000002",
+        "username": "friend",
+        "global_name": null,
+        "discriminator": "0",
+        "avatar": null,
+    })
+}
+
+pub const ROLE_ID: &str = "500000000000000001";
+
+/// GUILD_CREATE for `GUILD_ID`, owned by `USER_ID`, with `CHANNEL_ID` and a
+/// hoisted role `ROLE_ID`.
+pub fn guild() -> Value {
+    json!({
+        "id": GUILD_ID,
+        "name": "Mock Guild",
+        "owner_id": USER_ID,
+        "roles": [
+            { "id": GUILD_ID, "name": "@everyone", "position": 0, "permissions": "1024" },
+            { "id": ROLE_ID, "name": "Mods", "hoist": true, "position": 1, "permissions": "0" },
+        ],
+        "channels": [
+            { "id": CHANNEL_ID, "type": 0, "guild_id": GUILD_ID, "name": "general", "position": 0 },
+        ],
+        "members": [],
+        "presences": [],
+        "emojis": [],
+    })
+}
+
+pub fn message(id: &str, author: Value, content: &str) -> Value {
+    json!({
+        "id": id,
+        "type": 0,
+        "channel_id": CHANNEL_ID,
+        "author": author,
+        "content": content,
+        "timestamp": "2026-01-01T00:00:00.000000+00:00",
+        "edited_timestamp": null,
+        "mentions": [],
+        "mention_roles": [],
+        "mention_everyone": false,
+        "attachments": [],
+        "embeds": [],
+    })
+}
+
+fn router(shared: Shared) -> Router {
+    let api = Router::new()
+        .route("/users/@me", get(|| async { Json(user()) }))
+        .route(
+            "/users/@me/guilds",
+            get(|| async {
+                Json(json!([{
+                    "id": GUILD_ID,
+                    "name": "Mock Guild",
+                    "icon": null,
+                    "owner": true,
+                    "permissions": "2251799813685247",
+                }]))
+            }),
+        )
+        .route("/users/@me/channels", get(|| async { Json(json!([])) }))
+        .route("/guilds/{guild_id}/channels", get(guild_channels))
+        .route(
+            "/channels/{channel_id}/messages",
+            get(|| async { Json(json!([])) }).post(create_message),
+        )
+        .layer(middleware::from_fn_with_state(shared.clone(), authorize))
+        // Login needs no token
+        .route("/auth/login", post(login))
+        .route("/auth/mfa/{method}", post(submit_mfa));
+
+    Router::new()
+        .route("/", get(gateway))
+        .nest("/api/v10", api)
+        .with_state(shared)
+}
+
+/// Records the request, fails it if the test asked for that, and rejects
+/// anything without a valid `TOKEN`.
+async fn authorize(
+    State(shared): State<Shared>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = format!("{} {}", request.method(), request.uri().path());
+    shared.requests.lock().unwrap().push(route.clone());
+    if let Some(&status) = shared.failures.lock().unwrap().get(&route) {
+        let body = json!({ "message": status.to_string(), "code": 0 });
+        return (status, Json(body)).into_response();
+    }
+    let token = headers.get("authorization").and_then(|v| v.to_str().ok());
+    if token != Some(TOKEN) || shared.revoked.load(Ordering::SeqCst) {
+        let body = json!({ "message": "401: Unauthorized", "code": 0 });
+        return (StatusCode::UNAUTHORIZED, Json(body)).into_response();
+    }
+    next.run(request).await
+}
+
+fn invalid_form(field: &str, code: &str) -> Response {
+    let body = json!({
+        "code": 50035,
+        "message": "Invalid Form Body",
+        "errors": { field: { "_errors": [{ "code": code, "message": "" }] } },
+    });
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+async fn login(Json(body): Json<Value>) -> Response {
+    if body["login"] == OUTAGE_EMAIL {
+        let page = "<html><body>502 Bad Gateway</body></html>";
+        return (StatusCode::BAD_GATEWAY, page).into_response();
+    }
+    if body["password"] != ACCOUNT_PASSWORD {
+        return invalid_form("login", "INVALID_LOGIN");
+    }
+    match body["login"].as_str() {
+        Some(EMAIL) => Json(json!({ "user_id": USER_ID, "token": TOKEN })).into_response(),
+        Some(MFA_EMAIL) => Json(json!({
+            "user_id": USER_ID,
+            "mfa": true,
+            "sms": false,
+            "totp": true,
+            "backup": true,
+            "ticket": MFA_TICKET,
+        }))
+        .into_response(),
+        _ => invalid_form("login", "INVALID_LOGIN"),
+    }
+}
+
+async fn submit_mfa(Path(method): Path<String>, Json(body): Json<Value>) -> Response {
+    let code = match method.as_str() {
+        "totp" => TOTP_CODE,
+        "backup" => BACKUP_CODE,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    if body["ticket"] != MFA_TICKET || body["code"] != code {
+        let body = json!({ "code": 60008, "message": "Invalid two-factor code" });
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+    Json(json!({ "token": TOKEN })).into_response()
+}
+
+async fn guild_channels(Path(guild_id): Path<String>) -> Json<Value> {
+    Json(json!([{
+        "id": CHANNEL_ID,
+        "type": 0,
+        "guild_id": guild_id,
+        "name": "general",
+        "position": 0,
+        "parent_id": null,
+    }]))
+}
+
+async fn create_message(
+    State(shared): State<Shared>,
+    Path(channel_id): Path<String>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    shared.messages.lock().unwrap().push(body.clone());
+    let id = shared.next_id.fetch_add(1, Ordering::SeqCst).to_string();
+    let mut created = message(&id, user(), body["content"].as_str().unwrap_or_default());
+    created["channel_id"] = json!(channel_id);
+    created["nonce"] = body["nonce"].clone();
+    Json(created)
+}
+
+async fn gateway(State(shared): State<Shared>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| run_gateway(shared, socket))
+}
+
+/// One gateway connection. Answers IDENTIFY with READY, RESUME with RESUMED
+/// and heartbeats with ACKs, and forwards the test's frames.
+async fn run_gateway(shared: Shared, mut socket: WebSocket) {
+    let connection = shared.connections.fetch_add(1, Ordering::SeqCst) + 1;
+    let mut frames = shared.frames.subscribe();
+    let mut sequence = 0;
+
+    let hello = json!({ "op": 10, "d": { "heartbeat_interval": shared.heartbeat_interval } });
+    if send(&mut socket, &hello).await.is_err() {
+        return;
+    }
+
+    loop {
+        let reply = tokio::select! {
+            frame = frames.recv() => match frame {
+                Ok(ServerFrame::Dispatch(event, data)) => {
+                    sequence += 1;
+                    json!({ "op": 0, "t": event, "s": sequence, "d": data })
+                }
+                Ok(ServerFrame::Op(op, data)) => json!({ "op": op, "d": data }),
+                Ok(ServerFrame::Close(code)) => {
+                    close(&mut socket, code).await;
+                    return;
+                }
+                Err(_) => return,
+            },
+            msg = socket.recv() => {
+                let Some(Ok(Message::Text(text))) = msg else {
+                    return;
+                };
+                let payload: Value = serde_json::from_str(text.as_str()).unwrap();
+                let _ = shared.received.send(payload.clone());
+                match payload["op"].as_u64() {
+                    Some(1) => json!({ "op": 11 }),
+                    Some(2) if payload["d"]["token"] == TOKEN => {
+                        sequence += 1;
+                        let ready = json!({
+                            "v": 10,
+                            "user": user(),
+                            "session_id": format!("session-{}", connection),
+                            "resume_gateway_url": shared.gateway_url,
+                            "guilds": [],
+                            "private_channels": [],
+                            "users": [],
+                            "read_state": [],
+                        });
+                        json!({ "op": 0, "t": "READY", "s": sequence, "d": ready })
+                    }
+                    Some(2) => {
+                        close(&mut socket, 4004).await;
+                        return;
+                    }
+                    Some(6) => {
+                        sequence = payload["d"]["seq"].as_u64().unwrap_or(0) + 1;
+                        json!({ "op": 0, "t": "RESUMED", "s": sequence, "d": {} })
+                    }
+                    _ => continue,
+                }
+            }
+        };
+
+        if send(&mut socket, &reply).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, payload: &Value) -> Result<(), axum::Error> {
+    socket.send(Message::Text(payload.to_string().into())).await
+}
+
+async fn close(socket: &mut WebSocket, code: u16) {
+    let frame = CloseFrame {
+        code,
+        reason: "".into(),
+    };
+    let _ = socket.send(Message::Close(Some(frame))).await;
+}
+
+/// The app on tauri's mock runtime, with a fresh data directory whose
+/// `endpoints.json` points at a `MockDiscord`.
+pub struct TestApp {
+    app: App<MockRuntime>,
+    webview: WebviewWindow<MockRuntime>,
+    events: mpsc::Receiver<Value>,
+    data_dir: TempDir,
+}
+
+impl TestApp {
+    pub fn new(mock: &MockDiscord) -> Self {
+        let data_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            data_dir.path().join("endpoints.json"),
+            mock.endpoints().to_string(),
+        )
+        .unwrap();
+
+        let app = with_commands(mock_builder())
+            .build(mock_context(noop_assets()))
+            .expect("failed to build app");
+        app.manage(tauri::async_runtime::block_on(AppState::load(
+            data_dir.path(),
+        )));
+        spawn_background_tasks(app.handle());
+
+        let (events_tx, events) = mpsc::channel();
+        app.listen_any("discord-gateway", move |event| {
+            let _ = events_tx.send(serde_json::from_str(event.payload()).unwrap());
+        });
+        let webview = WebviewWindowBuilder::new(&app, "main", Default::default())
+            .build()
+            .unwrap();
+
+        Self {
+            app,
+            webview,
+            events,
+            data_dir,
+        }
+    }
+
+    /// The app data directory.
+    pub fn data_dir(&self) -> &std::path::Path {
+        self.data_dir.path()
+    }
+
+    /// Runs a command the way the frontend's `invoke` does.
+    pub fn invoke<T: DeserializeOwned>(&self, cmd: &str, args: Value) -> Result<T, Value> {
+        let request = InvokeRequest {
+            cmd: cmd.into(),
+            callback: CallbackFn(0),
+            error: CallbackFn(1),
+            url: if cfg!(windows) {
+                "http://tauri.localhost"
+            } else {
+                "tauri://localhost"
+            }
+            .parse()
+            .unwrap(),
+            body: InvokeBody::Json(args),
+            headers: Default::default(),
+            invoke_key: INVOKE_KEY.to_string(),
+        };
+        get_ipc_response(&self.webview, request)
+            .map(|body| body.deserialize().expect("unexpected response type"))
+    }
+
+    pub fn create_vault(&self) {
+        self.invoke::<()>("create_vault", json!({ "password": PASSWORD }))
+            .unwrap();
+    }
+
+    /// Creates the vault and signs in with `TOKEN`, which connects the
+    /// gateway.
+    pub fn sign_in(&self) {
+        self.create_vault();
+        assert!(
+            self.invoke::<bool>("set_token", json!({ "token": TOKEN }))
+                .unwrap()
+        );
+    }
+
+    /// Waits for the next "discord-gateway" event of type `kind`, skipping
+    /// others, and returns its data.
+    pub fn expect_event(&self, kind: &str) -> Value {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(remaining) {
+                Ok(event) if event["type"] == kind => return event["data"].clone(),
+                Ok(_) => {}
+                Err(_) => panic!("no {} event", kind),
+            }
+        }
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = self.invoke::<()>("stop_gateway", json!({}));
+    }
+}
+
+/// Polls until `condition` holds.
+pub fn wait_until(mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out waiting");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
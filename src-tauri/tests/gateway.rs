@@ -0,0 +1,190 @@
+mod common;
+
+use common::{
+    CHANNEL_ID, GUILD_ID, MockDiscord, ROLE_ID, ServerFrame, TOKEN, TestApp, guild, message,
+    other_user, user, wait_until,
+};
+use serde_json::{Value, json};
+
+#[test]
+fn identifies_and_reports_ready() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+
+    let identify = mock.expect_op(2);
+    assert_eq!(identify["d"]["token"], TOKEN);
+    app.expect_event("Connected");
+    let ready = app.expect_event("Ready");
+    assert_eq!(ready["session_id"], "session-1");
+    assert!(
+        app.invoke::<bool>("is_gateway_connected", json!({}))
+            .unwrap()
+    );
+}
+
+#[test]
+fn forwards_dispatched_messages() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+
+    mock.send(ServerFrame::Dispatch(
+        "MESSAGE_CREATE",
+        message("400000000000000001", other_user(), "hello from the mock"),
+    ));
+
+    let created = app.expect_event("MessageCreate");
+    assert_eq!(created["id"], "400000000000000001");
+    assert_eq!(created["content"], "hello from the mock");
+}
+
+#[test]
+fn heartbeats_with_the_last_sequence() {
+    let mock = MockDiscord::with_heartbeat_interval(100);
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+
+    // The first beat may go out before READY arrives
+    mock.expect_op(1);
+    let heartbeat = mock.expect_op(1);
+    assert_eq!(heartbeat["d"], 1);
+    // Acknowledged beats keep the connection alive
+    mock.expect_op(1);
+    assert_eq!(mock.connections(), 1);
+}
+
+#[test]
+fn resumes_after_reconnect() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+
+    mock.send(ServerFrame::Op(7, Value::Null));
+
+    let resume = mock.expect_op(6);
+    assert_eq!(resume["d"]["token"], TOKEN);
+    assert_eq!(resume["d"]["session_id"], "session-1");
+    assert_eq!(resume["d"]["seq"], 1);
+    assert_eq!(mock.connections(), 2);
+    app.expect_event("Connected");
+}
+
+#[test]
+fn identifies_again_after_invalid_session() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+
+    mock.send(ServerFrame::Op(9, json!(false)));
+
+    mock.expect_op(2);
+    let ready = app.expect_event("Ready");
+    assert_eq!(ready["session_id"], "session-2");
+}
+
+#[test]
+fn signs_out_when_the_token_is_rejected() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+
+    mock.send(ServerFrame::Close(4004));
+
+    assert_eq!(app.expect_event("SignedOut"), "gateway_closed_4004");
+    assert!(
+        !app.invoke::<bool>("is_gateway_connected", json!({}))
+            .unwrap()
+    );
+    assert_eq!(
+        app.invoke::<Value>("get_account_summary", json!({}))
+            .unwrap(),
+        Value::Null
+    );
+}
+
+#[test]
+fn subscribes_to_the_member_list() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+    mock.send(ServerFrame::Dispatch("GUILD_CREATE", guild()));
+
+    let args = json!({ "guildId": GUILD_ID });
+    wait_until(|| {
+        app.invoke::<Value>("fetch_guild_members", args.clone())
+            .is_ok()
+    });
+    let subscription = mock.expect_op(14);
+    assert_eq!(subscription["d"]["guild_id"], GUILD_ID);
+    assert_eq!(subscription["d"]["channels"][CHANNEL_ID], json!([[0, 99]]));
+
+    let mut friend = json!({
+        "user": other_user(),
+        "roles": [ROLE_ID],
+        "nick": null,
+        "avatar": "guild-avatar",
+        "presence": { "user": { "id": other_user()["id"] }, "status": "online" },
+    });
+    friend["user"]["avatar"] = json!("user-avatar");
+    let me = json!({ "user": user(), "roles": [], "nick": null, "avatar": null });
+    mock.send(ServerFrame::Dispatch(
+        "GUILD_MEMBER_LIST_UPDATE",
+        json!({
+            "guild_id": GUILD_ID,
+            "id": "everyone",
+            "ops": [{
+                "op": "SYNC",
+                "range": [0, 99],
+                "items": [
+                    { "group": { "id": ROLE_ID, "count": 1 } },
+                    { "member": friend },
+                    { "group": { "id": "offline", "count": 1 } },
+                    { "member": me },
+                ],
+            }],
+        }),
+    ));
+    assert_eq!(app.expect_event("GuildMembersUpdate"), GUILD_ID);
+
+    let groups: Value = app.invoke("fetch_guild_members", args).unwrap();
+    assert_eq!(groups[0]["name"], "Mods");
+    assert_eq!(groups[0]["members"][0]["username"], "friend");
+    assert_eq!(groups[0]["members"][0]["status"], "online");
+    assert_eq!(groups[0]["members"][0]["avatar"], "user-avatar");
+    assert_eq!(groups[0]["members"][0]["guild_avatar"], "guild-avatar");
+    assert_eq!(groups[1]["id"], "offline");
+    assert_eq!(groups[1]["members"][0]["display_name"], "Mock User");
+}
+
+#[test]
+fn role_changes_refresh_the_channel_tree() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+    mock.send(ServerFrame::Dispatch("GUILD_CREATE", guild()));
+
+    mock.send(ServerFrame::Dispatch(
+        "GUILD_ROLE_UPDATE",
+        json!({
+            "guild_id": GUILD_ID,
+            "role": { "id": ROLE_ID, "name": "Mods", "permissions": "1024" },
+        }),
+    ));
+
+    assert_eq!(app.expect_event("ChannelTreeUpdate"), GUILD_ID);
+}
@@ -0,0 +1,45 @@
+use messagify_lib::logging::redact;
+
+#[test]
+fn redacts_tokens() {
+    let token = "MTIzNDU2Nzg5MDEyMzQ1Njc4.GaBcDe.abcdefghijklmnopqrstuvwxyz0123";
+    assert_eq!(
+        redact(&format!("Validating {}", token)),
+        "Validating [token]"
+    );
+    assert_eq!(
+        redact(r#"Sent {"op":2,"d":{"token":"abc","intents":0}}"#),
+        r#"Sent {"op":2,"d":{"token":"[redacted]","intents":0}}"#
+    );
+    assert_eq!(
+        redact(r#"Login { password: "hunter2" }"#),
+        r#"Login { password: "[redacted]" }"#
+    );
+}
+
+#[test]
+fn redacts_authorization_headers() {
+    assert_eq!(
+        redact("Authorization: Bearer abc def}\nHost: discord.com"),
+        "Authorization: \"[redacted]\"\nHost: discord.com"
+    );
+    assert_eq!(
+        redact(r#"{"authorization": "Bot abc def", "accept": "*/*"}"#),
+        r#"{"authorization": "[redacted]", "accept": "*/*"}"#
+    );
+}
+
+#[test]
+fn redacts_emails_and_phone_numbers() {
+    assert_eq!(
+        redact("Logging in as jane.doe@example.com"),
+        "Logging in as [email]"
+    );
+    assert_eq!(redact("Phone: +1 555 555 0123"), "Phone: [phone]");
+}
+
+#[test]
+fn keeps_ids_and_timestamps() {
+    let line = "Message 1234567890123456789 in 987654321098765432 at 2024-01-01T12:00:00.000+00:00";
+    assert_eq!(redact(line), line);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::{
+    ACCOUNT_PASSWORD, BACKUP_CODE, EMAIL, MFA_EMAIL, MockDiscord, OUTAGE_EMAIL, TOTP_CODE, TestApp,
+    USER_ID,
+};
+use serde_json::{Value, json};
+
+fn login(app: &TestApp, email: &str, password: &str) -> Result<Value, Value> {
+    app.invoke("login", json!({ "email": email, "password": password }))
+}
+
+fn submit_code(app: &TestApp, method: &str, code: &str) -> Result<(), Value> {
+    app.invoke("submit_mfa_code", json!({ "method": method, "code": code }))
+}
+
+fn signed_in_user(app: &TestApp) -> Value {
+    let summary: Value = app.invoke("get_account_summary", json!({})).unwrap();
+    summary["user_id"].clone()
+}
+
+#[test]
+fn logs_in_with_a_password() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.create_vault();
+
+    let step = login(&app, EMAIL, ACCOUNT_PASSWORD).unwrap();
+
+    assert_eq!(step, json!({ "status": "logged_in" }));
+    assert_eq!(signed_in_user(&app), USER_ID);
+}
+
+#[test]
+fn logs_in_with_a_totp_code() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.create_vault();
+
+    let step = login(&app, MFA_EMAIL, ACCOUNT_PASSWORD).unwrap();
+    assert_eq!(
+        step,
+        json!({ "status": "mfa_required", "methods": ["totp", "backup"] })
+    );
+
+    let error = submit_code(&app, "totp", "000000").unwrap_err();
+    assert_eq!(error, json!({ "kind": "invalid_code" }));
+    // The ticket survives a wrong code
+    submit_code(&app, "totp", &format!(" {} ", TOTP_CODE)).unwrap();
+    assert_eq!(signed_in_user(&app), USER_ID);
+
+    let error = submit_code(&app, "totp", TOTP_CODE).unwrap_err();
+    assert_eq!(error, json!({ "kind": "no_pending_mfa" }));
+}
+
+#[test]
+fn logs_in_with_a_backup_code() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.create_vault();
+
+    login(&app, MFA_EMAIL, ACCOUNT_PASSWORD).unwrap();
+    submit_code(&app, "backup", BACKUP_CODE).unwrap();
+
+    assert_eq!(signed_in_user(&app), USER_ID);
+}
+
+#[test]
+fn rejects_invalid_credentials() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.create_vault();
+
+    let error = login(&app, EMAIL, "wrong password").unwrap_err();
+
+    assert_eq!(error, json!({ "kind": "invalid_credentials" }));
+    assert_eq!(
+        app.invoke::<Value>("get_account_summary", json!({}))
+            .unwrap(),
+        Value::Null
+    );
+}
+
+#[test]
+fn reports_server_errors_without_json() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.create_vault();
+
+    let error = login(&app, OUTAGE_EMAIL, ACCOUNT_PASSWORD).unwrap_err();
+
+    assert_eq!(error, json!({ "kind": "http", "status": 502 }));
+}
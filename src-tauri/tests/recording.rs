@@ -0,0 +1,108 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{MockDiscord, TOKEN, TestApp};
+use serde_json::{Value, json};
+
+fn fixture() -> &'static str {
+    concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/ready-and-message.jsonl"
+    )
+}
+
+#[test]
+fn records_frames_with_the_token_redacted() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    let path: String = app.invoke("start_gateway_recording", json!({})).unwrap();
+
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+    let stopped: Option<String> = app.invoke("stop_gateway_recording", json!({})).unwrap();
+    assert_eq!(stopped.as_deref(), Some(path.as_str()));
+
+    let recording = std::fs::read_to_string(&path).unwrap();
+    assert!(!recording.contains(TOKEN));
+    let frames: Vec<Value> = recording
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(frames[0]["direction"], "inbound");
+    assert_eq!(frames[0]["payload"]["op"], 10);
+    let identify = frames
+        .iter()
+        .find(|frame| frame["direction"] == "outbound" && frame["payload"]["op"] == 2)
+        .expect("IDENTIFY was not recorded");
+    assert_eq!(identify["payload"]["d"]["token"], "[redacted]");
+    assert!(
+        frames
+            .iter()
+            .any(|frame| frame["payload"]["t"] == "READY" && frame["at_ms"].is_i64())
+    );
+}
+
+#[test]
+fn replays_what_discord_sent() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+
+    let replayed: usize = app
+        .invoke("replay_gateway_recording", json!({ "path": fixture() }))
+        .unwrap();
+
+    // HELLO, READY and MESSAGE_CREATE; our IDENTIFY is skipped
+    assert_eq!(replayed, 3);
+    assert_eq!(app.expect_event("Ready")["session_id"], "session-1");
+    assert_eq!(
+        app.expect_event("MessageCreate")["content"],
+        "recorded hello"
+    );
+    assert_eq!(mock.connections(), 0);
+}
+
+#[test]
+fn replay_leaves_the_signed_in_account_alone() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+    app.invoke::<()>("stop_gateway", json!({})).unwrap();
+    let requests = mock.requests();
+    let vault = std::fs::read(app.data_dir().join("vault.json")).unwrap();
+
+    let replayed: usize = app
+        .invoke("replay_gateway_recording", json!({ "path": fixture() }))
+        .unwrap();
+
+    assert_eq!(replayed, 3);
+    app.expect_event("MessageCreate");
+    // Give anything the replay might have spawned time to run
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(mock.requests(), requests);
+    assert_eq!(
+        std::fs::read(app.data_dir().join("vault.json")).unwrap(),
+        vault
+    );
+}
+
+#[test]
+fn replay_is_refused_while_connected() {
+    let mock = MockDiscord::start();
+    let app = TestApp::new(&mock);
+    app.sign_in();
+    mock.expect_op(2);
+    app.expect_event("Ready");
+
+    let error = app
+        .invoke::<usize>(
+            "replay_gateway_recording",
+            json!({ "path": "missing.jsonl" }),
+        )
+        .unwrap_err();
+
+    assert_eq!(error, "gateway_connected");
+}
@@ -2,11 +2,13 @@ import { use, useEffect, useState } from "react";
 import type { Guild } from "../../types/discord";
 import { invoke } from "@tauri-apps/api/core";
 import { useNavigate } from "react-router-dom";
+import { useCdnUrl } from "../../hooks/useCdnUrl";
 
 const Guild = () => {
   const [guilds, setGuilds] = useState<Array<Guild>>([]);
   const [selectedId, setSelectedId] = useState<string>("home");
   const navigate = useNavigate();
+  const cdnUrl = useCdnUrl();
   useEffect(() => {
     invoke<string>("fetch_user_guilds")
       .then((json) => {
@@ -23,7 +25,7 @@ const Guild = () => {
   const renderGuildButton = (guild: Guild) => {
     const isSelected = guild.id === selectedId;
     const iconUrl = guild.icon
-      ? `https://cdn.discordapp.com/icons/${guild.id}/${guild.icon}.png?size=64`
+      ? `${cdnUrl}/icons/${guild.id}/${guild.icon}.png?size=64`
       : null;
 
     return (
@@ -1,6 +1,11 @@
 import { useState, useRef, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import type { DiscordMessage } from "../../types/discord";
+import { useNavigate } from "react-router-dom";
+import type {
+  DiscordMessage,
+  LinkTarget,
+  MessageContext,
+} from "../../types/discord";
 import { useMessageStore } from "../../stores/messageStore";
 
 type MainChatProps = {
@@ -9,6 +14,8 @@ type MainChatProps = {
   messages?: DiscordMessage[];
   loading?: boolean;
   error?: string | null;
+  // False when our permissions do not allow sending here
+  canSend?: boolean;
 };
 
 const Chat = ({
@@ -17,13 +24,30 @@ const Chat = ({
   messages,
   loading,
   error,
+  canSend = true,
 }: MainChatProps) => {
   const list = messages ?? [];
   const [messageInput, setMessageInput] = useState("");
-  const [sending, setSending] = useState(false);
-  const addMessage = useMessageStore((state) => state.addMessage);
+  const deleteMessage = useMessageStore((state) => state.deleteMessage);
+  const replaceMessages = useMessageStore((state) => state.replaceMessages);
+  const showContext = useMessageStore((state) => state.showContext);
+  const clearJumpTarget = useMessageStore((state) => state.clearJumpTarget);
+  // Set while showing an older part of the channel
+  const jumpTarget = useMessageStore((state) =>
+    state.jumpTarget?.channelId === channelId
+      ? state.jumpTarget.messageId
+      : null
+  );
+  const navigate = useNavigate();
   const inputRef = useRef<HTMLTextAreaElement>(null);
 
+  useEffect(() => {
+    if (!jumpTarget) return;
+    document
+      .getElementById(`message-${jumpTarget}`)
+      ?.scrollIntoView({ block: "center" });
+  }, [jumpTarget, list]);
+
   // Auto-resize textarea
   useEffect(() => {
     if (inputRef.current) {
@@ -32,33 +56,100 @@ const Chat = ({
     }
   }, [messageInput]);
 
-  const handleSubmit = async (e: React.FormEvent<HTMLFormElement>) => {
+  const handleSubmit = (e: React.FormEvent<HTMLFormElement>) => {
     e.preventDefault();
-    if (!channelId || !messageInput.trim() || sending) return;
+    if (!channelId || !canSend || !messageInput.trim()) return;
 
     const content = messageInput.trim();
     setMessageInput("");
-    setSending(true);
+    sendContent(channelId, content);
+  };
 
-    try {
-      const json = await invoke<string>("send_message", {
-        channelId: channelId,
-        content: content,
-      });
-      const sentMessage = JSON.parse(json) as DiscordMessage;
-      // Add the sent message to the store immediately
-      addMessage(channelId, sentMessage);
-    } catch (e) {
+  const sendContent = (
+    channelId: string,
+    content: string,
+    overflow?: "split" | "attachment"
+  ) => {
+    // The backend queues the message, shows it as pending right away and
+    // reports its progress through gateway events.
+    invoke<string>("send_message", {
+      channelId: channelId,
+      content: content,
+      overflow: overflow,
+    }).catch((e) => {
+      if (e === "message_too_long") {
+        const split = window.confirm(
+          "This message is over 2000 characters. Split it into several messages? Cancel sends it as message.txt instead."
+        );
+        sendContent(channelId, content, split ? "split" : "attachment");
+        return;
+      }
       console.error("Failed to send message:", e);
-      // Restore the message input on error
-      setMessageInput(content);
-    } finally {
-      setSending(false);
-      // Reset textarea height
-      if (inputRef.current) {
-        inputRef.current.style.height = "auto";
+      // Rejected before it was queued; give the text back
+      if (e === "missing_permissions" || e === "not_authenticated") {
+        setMessageInput(content);
       }
-    }
+    });
+  };
+
+  const retryMessage = (nonce: string) => {
+    invoke<string>("retry_message", { nonce }).catch((e) => {
+      console.error("Failed to retry message:", e);
+    });
+  };
+
+  const discardMessage = (message: DiscordMessage) => {
+    invoke("discard_message", { nonce: message.id }).catch((e) => {
+      console.error("Failed to discard message:", e);
+    });
+    deleteMessage(message.channel_id, message.id);
+  };
+
+  const jumpToDate = (value: string) => {
+    if (!channelId || !value) return;
+    invoke<MessageContext>("jump_to_date", {
+      channelId,
+      timestamp: new Date(value).getTime(),
+    })
+      .then((context) => {
+        showContext(channelId, context.messages, context.target_id);
+      })
+      .catch((e) => {
+        console.error("Failed to jump to date:", e);
+      });
+  };
+
+  const openLink = (url: string) => {
+    invoke<LinkTarget>("open_message_link", { url })
+      .then((target) => {
+        if (target.context) {
+          showContext(
+            target.channel_id,
+            target.context.messages,
+            target.context.target_id
+          );
+        }
+        navigate(
+          target.guild_id
+            ? `/discord/guild/${target.guild_id}/${target.channel_id}`
+            : `/discord/user/${target.channel_id}`
+        );
+      })
+      .catch((e) => {
+        console.error("Failed to open link:", e);
+      });
+  };
+
+  const jumpToPresent = () => {
+    if (!channelId) return;
+    invoke<string>("fetch_channel_messages", { channelId, limit: 50 })
+      .then((json) => {
+        replaceMessages(channelId, JSON.parse(json) as DiscordMessage[]);
+        clearJumpTarget();
+      })
+      .catch((e) => {
+        console.error("Failed to load latest messages:", e);
+      });
   };
 
   const handleKeyDown = (e: React.KeyboardEvent<HTMLTextAreaElement>) => {
@@ -79,6 +170,25 @@ const Chat = ({
         <h3 className="font-semibold text-white">
           {channelTitle ?? "Select a channel"}
         </h3>
+        {channelId && (
+          <div className="ml-auto flex items-center gap-2 text-sm">
+            {jumpTarget && (
+              <button
+                type="button"
+                onClick={jumpToPresent}
+                className="text-indigo-300 hover:underline"
+              >
+                Jump to present
+              </button>
+            )}
+            <input
+              type="date"
+              onChange={(e) => jumpToDate(e.target.value)}
+              title="Jump to date"
+              className="bg-gray-700 text-gray-300 rounded px-2 py-1"
+            />
+          </div>
+        )}
       </div>
 
       {/* Messages — flex-col-reverse renders newest (index 0) at bottom
@@ -99,7 +209,10 @@ const Chat = ({
             return (
               <div
                 key={m.id}
-                className="flex space-x-4 group hover:bg-gray-800/50 rounded px-2 py-1 -mx-2"
+                id={`message-${m.id}`}
+                className={`flex space-x-4 group hover:bg-gray-800/50 rounded px-2 py-1 -mx-2 ${
+                  m.id === jumpTarget ? "bg-indigo-500/10" : ""
+                }`}
               >
                 <div className="w-10 h-10 bg-indigo-500 rounded-full flex items-center justify-center shrink-0">
                   <span className="text-white text-sm font-bold">
@@ -115,11 +228,68 @@ const Chat = ({
                       {new Date(m.timestamp).toLocaleString()}
                     </span>
                   </div>
-                  <div className="text-gray-300 mt-1 whitespace-pre-wrap wrap-break-word">
+                  <div
+                    className={`mt-1 whitespace-pre-wrap wrap-break-word ${
+                      m.send_state === "failed"
+                        ? "text-red-300"
+                        : m.send_state
+                          ? "text-gray-500"
+                          : "text-gray-300"
+                    }`}
+                  >
                     {m.content || (
                       <span className="text-gray-500">(no content)</span>
                     )}
                   </div>
+                  {m.resolved?.links.map((link) => (
+                    <button
+                      key={link.url}
+                      type="button"
+                      onClick={() => openLink(link.url)}
+                      className="mt-1 block w-full text-left border-l-4 border-gray-500 pl-3 py-1 text-sm rounded-r hover:bg-gray-800"
+                    >
+                      <div className="text-xs text-gray-400">
+                        #{link.channel_name ?? "unknown channel"}
+                      </div>
+                      {link.message && (
+                        <>
+                          <span className="font-semibold text-white">
+                            {link.message.author.display_name}
+                          </span>
+                          <div className="text-gray-300 whitespace-pre-wrap wrap-break-word">
+                            {link.message.snippet ||
+                              (link.message.has_attachments ? (
+                                <span className="text-gray-500">Attachment</span>
+                              ) : null)}
+                          </div>
+                        </>
+                      )}
+                    </button>
+                  ))}
+                  {m.send_state === "pending" && m.retry_at && (
+                    <div className="text-xs text-gray-500 mt-1">
+                      Waiting to send…
+                    </div>
+                  )}
+                  {m.send_state === "failed" && (
+                    <div className="text-xs text-red-400 mt-1 space-x-2">
+                      <span>Failed to send.</span>
+                      <button
+                        type="button"
+                        onClick={() => retryMessage(m.id)}
+                        className="underline hover:text-red-300"
+                      >
+                        Retry
+                      </button>
+                      <button
+                        type="button"
+                        onClick={() => discardMessage(m)}
+                        className="underline hover:text-red-300"
+                      >
+                        Discard
+                      </button>
+                    </div>
+                  )}
                 </div>
               </div>
             );
@@ -137,18 +307,22 @@ const Chat = ({
                 value={messageInput}
                 onChange={(e) => setMessageInput(e.target.value)}
                 onKeyDown={handleKeyDown}
-                placeholder={`Message #${channelTitle ?? "channel"}`}
-                disabled={sending}
+                disabled={!canSend}
+                placeholder={
+                  canSend
+                    ? `Message #${channelTitle ?? "channel"}`
+                    : "You do not have permission to send messages in this channel"
+                }
                 rows={1}
                 className="w-full bg-gray-700 text-white placeholder-gray-400 rounded-lg px-4 py-2 pr-12 resize-none focus:outline-none focus:ring-2 focus:ring-indigo-500 disabled:opacity-50 disabled:cursor-not-allowed max-h-48 overflow-y-auto"
               />
             </div>
             <button
               type="submit"
-              disabled={!messageInput.trim() || sending}
+              disabled={!canSend || !messageInput.trim()}
               className="px-4 py-2 bg-indigo-500 text-white rounded-lg hover:bg-indigo-600 disabled:opacity-50 disabled:cursor-not-allowed transition-colors font-medium"
             >
-              {sending ? "Sending..." : "Send"}
+              Send
             </button>
           </form>
         </div>
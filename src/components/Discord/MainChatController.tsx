@@ -1,13 +1,16 @@
 import { invoke } from "@tauri-apps/api/core";
 import { useEffect, useState } from "react";
 import { useParams } from "react-router-dom";
-import type { DiscordMessage } from "../../types/discord";
+import type {
+  ChannelCapabilities,
+  DiscordMessage,
+} from "../../types/discord";
 import Chat from "./MainChat";
 import { useMessageStore } from "../../stores/messageStore";
 
 const EMPTY_ARRAY: DiscordMessage[] = [];
 export default function ChatController() {
-  const { channelId, userChannelId } = useParams();
+  const { guildId, channelId, userChannelId } = useParams();
   const targetId = channelId ?? userChannelId as string;
   const setMessages = useMessageStore((state) => state.setMessages);
   const messages = useMessageStore(
@@ -15,8 +18,29 @@ export default function ChatController() {
   );
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [capabilities, setCapabilities] = useState<
+    Record<string, ChannelCapabilities>
+  >({});
   useEffect(() => {
-    if (!targetId || messages.length >= 50)
+    // DMs are never restricted
+    if (!guildId) return void setCapabilities({});
+    let cancelled = false;
+    invoke<Record<string, ChannelCapabilities>>("fetch_channel_capabilities", {
+      guildId,
+    })
+      .then((caps) => !cancelled && setCapabilities(caps))
+      .catch((e) => {
+        console.error("Failed to fetch channel capabilities:", e);
+        !cancelled && setCapabilities({});
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [guildId, channelId]);
+  useEffect(() => {
+    // Jumping into the channel already loaded the messages to show
+    const jumped = useMessageStore.getState().jumpTarget?.channelId === targetId;
+    if (!targetId || jumped || messages.length >= 50)
       return void (setError(null), setLoading(false));
     let cancelled = false;
     setLoading(true);
@@ -46,6 +70,7 @@ export default function ChatController() {
       messages={messages}
       loading={loading}
       error={error}
+      canSend={capabilities[targetId]?.send ?? true}
     />
   );
 }
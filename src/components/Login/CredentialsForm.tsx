@@ -0,0 +1,157 @@
+import { invoke } from "@tauri-apps/api/core";
+import { useState } from "react";
+
+type MfaMethod = "totp" | "backup";
+
+type LoginStep =
+  | { status: "logged_in" }
+  | { status: "mfa_required"; methods: MfaMethod[] };
+
+// Matches Rust LoginError
+type LoginError =
+  | { kind: "invalid_credentials" }
+  | { kind: "captcha_required"; sitekey: string | null; service: string | null }
+  | { kind: "new_location_verification" }
+  | { kind: "invalid_code" }
+  | { kind: "no_pending_mfa" }
+  | { kind: "rate_limited"; retry_after_ms: number }
+  | { kind: "token_rejected" }
+  | { kind: "request" }
+  | { kind: "parse" }
+  | { kind: "http"; status: number }
+  | { kind: "storage"; message: string };
+
+function describeError(e: LoginError): string {
+  switch (e.kind) {
+    case "invalid_credentials":
+      return "Wrong email or password.";
+    case "captcha_required":
+      return "Discord wants a captcha solved. Log in once in the official client, or paste a token instead.";
+    case "new_location_verification":
+      return "Discord sent you an email to approve this login location. Approve it, then try again.";
+    case "invalid_code":
+      return "That code did not work.";
+    case "no_pending_mfa":
+      return "The login expired, start again.";
+    case "rate_limited":
+      return `Too many attempts, try again in ${Math.ceil(e.retry_after_ms / 1000)}s.`;
+    case "token_rejected":
+      return "Discord did not accept the new session.";
+    case "request":
+      return "Could not reach Discord.";
+    case "storage":
+      return e.message;
+    default:
+      return "Login failed.";
+  }
+}
+
+/** Email/password login, followed by a TOTP or backup code when required. */
+export default function CredentialsForm({ onLoggedIn }: { onLoggedIn: () => void }) {
+  const [email, setEmail] = useState("");
+  const [password, setPassword] = useState("");
+  const [mfaMethods, setMfaMethods] = useState<MfaMethod[] | null>(null);
+  const [method, setMethod] = useState<MfaMethod>("totp");
+  const [code, setCode] = useState("");
+  const [error, setError] = useState<string | null>(null);
+  const [busy, setBusy] = useState(false);
+
+  async function run(action: () => Promise<void>) {
+    setBusy(true);
+    setError(null);
+    try {
+      await action();
+    } catch (e) {
+      console.error(e);
+      setError(
+        typeof e === "object" && e !== null && "kind" in e
+          ? describeError(e as LoginError)
+          : String(e)
+      );
+    } finally {
+      setBusy(false);
+    }
+  }
+
+  function submitCredentials(e: React.FormEvent<HTMLFormElement>) {
+    e.preventDefault();
+    run(async () => {
+      const step = await invoke<LoginStep>("login", { email, password });
+      if (step.status === "logged_in") {
+        onLoggedIn();
+      } else {
+        setPassword("");
+        setMfaMethods(step.methods);
+        setMethod(step.methods[0] ?? "totp");
+      }
+    });
+  }
+
+  function submitCode(e: React.FormEvent<HTMLFormElement>) {
+    e.preventDefault();
+    run(async () => {
+      await invoke("submit_mfa_code", { method, code });
+      onLoggedIn();
+    });
+  }
+
+  const inputClass =
+    "w-full rounded-xl bg-transparent px-5 p-2 text-gray-200 outline-1 outline-gray-500 focus:outline-none";
+
+  return mfaMethods ? (
+    <form onSubmit={submitCode} className="space-y-2 rounded-3xl bg-gray-700 p-4">
+      <div className="flex gap-2 text-sm text-gray-300">
+        {mfaMethods.map((m) => (
+          <button
+            key={m}
+            type="button"
+            onClick={() => setMethod(m)}
+            className={m === method ? "font-bold text-indigo-300" : ""}
+          >
+            {m === "totp" ? "Authenticator code" : "Backup code"}
+          </button>
+        ))}
+      </div>
+      <input
+        autoFocus
+        value={code}
+        onChange={(e) => setCode(e.target.value)}
+        placeholder={method === "totp" ? "6-digit code" : "8-character backup code"}
+        className={inputClass}
+      />
+      {error ? <p className="text-xs text-red-400">{error}</p> : null}
+      <button
+        type="submit"
+        disabled={busy || !code}
+        className="w-full rounded-2xl bg-indigo-500 px-8 py-2 font-bold text-gray-200 disabled:opacity-60"
+      >
+        Verify
+      </button>
+    </form>
+  ) : (
+    <form onSubmit={submitCredentials} className="space-y-2 rounded-3xl bg-gray-700 p-4">
+      <input
+        type="email"
+        value={email}
+        onChange={(e) => setEmail(e.target.value)}
+        placeholder="Email"
+        className={inputClass}
+      />
+      <input
+        type="password"
+        value={password}
+        onChange={(e) => setPassword(e.target.value)}
+        placeholder="Password"
+        className={inputClass}
+      />
+      {error ? <p className="text-xs text-red-400">{error}</p> : null}
+      <button
+        type="submit"
+        disabled={busy || !email || !password}
+        className="w-full rounded-2xl bg-indigo-500 px-8 py-2 font-bold text-gray-200 disabled:opacity-60"
+      >
+        Log in
+      </button>
+    </form>
+  );
+}
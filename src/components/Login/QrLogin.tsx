@@ -0,0 +1,93 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useEffect, useState } from "react";
+
+interface RemoteAuthUser {
+  id: string;
+  discriminator: string;
+  avatar: string | null;
+  username: string;
+}
+
+// Matches Rust RemoteAuthEvent
+type RemoteAuthEvent =
+  | { type: "QrCode"; data: { url: string; svg: string } }
+  | { type: "Scanned"; data: RemoteAuthUser }
+  | { type: "LoggedIn" }
+  | { type: "Cancelled" }
+  | { type: "Failed"; data: string };
+
+/** Login by scanning a QR code with the Discord app on a phone. */
+export default function QrLogin({ onLoggedIn }: { onLoggedIn: () => void }) {
+  const [svg, setSvg] = useState<string | null>(null);
+  const [user, setUser] = useState<RemoteAuthUser | null>(null);
+  const [message, setMessage] = useState<string | null>(null);
+  const [running, setRunning] = useState(false);
+
+  useEffect(() => {
+    const unlisten = listen<RemoteAuthEvent>("discord-remote-auth", (event) => {
+      const payload = event.payload;
+      switch (payload.type) {
+        case "QrCode":
+          setSvg(payload.data.svg);
+          break;
+        case "Scanned":
+          setUser(payload.data);
+          break;
+        case "LoggedIn":
+          setRunning(false);
+          onLoggedIn();
+          break;
+        case "Cancelled":
+          setRunning(false);
+          setSvg(null);
+          setUser(null);
+          setMessage("The QR code expired or was cancelled.");
+          break;
+        case "Failed":
+          setRunning(false);
+          setSvg(null);
+          setMessage(`QR login failed: ${payload.data}`);
+          break;
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+      invoke("cancel_remote_auth").catch(console.error);
+    };
+  }, [onLoggedIn]);
+
+  async function start() {
+    setMessage(null);
+    setUser(null);
+    setRunning(true);
+    await invoke("start_remote_auth");
+  }
+
+  return (
+    <div className="flex flex-col items-center gap-2 rounded-3xl bg-gray-700 p-4 text-sm text-gray-300">
+      {user ? (
+        <p>Confirm the login on your phone, {user.username}.</p>
+      ) : svg ? (
+        <>
+          <img
+            src={`data:image/svg+xml;utf8,${encodeURIComponent(svg)}`}
+            alt="QR code to scan with the Discord app"
+            className="h-48 w-48 rounded-lg bg-white"
+          />
+          <p>Scan with the Discord app on your phone.</p>
+        </>
+      ) : (
+        <button
+          type="button"
+          disabled={running}
+          onClick={() => start().catch(console.error)}
+          className="rounded-2xl bg-indigo-500 px-8 py-2 font-bold text-gray-200 disabled:opacity-60"
+        >
+          {running ? "Preparing QR code…" : "Log in with QR code"}
+        </button>
+      )}
+      {message ? <p className="text-xs text-red-400">{message}</p> : null}
+    </div>
+  );
+}
@@ -0,0 +1,184 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { type ReactNode, useCallback, useEffect, useState } from "react";
+
+type VaultState = "missing" | "corrupt" | "locked" | "unlocked";
+
+interface VaultStatus {
+  state: VaultState;
+  has_legacy_token: boolean;
+}
+
+const ERROR_MESSAGES: Record<string, string> = {
+  wrong_password: "Wrong password.",
+  password_too_short: "Use at least 8 characters.",
+  vault_corrupted: "The vault file is damaged and cannot be opened.",
+};
+
+function describeError(e: unknown): string {
+  return ERROR_MESSAGES[String(e)] ?? String(e);
+}
+
+/**
+ * Shows a create or unlock form until the credential vault is unlocked, then
+ * renders its children.
+ */
+export default function VaultGate({ children }: { children: ReactNode }) {
+  const [status, setStatus] = useState<VaultStatus | null>(null);
+  const [password, setPassword] = useState("");
+  const [confirm, setConfirm] = useState("");
+  const [error, setError] = useState<string | null>(null);
+  const [busy, setBusy] = useState(false);
+
+  const refresh = useCallback(async () => {
+    setStatus(await invoke<VaultStatus>("vault_status"));
+  }, []);
+
+  useEffect(() => {
+    refresh().catch(console.error);
+    // Locked by the user or after inactivity
+    const unlisten = listen("vault-locked", () => {
+      refresh().catch(console.error);
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [refresh]);
+
+  if (!status) {
+    return null;
+  }
+  if (status.state === "unlocked") {
+    return <>{children}</>;
+  }
+
+  if (status.state === "corrupt") {
+    return (
+      <main className="flex h-screen w-screen items-center justify-center bg-slate-950 text-slate-100">
+        <div className="w-full max-w-sm space-y-3 rounded-xl border border-slate-800 bg-slate-900/40 p-6">
+          <h1 className="text-2xl font-bold tracking-tight">Vault damaged</h1>
+          <p className="text-sm text-slate-400">
+            {ERROR_MESSAGES.vault_corrupted} Restore vault.json from a backup,
+            or move it away to start over, then restart Messagify.
+          </p>
+        </div>
+      </main>
+    );
+  }
+
+  const creating = status.state === "missing";
+
+  async function submit(e: React.FormEvent<HTMLFormElement>) {
+    e.preventDefault();
+    if (creating && password !== confirm) {
+      setError("Passwords do not match.");
+      return;
+    }
+    setBusy(true);
+    setError(null);
+    try {
+      await invoke(creating ? "create_vault" : "unlock_vault", { password });
+      setPassword("");
+      setConfirm("");
+      await refresh();
+    } catch (e) {
+      setError(describeError(e));
+    } finally {
+      setBusy(false);
+    }
+  }
+
+  return (
+    <main className="flex h-screen w-screen items-center justify-center bg-slate-950 text-slate-100">
+      <form
+        onSubmit={submit}
+        className="w-full max-w-sm space-y-3 rounded-xl border border-slate-800 bg-slate-900/40 p-6"
+      >
+        <h1 className="text-2xl font-bold tracking-tight">
+          {creating ? "Set a password" : "Unlock Messagify"}
+        </h1>
+        <p className="text-sm text-slate-400">
+          {creating
+            ? "Your Discord token is stored encrypted with this password."
+            : "Enter your password to open your saved accounts."}
+        </p>
+        {creating && status.has_legacy_token ? (
+          <p className="text-xs text-indigo-300">
+            Your existing saved token will be moved into the encrypted vault.
+          </p>
+        ) : null}
+        <input
+          type="password"
+          autoFocus
+          value={password}
+          onChange={(e) => setPassword(e.target.value)}
+          placeholder="Password"
+          className="w-full rounded-md bg-slate-800 px-3 py-2 text-sm focus:outline-none"
+        />
+        {creating ? (
+          <input
+            type="password"
+            value={confirm}
+            onChange={(e) => setConfirm(e.target.value)}
+            placeholder="Confirm password"
+            className="w-full rounded-md bg-slate-800 px-3 py-2 text-sm focus:outline-none"
+          />
+        ) : null}
+        {error ? <p className="text-xs text-red-400">{error}</p> : null}
+        <button
+          type="submit"
+          disabled={busy || !password}
+          className="w-full rounded-md bg-indigo-600 px-3 py-2 text-sm font-semibold text-white hover:bg-indigo-500 disabled:cursor-not-allowed disabled:opacity-60"
+        >
+          {busy ? "Working…" : creating ? "Create vault" : "Unlock"}
+        </button>
+      </form>
+    </main>
+  );
+}
+
+/** Form for re-encrypting the vault under a new password. */
+export function ChangePasswordForm() {
+  const [oldPassword, setOldPassword] = useState("");
+  const [newPassword, setNewPassword] = useState("");
+  const [message, setMessage] = useState<string | null>(null);
+
+  async function submit(e: React.FormEvent<HTMLFormElement>) {
+    e.preventDefault();
+    try {
+      await invoke("change_vault_password", { oldPassword, newPassword });
+      setOldPassword("");
+      setNewPassword("");
+      setMessage("Password changed.");
+    } catch (e) {
+      setMessage(describeError(e));
+    }
+  }
+
+  return (
+    <form onSubmit={submit} className="flex flex-wrap items-center gap-2">
+      <input
+        type="password"
+        value={oldPassword}
+        onChange={(e) => setOldPassword(e.target.value)}
+        placeholder="Current password"
+        className="rounded-md bg-slate-800 px-3 py-2 text-sm focus:outline-none"
+      />
+      <input
+        type="password"
+        value={newPassword}
+        onChange={(e) => setNewPassword(e.target.value)}
+        placeholder="New password"
+        className="rounded-md bg-slate-800 px-3 py-2 text-sm focus:outline-none"
+      />
+      <button
+        type="submit"
+        disabled={!oldPassword || !newPassword}
+        className="rounded-md bg-slate-800 px-3 py-2 text-sm font-semibold text-slate-100 hover:bg-slate-700 disabled:opacity-60"
+      >
+        Change password
+      </button>
+      {message ? <span className="text-xs text-slate-400">{message}</span> : null}
+    </form>
+  );
+}
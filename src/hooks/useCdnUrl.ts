@@ -0,0 +1,36 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+
+const DEFAULT_CDN_URL = "https://cdn.discordapp.com";
+
+// The endpoints only change on restart, so one lookup serves every caller
+let cdnUrl: Promise<string> | null = null;
+
+function loadCdnUrl(): Promise<string> {
+  cdnUrl ??= invoke<{ cdn_url: string }>("get_endpoints")
+    .then((endpoints) => endpoints.cdn_url.replace(/\/+$/, ""))
+    .catch((e) => {
+      console.error("Failed to load endpoints:", e);
+      return DEFAULT_CDN_URL;
+    });
+  return cdnUrl;
+}
+
+/**
+ * Base URL for avatars and icons, from the backend's endpoint config.
+ */
+export function useCdnUrl(): string {
+  const [url, setUrl] = useState(DEFAULT_CDN_URL);
+
+  useEffect(() => {
+    let cancelled = false;
+    loadCdnUrl().then((loaded) => {
+      if (!cancelled) setUrl(loaded);
+    });
+    return () => {
+      cancelled = true;
+    };
+  }, []);
+
+  return url;
+}
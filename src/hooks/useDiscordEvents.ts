@@ -1,7 +1,11 @@
 import { useEffect, useRef } from "react";
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { useMessageStore } from "../stores/messageStore";
-import type { DiscordMessage, DiscordUserLite } from "../types/discord";
+import type {
+  DiscordMessage,
+  DiscordUserLite,
+  ResolvedEntities,
+} from "../types/discord";
 
 // Gateway event types matching Rust GatewayEvent enum
 interface GatewayEventPayload {
@@ -9,8 +13,12 @@ interface GatewayEventPayload {
     | "MessageCreate"
     | "MessageUpdate"
     | "MessageDelete"
+    | "MessageResolved"
+    | "MessagePending"
+    | "MessageSendFailed"
     | "Ready"
     | "GatewayError"
+    | "SignedOut"
     | "Connected"
     | "Disconnected";
   data?: unknown;
@@ -21,6 +29,13 @@ interface MessageUpdateData {
   channel_id: string;
   content?: string;
   edited_timestamp?: string | null;
+  resolved?: ResolvedEntities;
+}
+
+interface MessageResolvedData {
+  id: string;
+  channel_id: string;
+  resolved: ResolvedEntities;
 }
 
 interface MessageDeleteData {
@@ -62,6 +77,8 @@ export function useDiscordEvents() {
                 content: data.content,
                 timestamp: data.timestamp,
                 edited_timestamp: data.edited_timestamp,
+                nonce: data.nonce,
+                resolved: data.resolved,
                 author: {
                   id: data.author.id,
                   username: data.author.username,
@@ -88,6 +105,32 @@ export function useDiscordEvents() {
               updateMessage(data.channel_id, data.id, {
                 content: data.content,
                 edited_timestamp: data.edited_timestamp,
+                // Embed-only updates are not resolved again
+                ...(data.resolved && { resolved: data.resolved }),
+              });
+              break;
+            }
+
+            case "MessageResolved": {
+              const data = payload.data as MessageResolvedData;
+              updateMessage(data.channel_id, data.id, {
+                resolved: data.resolved,
+              });
+              break;
+            }
+
+            case "MessagePending": {
+              const data = payload.data as DiscordMessage;
+              addMessage(data.channel_id, data);
+              break;
+            }
+
+            case "MessageSendFailed": {
+              const data = payload.data as DiscordMessage;
+              console.error("[Gateway] Send failed:", data.send_error);
+              updateMessage(data.channel_id, data.id, {
+                send_state: data.send_state,
+                send_error: data.send_error,
               });
               break;
             }
@@ -0,0 +1,45 @@
+import { useEffect } from "react";
+import { useNavigate } from "react-router-dom";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+// Activity is reported at most this often; the vault locks after 15 idle minutes
+const ACTIVITY_THROTTLE_MS = 60_000;
+
+/**
+ * Keeps the credential vault unlocked while the user is active, and returns
+ * to the account screen when it locks or Discord signs the account out.
+ */
+export function useVaultLock() {
+  const navigate = useNavigate();
+
+  useEffect(() => {
+    const unlisten = listen("vault-locked", () => navigate("/"));
+    // The account screen shows the token as expired and offers to replace it
+    const unlistenGateway = listen<{ type: string; data?: unknown }>(
+      "discord-gateway",
+      (event) => {
+        if (event.payload.type === "SignedOut") {
+          console.warn("[Gateway] Signed out:", event.payload.data);
+          navigate("/");
+        }
+      }
+    );
+
+    let lastReport = 0;
+    const reportActivity = () => {
+      const now = Date.now();
+      if (now - lastReport < ACTIVITY_THROTTLE_MS) return;
+      lastReport = now;
+      invoke("vault_activity").catch(console.error);
+    };
+    const events = ["keydown", "pointerdown", "mousemove", "wheel"] as const;
+    events.forEach((e) => window.addEventListener(e, reportActivity));
+
+    return () => {
+      unlisten.then((fn) => fn());
+      unlistenGateway.then((fn) => fn());
+      events.forEach((e) => window.removeEventListener(e, reportActivity));
+    };
+  }, [navigate]);
+}
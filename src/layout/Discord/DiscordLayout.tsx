@@ -3,9 +3,12 @@ import ChannelBarController from "../../components/Discord/ChannelBar/ChannelBar
 import MembersBar from "../../components/Discord/Members";
 import ChatController from "../../components/Discord/MainChatController";
 import { DiscordEventListener } from "../../hooks/useDiscordEvents";
+import { useVaultLock } from "../../hooks/useVaultLock";
 import { Outlet } from "react-router-dom";
 
 function DiscordLayout() {
+  useVaultLock();
+
   return (
     <div className="flex h-screen w-screen bg-gray-800 text-gray-100">
       {/* Gateway event listener - listens to WebSocket events and updates store */}
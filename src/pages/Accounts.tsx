@@ -1,19 +1,36 @@
 import { invoke } from "@tauri-apps/api/core";
 import { useEffect, useState } from "react";
 import { Link, useNavigate } from "react-router-dom";
+import { useCdnUrl } from "../hooks/useCdnUrl";
+import VaultGate, { ChangePasswordForm } from "../components/Vault/VaultGate";
+import type { AccountSummary } from "../types/discord";
 
 export default function Accounts() {
+  return (
+    <VaultGate>
+      <AccountList />
+    </VaultGate>
+  );
+}
+
+function AccountList() {
   const navigate = useNavigate();
+  const cdnUrl = useCdnUrl();
 
-  const [token, setToken] = useState<string | null>(null);
+  const [account, setAccount] = useState<AccountSummary | null>(null);
+  const [saved, setSaved] = useState<AccountSummary[]>([]);
   const [loading, setLoading] = useState(true);
 
   useEffect(() => {
     let cancelled = false;
     (async () => {
       try {
-        const t = await invoke<string | null>("get_token");
-        if (!cancelled) setToken(t);
+        const summary = await invoke<AccountSummary | null>(
+          "get_account_summary"
+        );
+        if (!cancelled) setAccount(summary);
+        const accounts = await invoke<AccountSummary[]>("list_accounts");
+        if (!cancelled) setSaved(accounts.filter((a) => !a.active));
       } finally {
         if (!cancelled) setLoading(false);
       }
@@ -23,9 +40,19 @@ export default function Accounts() {
     };
   }, []);
 
-  const hasToken = Boolean(token && token.length > 0);
-  const maskedToken = token ? `${token.slice(0, 6)}…${token.slice(-4)}` : "";
-  const avatarText = "D";
+  const hasToken = account !== null;
+  const maskedToken = account?.masked_token ?? "";
+  const displayName =
+    account?.global_name ?? account?.username ?? "Discord";
+  const avatarText = displayName.slice(0, 1).toUpperCase();
+  const avatarUrl =
+    account?.user_id && account.avatar
+      ? `${cdnUrl}/avatars/${account.user_id}/${account.avatar}.png?size=64`
+      : null;
+
+  async function lock() {
+    await invoke("lock_vault");
+  }
 
   return (
     <main className="min-h-screen w-screen bg-slate-950 text-slate-100">
@@ -43,6 +70,13 @@ export default function Accounts() {
           </div>
 
           <div className="flex items-center gap-2">
+            <button
+              type="button"
+              onClick={lock}
+              className="rounded-md bg-slate-800 px-3 py-2 text-sm font-semibold text-slate-100 hover:bg-slate-700"
+            >
+              Lock
+            </button>
             <Link
               to="/login"
               className="rounded-md bg-slate-800 px-3 py-2 text-sm font-semibold text-slate-100 hover:bg-slate-700"
@@ -79,13 +113,25 @@ export default function Accounts() {
               >
                 <div className="flex items-center gap-3">
                   <div className="relative">
-                    <div className="flex h-11 w-11 items-center justify-center rounded-full bg-slate-700 text-sm font-bold text-slate-100">
-                      {avatarText}
-                    </div>
+                    {avatarUrl ? (
+                      <img
+                        src={avatarUrl}
+                        alt=""
+                        className="h-11 w-11 rounded-full object-cover"
+                      />
+                    ) : (
+                      <div className="flex h-11 w-11 items-center justify-center rounded-full bg-slate-700 text-sm font-bold text-slate-100">
+                        {avatarText}
+                      </div>
+                    )}
                     <span
                       className={[
                         "absolute bottom-0 right-0 h-3 w-3 rounded-full ring-2 ring-slate-900",
-                        hasToken ? "bg-emerald-500" : "bg-gray-500",
+                        account?.validity === "valid"
+                          ? "bg-emerald-500"
+                          : account?.validity === "invalid"
+                          ? "bg-red-500"
+                          : "bg-gray-500",
                       ].join(" ")}
                     />
                   </div>
@@ -93,12 +139,16 @@ export default function Accounts() {
                   <div className="min-w-0 flex-1">
                     <div className="flex items-center justify-between gap-2">
                       <div className="truncate font-semibold text-slate-100">
-                        Discord
+                        {displayName}
                       </div>
-                      {hasToken ? (
+                      {account?.validity === "valid" ? (
                         <span className="text-xs font-semibold text-indigo-300">
                           Ready
                         </span>
+                      ) : account?.validity === "invalid" ? (
+                        <span className="text-xs font-semibold text-red-300">
+                          Token expired
+                        </span>
                       ) : null}
                     </div>
                     <div className="mt-0.5 truncate text-xs text-slate-400">
@@ -113,6 +163,34 @@ export default function Accounts() {
               </div>
             </div>
 
+            {saved.length > 0 ? (
+              <div className="mt-3 space-y-2">
+                <div className="px-2 text-xs font-semibold uppercase tracking-wider text-slate-400">
+                  Saved
+                </div>
+                {saved.map((a) => (
+                  <div
+                    key={a.user_id ?? a.masked_token}
+                    className="rounded-lg border border-slate-800 bg-slate-950/30 px-3 py-2"
+                  >
+                    <div className="truncate text-sm font-semibold text-slate-100">
+                      {a.global_name ?? a.username ?? a.masked_token}
+                    </div>
+                    <div className="truncate text-xs text-slate-400">
+                      {a.validity === "invalid"
+                        ? "Token expired"
+                        : a.validity === "valid"
+                        ? "Valid"
+                        : "Not checked"}
+                      {a.checked_at
+                        ? ` · ${new Date(a.checked_at).toLocaleString()}`
+                        : ""}
+                    </div>
+                  </div>
+                ))}
+              </div>
+            ) : null}
+
             <div className="mt-3 border-t border-slate-800 pt-3">
               <Link
                 to="/login"
@@ -129,13 +207,21 @@ export default function Accounts() {
               <div className="flex h-full flex-col">
                 <div className="flex items-center gap-4">
                   <div className="relative">
-                    <div className="flex h-14 w-14 items-center justify-center rounded-2xl bg-indigo-600/80 text-lg font-black text-white">
-                      {avatarText}
-                    </div>
+                    {avatarUrl ? (
+                      <img
+                        src={avatarUrl}
+                        alt=""
+                        className="h-14 w-14 rounded-2xl object-cover"
+                      />
+                    ) : (
+                      <div className="flex h-14 w-14 items-center justify-center rounded-2xl bg-indigo-600/80 text-lg font-black text-white">
+                        {avatarText}
+                      </div>
+                    )}
                   </div>
                   <div className="min-w-0">
                     <div className="truncate text-lg font-bold text-slate-100">
-                      Discord
+                      {displayName}
                     </div>
                     <div className="truncate text-sm text-slate-400">
                       {maskedToken}
@@ -178,6 +264,10 @@ export default function Accounts() {
                   <p className="mt-3 text-xs text-slate-500">
                     Tip: this app is currently in “single Discord token” mode.
                   </p>
+                  <div className="mt-4">
+                    <ChangePasswordForm />
+                  </div>
+                  <SignOutSettings />
                 </div>
               </div>
             ) : (
@@ -191,3 +281,39 @@ export default function Accounts() {
     </main>
   );
 }
+
+interface AuthSettings {
+  clear_private_data: boolean;
+}
+
+/** Whether cached data is forgotten when Discord signs the account out. */
+function SignOutSettings() {
+  const [settings, setSettings] = useState<AuthSettings | null>(null);
+
+  useEffect(() => {
+    invoke<AuthSettings>("get_auth_settings")
+      .then(setSettings)
+      .catch(console.error);
+  }, []);
+
+  if (!settings) {
+    return null;
+  }
+
+  async function toggle(clear_private_data: boolean) {
+    const next: AuthSettings = { clear_private_data };
+    await invoke("set_auth_settings", { settings: next });
+    setSettings(next);
+  }
+
+  return (
+    <label className="mt-3 flex items-center gap-2 text-xs text-slate-400">
+      <input
+        type="checkbox"
+        checked={settings.clear_private_data}
+        onChange={(e) => toggle(e.target.checked).catch(console.error)}
+      />
+      Clear cached messages and unsent drafts when signed out
+    </label>
+  );
+}
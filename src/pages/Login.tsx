@@ -1,10 +1,13 @@
-import { useRef } from "react";
+import { useCallback, useRef } from "react";
 import { useNavigate } from "react-router-dom";
 import { invoke } from "@tauri-apps/api/core";
+import CredentialsForm from "../components/Login/CredentialsForm";
+import QrLogin from "../components/Login/QrLogin";
 
 function Login() {
   const nameInputRef = useRef<HTMLInputElement>(null);
   const navigate = useNavigate();
+  const onLoggedIn = useCallback(() => navigate("/discord/user"), [navigate]);
   async function submit(e: React.FormEvent<HTMLFormElement>) {
     e.preventDefault();
     const token = nameInputRef.current?.value || "";
@@ -20,6 +23,10 @@ function Login() {
       }
     } catch (e) {
       console.error(e);
+      // The vault locked while the form was open
+      if (e === "vault_locked") {
+        navigate("/");
+      }
       return;
     }
   }
@@ -51,6 +58,11 @@ function Login() {
             Confirm
           </button>
         </form>
+        <p className="py-4 text-center text-sm text-gray-400">or log in</p>
+        <CredentialsForm onLoggedIn={onLoggedIn} />
+        <div className="pt-4">
+          <QrLogin onLoggedIn={onLoggedIn} />
+        </div>
       </div>
     </main>
   );
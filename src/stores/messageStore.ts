@@ -7,9 +7,25 @@ interface MessageState {
   // Map of channelId -> messages array
   messages: Map<channel_id, DiscordMessage[]>;
 
+  // Message to scroll to while showing an older part of a channel
+  jumpTarget: { channelId: string; messageId: string } | null;
+
   // Set all messages for a channel (used for initial fetch)
   setMessages: (channelId: string, messages: DiscordMessage[]) => void;
 
+  // Replace a channel's messages (used when jumping to another point)
+  replaceMessages: (channelId: string, messages: DiscordMessage[]) => void;
+
+  // Show the messages around a jump target instead of the latest ones
+  showContext: (
+    channelId: string,
+    messages: DiscordMessage[],
+    targetId: string | null
+  ) => void;
+
+  // Go back to following the latest messages
+  clearJumpTarget: () => void;
+
   // Add a new message to a channel
   addMessage: (channelId: string, message: DiscordMessage) => void;
 
@@ -32,6 +48,7 @@ interface MessageState {
 
 export const useMessageStore = create<MessageState>((set, get) => ({
   messages: new Map(),
+  jumpTarget: null,
 
   setMessages: (channelId, messages) =>
     set((state) => {
@@ -50,14 +67,43 @@ export const useMessageStore = create<MessageState>((set, get) => ({
       return { messages: newMessages };
     }),
 
+  replaceMessages: (channelId, messages) =>
+    set((state) => {
+      const newMessages = new Map(state.messages);
+      newMessages.set(channelId, messages);
+      return { messages: newMessages };
+    }),
+
+  showContext: (channelId, messages, targetId) =>
+    set((state) => {
+      const newMessages = new Map(state.messages);
+      newMessages.set(channelId, messages);
+      return {
+        messages: newMessages,
+        jumpTarget: targetId ? { channelId, messageId: targetId } : null,
+      };
+    }),
+
+  clearJumpTarget: () => set({ jumpTarget: null }),
+
   addMessage: (channelId, message) =>
     set((state) => {
       const newMessages = new Map(state.messages);
       // Get existing messages or create new array if channel doesn't exist
       const channelMessages = newMessages.get(channelId);
       if (channelMessages) {
-        // Channel exists - insert new message if not duplicate
-        if (!channelMessages.some((m) => m.id === message.id)) {
+        // A confirmed message replaces its local echo (whose id is the nonce)
+        const echoIndex = message.nonce
+          ? channelMessages.findIndex(
+              (m) => m.send_state && m.id === message.nonce
+            )
+          : -1;
+        if (echoIndex !== -1) {
+          const updated = [...channelMessages];
+          updated[echoIndex] = message;
+          newMessages.set(channelId, updated);
+        } else if (!channelMessages.some((m) => m.id === message.id)) {
+          // Channel exists - insert new message if not duplicate
           newMessages.set(channelId, [message, ...channelMessages]);
         }
       } else {
@@ -100,4 +100,88 @@ export interface DiscordMessage {
   timestamp: string;
   edited_timestamp?: string | null;
   author: DiscordUserLite;
+  // Set on messages we sent; local echoes use it as their id
+  nonce?: string | null;
+  // Only on local echoes of messages still in the outbox
+  send_state?: "pending" | "sending" | "failed";
+  send_error?: string | null;
+  // Unix ms of the next attempt when waiting on a rate limit or backoff
+  retry_at?: number | null;
+  // Names for mentions and previews of Discord links, filled in by the backend
+  resolved?: ResolvedEntities;
+}
+
+export interface ResolvedUser {
+  id: string;
+  display_name: string;
+  username: string;
+  avatar: string | null;
+  color: number | null;
+}
+
+export interface QuotedMessage {
+  author: ResolvedUser;
+  snippet: string;
+  has_attachments: boolean;
+  timestamp: string;
+}
+
+// A discord.com/channels/... link found in a message
+export interface LinkPreview {
+  url: string;
+  guild_id: string | null;
+  channel_id: string;
+  channel_name: string | null;
+  message_id: string | null;
+  // Missing for channel links and messages we cannot read
+  message: QuotedMessage | null;
+}
+
+export interface ResolvedEntities {
+  users: Record<string, ResolvedUser>;
+  channels: Record<string, { id: string; name: string | null; guild_id: string | null }>;
+  roles: Record<string, { id: string; name: string; color: number | null }>;
+  links: LinkPreview[];
+}
+
+// Matches Rust ChannelCapabilities. fetch_channel_capabilities leaves out
+// channels it cannot decide for, which should not be restricted
+export interface ChannelCapabilities {
+  view: boolean;
+  send: boolean;
+  attach: boolean;
+  react: boolean;
+  manage_messages: boolean;
+  bypass_slowmode: boolean;
+}
+
+// Returned by open_message_link
+export interface LinkTarget {
+  guild_id: string | null;
+  channel_id: string;
+  // Missing for channel links
+  context: MessageContext | null;
+}
+
+// Returned by jump_to_message and jump_to_date
+export interface MessageContext {
+  // Newest first
+  messages: DiscordMessage[];
+  // The message to scroll to, null when the channel is empty
+  target_id: string | null;
+}
+
+// Matches Rust AccountSummary; the token itself never reaches the webview
+export interface AccountSummary {
+  user_id: string | null;
+  username: string | null;
+  global_name: string | null;
+  avatar: string | null;
+  flags: number;
+  premium_type: number;
+  masked_token: string;
+  validity: "valid" | "invalid" | "unknown";
+  // RFC 3339 time Discord was last asked about the token
+  checked_at: string | null;
+  active: boolean;
 }